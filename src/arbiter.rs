@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// Source name used by the desktop visibility monitor
+pub const SOURCE_VISIBILITY: &str = "visibility";
+
+/// Default priority for visibility-based requests; other sources (manual overrides, schedules...) can
+/// outrank it by using a higher value
+pub const PRIORITY_VISIBILITY: u8 = 50;
+
+/// `None` addresses all wallpapers at once, `Some(index)` a single monitor (same as `WallpaperController`)
+pub type Target = Option<i64>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DesiredState {
+    Playing,
+    Paused,
+}
+
+/// A request from a named source to hold a target in a given state
+#[derive(Clone, Debug)]
+pub struct PauseRequest {
+    pub source: String,
+    pub target: Target,
+    pub state: DesiredState,
+    pub priority: u8,
+    pub expires_at: Option<Instant>,
+}
+
+impl PauseRequest {
+    pub fn pause(source: &str, target: Target, priority: u8) -> Self {
+        Self {
+            source: source.to_string(),
+            target,
+            state: DesiredState::Paused,
+            priority,
+            expires_at: None,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+}
+
+/// Collects pause/play requests from all sources and resolves them into one desired state per target.
+///
+/// For each target the highest-priority live request wins; on a tie pausing wins. A target without any
+/// live request is expected to be playing.
+#[derive(Clone, Default)]
+pub struct PauseArbiter {
+    // Keyed by (source, target) so a source holds at most one request per target
+    requests: HashMap<(String, Target), PauseRequest>,
+}
+
+impl PauseArbiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a request, replacing any previous request from the same source for the same target
+    pub fn submit(&mut self, request: PauseRequest) {
+        self.requests.insert((request.source.clone(), request.target), request);
+    }
+
+    /// Removes the request of `source` for `target`; returns whether there was one
+    pub fn withdraw(&mut self, source: &str, target: Target) -> bool {
+        self.requests.remove(&(source.to_string(), target)).is_some()
+    }
+
+    /// Drops expired requests and returns them
+    pub fn prune_expired(&mut self, now: Instant) -> Vec<PauseRequest> {
+        let expired: Vec<_> = self.requests.iter()
+            .filter(|(_, r)| r.is_expired(now))
+            .map(|(k, _)| k.clone())
+            .collect();
+        expired.into_iter().filter_map(|k| self.requests.remove(&k)).collect()
+    }
+
+    /// All targets that currently have at least one request
+    pub fn targets(&self) -> HashSet<Target> {
+        self.requests.values().map(|r| r.target).collect()
+    }
+
+    pub fn desired_state(&self, target: Target, now: Instant) -> DesiredState {
+        self.active_reasons(target, now)
+            .first()
+            .map(|r| r.state)
+            .unwrap_or(DesiredState::Playing)
+    }
+
+    /// Live requests for `target`, strongest first
+    pub fn active_reasons(&self, target: Target, now: Instant) -> Vec<&PauseRequest> {
+        let mut reasons: Vec<&PauseRequest> = self.requests.values()
+            .filter(|r| r.target == target && !r.is_expired(now))
+            .collect();
+        reasons.sort_by(|a, b| {
+            b.priority.cmp(&a.priority)
+                .then_with(|| (b.state == DesiredState::Paused).cmp(&(a.state == DesiredState::Paused)))
+                .then_with(|| a.source.cmp(&b.source))
+        });
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn play(source: &str, target: Target, priority: u8) -> PauseRequest {
+        PauseRequest { state: DesiredState::Playing, ..PauseRequest::pause(source, target, priority) }
+    }
+
+    fn sources(arbiter: &PauseArbiter, target: Target, now: Instant) -> Vec<&str> {
+        arbiter.active_reasons(target, now).iter().map(|r| r.source.as_str()).collect()
+    }
+
+    #[test]
+    fn no_request_means_playing() {
+        assert_eq!(PauseArbiter::new().desired_state(None, Instant::now()), DesiredState::Playing);
+    }
+
+    #[test]
+    fn higher_priority_wins() {
+        let now = Instant::now();
+        let mut arbiter = PauseArbiter::new();
+        arbiter.submit(PauseRequest::pause(SOURCE_VISIBILITY, None, PRIORITY_VISIBILITY));
+        arbiter.submit(play("schedule", None, PRIORITY_VISIBILITY + 10));
+        assert_eq!(arbiter.desired_state(None, now), DesiredState::Playing);
+        assert_eq!(sources(&arbiter, None, now), ["schedule", SOURCE_VISIBILITY]);
+
+        arbiter.submit(PauseRequest::pause("user", None, 100));
+        assert_eq!(arbiter.desired_state(None, now), DesiredState::Paused);
+        assert_eq!(sources(&arbiter, None, now), ["user", "schedule", SOURCE_VISIBILITY]);
+    }
+
+    #[test]
+    fn pausing_wins_a_tie() {
+        let now = Instant::now();
+        let mut arbiter = PauseArbiter::new();
+        arbiter.submit(play("a", None, 10));
+        arbiter.submit(PauseRequest::pause("b", None, 10));
+        assert_eq!(arbiter.desired_state(None, now), DesiredState::Paused);
+        assert_eq!(sources(&arbiter, None, now), ["b", "a"]);
+    }
+
+    #[test]
+    fn targets_are_independent() {
+        let now = Instant::now();
+        let mut arbiter = PauseArbiter::new();
+        arbiter.submit(PauseRequest::pause(SOURCE_VISIBILITY, Some(1), PRIORITY_VISIBILITY));
+        assert_eq!(arbiter.desired_state(Some(1), now), DesiredState::Paused);
+        assert_eq!(arbiter.desired_state(Some(2), now), DesiredState::Playing);
+        assert_eq!(arbiter.desired_state(None, now), DesiredState::Playing);
+        assert_eq!(arbiter.targets(), HashSet::from([Some(1)]));
+    }
+
+    #[test]
+    fn resubmitting_replaces_and_withdrawing_removes() {
+        let now = Instant::now();
+        let mut arbiter = PauseArbiter::new();
+        arbiter.submit(PauseRequest::pause(SOURCE_VISIBILITY, None, PRIORITY_VISIBILITY));
+        arbiter.submit(play(SOURCE_VISIBILITY, None, PRIORITY_VISIBILITY));
+        assert_eq!(arbiter.active_reasons(None, now).len(), 1);
+        assert_eq!(arbiter.desired_state(None, now), DesiredState::Playing);
+
+        assert!(arbiter.withdraw(SOURCE_VISIBILITY, None));
+        assert!(!arbiter.withdraw(SOURCE_VISIBILITY, None));
+        assert!(arbiter.targets().is_empty());
+    }
+
+    #[test]
+    fn expired_overrides_fall_back_to_weaker_reasons() {
+        let now = Instant::now();
+        let mut arbiter = PauseArbiter::new();
+        arbiter.submit(PauseRequest::pause(SOURCE_VISIBILITY, None, PRIORITY_VISIBILITY));
+        arbiter.submit(PauseRequest { expires_at: Some(now + Duration::from_secs(60)), ..play("user", None, 100) });
+        assert_eq!(arbiter.desired_state(None, now + Duration::from_secs(59)), DesiredState::Playing);
+
+        // Expired requests no longer count, even before they are pruned
+        let later = now + Duration::from_secs(60);
+        assert_eq!(arbiter.desired_state(None, later), DesiredState::Paused);
+        assert_eq!(sources(&arbiter, None, later), [SOURCE_VISIBILITY]);
+
+        let expired = arbiter.prune_expired(later);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].source, "user");
+        assert!(arbiter.prune_expired(later).is_empty());
+        assert_eq!(arbiter.active_reasons(None, now).len(), 1);
+    }
+}
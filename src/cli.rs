@@ -1,11 +1,15 @@
-use clap::Parser;
-use tracing::warn;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 
-#[derive(Parser, Debug)]
+use crate::config::MonitorWeights;
+use crate::install::plan::PlanFormat;
+use crate::install::task_xml::{TaskTrigger, RESTART_INTERVAL_SECS};
+use crate::selector::MonitorSelector;
+
+#[derive(Parser, Clone, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Monitors to watch, use numbers shown in Display Settings or use -L to list monitors (comma-separated, or "all" for all monitors)
-    #[arg(short, long, default_value = "all")]
+    /// Monitors to watch: numbers shown in Display Settings (see -L), ranges like 1-3, "all", "primary", "largest" or "smallest", comma-separated; prefix with ! to exclude, e.g. "all,!2"
+    #[arg(short, long, default_value = "all", value_parser = parse_monitors)]
     pub monitors: String,
 
     /// Minimum visibility threshold percentage (0-100) to pause the wallpaper engine
@@ -20,18 +24,58 @@ pub struct Cli {
     #[arg(short, long, default_value_t = 500)]
     pub update_rate: u64,
 
-    /// Path to Wallpaper Engine executable
-    #[arg(short='w', long, default_value = "C:\\Program Files (x86)\\Steam\\steamapps\\common\\wallpaper_engine")]
-    pub wallpaper_engine_path: String,
+    /// Path to the Wallpaper Engine folder (detected from your Steam libraries if omitted)
+    #[arg(short='w', long)]
+    pub wallpaper_engine_path: Option<String>,
     
-    /// Use the 64-bit version of Wallpaper Engine (wallpaper64.exe), otherwise use 32-bit (wallpaper32.exe)
+    /// Relative weight of monitors in the global visibility, e.g. "1=2,2=0.5" (monitors not listed weigh 1)
+    #[arg(long, value_name = "MONITOR=WEIGHT,...")]
+    pub weights: Option<MonitorWeights>,
+
+    /// Use the 64-bit version of Wallpaper Engine (wallpaper64.exe), otherwise use 32-bit (wallpaper32.exe) unless only the 64-bit one is installed
     #[arg(long="64bit")]
     pub bit64: bool,
     
+    /// Run the full monitoring pipeline but only log the Wallpaper Engine commands that would be executed
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Profile from the config file to use when no rule picks one
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Config file to read settings from (defaults to config.toml in the roaming app data folder, or next to the executable in portable mode)
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Print the effective configuration and where each value came from, then exit
+    #[arg(long = "print-config")]
+    pub print_config: bool,
+
+    /// Show the active profile and settings of the running controller, then exit
+    #[arg(long)]
+    pub status: bool,
+
+    /// Switch the running controller to a profile from the config file, or back to the rules with "auto", then exit
+    #[arg(long = "switch-profile", value_name = "NAME")]
+    pub switch_profile: Option<String>,
+
     /// List all available monitors and their IDs, then exit
     #[arg(short='L', long="list-monitors")]
     pub list_monitors: bool,
 
+    /// Print the last N pause/resume decisions with an explanation of each, then exit
+    #[arg(long = "explain-last", value_name = "N")]
+    pub explain_last: Option<usize>,
+
+    /// File where decisions are recorded for --explain-last (defaults to decisions.jsonl in the local app data folder, or next to the executable in portable mode)
+    #[arg(long = "trace-file")]
+    pub trace_file: Option<String>,
+
+    /// Keep logs, traces and state next to the executable instead of in the user profile, and never register a service or task (also enabled by a file named `portable` next to the executable)
+    #[arg(long)]
+    pub portable: bool,
+
     /// Disable Sentry error reporting
     #[arg(long, default_value = "false")]
     pub disable_sentry: bool,
@@ -40,6 +84,10 @@ pub struct Cli {
     #[arg(long, default_value = "https://c6caa06487e9769daccfbedcd8de6324@o504783.ingest.us.sentry.io/4509839881076736")]
     pub sentry_dsn: Option<String>,
 
+    /// Check the environment (elevation, Wallpaper Engine, installation, running instances, monitors) and suggest fixes, then exit
+    #[arg(long)]
+    pub doctor: bool,
+
     /// Launch interactive installer (TUI)
     #[arg(long)]
     pub install_tui: bool,
@@ -48,6 +96,18 @@ pub struct Cli {
     #[arg(long = "install-dir")]
     pub install_dir: Option<String>,
 
+    /// Allow --install-dir to replace an installed newer version with this one
+    #[arg(long = "allow-downgrade")]
+    pub allow_downgrade: bool,
+
+    /// Install unattended with the settings from an answers file (see --save-answers) and exit
+    #[arg(long = "install-from", value_name = "FILE")]
+    pub install_from: Option<String>,
+
+    /// With --install-tui, save the choices to an answers file for --install-from
+    #[arg(long = "save-answers", value_name = "FILE")]
+    pub save_answers: Option<String>,
+
     /// Add a Windows service to run this program with the specified flags and exit (non-interactive path)
     #[arg(long = "add-startup-service")]
     pub add_startup_service: bool,
@@ -55,21 +115,432 @@ pub struct Cli {
     /// Add a Windows Scheduled Task to run this program at user logon and exit (non-interactive path)
     #[arg(long = "add-startup-task")]
     pub add_startup_task: bool,
+
+    /// Install for the current user only, without administrator rights: into the user profile, with a non-elevated logon task
+    #[arg(long = "per-user")]
+    pub per_user: bool,
+
+    /// Print the steps the install options would perform (as text or JSON) without changing anything
+    #[arg(long = "install-dry-run", value_name = "FORMAT", value_enum, num_args = 0..=1, default_missing_value = "text")]
+    pub install_dry_run: Option<PlanFormat>,
+
+    /// Change the settings of the installed service/task in the interactive installer, pre-filled with the current ones
+    #[arg(long)]
+    pub reconfigure: bool,
+
+    /// Check the installed service/task, executable, manifest and Wallpaper Engine files and print a pass/fail checklist
+    #[arg(long = "verify-install")]
+    pub verify_install: bool,
+
+    /// Like --verify-install, but also fix what can be fixed automatically
+    #[arg(long)]
+    pub repair: bool,
+
+    /// Stop and remove the startup service/task, the installed executable and recorded state, then exit
+    #[arg(long)]
+    pub uninstall: bool,
+
+    /// Download the latest release, verify it and replace the installed copy, then restart the service/task
+    #[arg(long)]
+    pub update: bool,
+
+    /// Only report whether a newer release than the installed copy is available
+    #[arg(long = "check-update")]
+    pub check_update: bool,
+
+    /// Release manifest to update from: an http(s) or file:// URL, or a local path
+    #[arg(long = "update-url", value_name = "URL")]
+    pub update_url: Option<String>,
+
+    /// Hex-encoded ed25519 key release manifests must be signed with, instead of the built-in release key
+    #[arg(long = "update-public-key", value_name = "HEX")]
+    pub update_public_key: Option<String>,
+
+    /// Seconds to wait after the trigger fires before the scheduled task starts the controller
+    #[arg(long = "task-delay", value_name = "SECONDS", default_value_t = 75, value_parser = clap::value_parser!(u64).range(0..=86400))]
+    pub task_delay: u64,
+
+    /// Events that start the scheduled task (comma-separated)
+    #[arg(long = "task-trigger", value_enum, value_delimiter = ',', default_value = "logon")]
+    pub task_triggers: Vec<TaskTrigger>,
+
+    /// How many times Task Scheduler restarts the controller after it fails (0 disables restarting)
+    #[arg(long = "task-restart-count", value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u16).range(0..=999))]
+    pub task_restart_count: u16,
+
+    /// Seconds between restart attempts of a failed scheduled task (at least 60)
+    #[arg(long = "task-restart-interval", value_name = "SECONDS", default_value_t = 60, value_parser = clap::value_parser!(u64).range(RESTART_INTERVAL_SECS))]
+    pub task_restart_interval: u64,
 }
 
-pub fn parse_monitor_indices(input: &str) -> Option<Vec<i64>> {
-    if input.to_lowercase() == "all" {
-        return None; // None represents all monitors
-    }
-    
-    let mut indices = Vec::new();
-    for id_str in input.split(',') {
-        if let Ok(index) = id_str.trim().parse::<i64>() {
-            indices.push(index);
+// The command line has subcommands (`run`, `install`, ...), but each one stands for a set of the flat options
+// `Cli` defines, which is what everything after parsing reads. Flat invocations without a subcommand stay valid:
+// services and tasks registered by earlier versions start the controller that way, and installs still register
+// it that way so a downgraded executable can read them.
+
+/// What an invocation asks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Watch the desktop and pause Wallpaper Engine while it is hidden
+    Run,
+    /// List the monitors
+    Monitors,
+    /// Install (interactively unless an install option is given)
+    Install,
+    Uninstall,
+    /// Show the running controller's status
+    Status,
+    /// Switch the running controller's profile
+    SwitchProfile,
+    /// Check the environment and suggest fixes
+    Doctor,
+    /// Explain recent pause/resume decisions
+    Explain,
+    /// Check (or repair) the installation
+    Verify,
+    /// Change the installed settings
+    Reconfigure,
+    /// Update the installed copy
+    Update,
+    /// Check for an update
+    CheckUpdate,
+    /// Run without touching Wallpaper Engine
+    Simulate,
+}
+
+struct SubcommandSpec {
+    mode: Mode,
+    name: &'static str,
+    about: &'static str,
+    /// Flat options the subcommand stands for
+    implies: &'static [&'static str],
+    /// `Cli` fields it accepts
+    args: &'static [&'static [&'static str]],
+    /// `Cli` field given as the subcommand's positional argument; it follows the implied flags in the flat form
+    value: Option<&'static str>,
+}
+
+const RUNTIME_ARGS: &[&str] = &[
+    "monitors", "threshold", "per_monitor", "update_rate", "wallpaper_engine_path", "weights", "bit64", "config",
+    "profile", "print_config", "trace_file", "portable", "disable_sentry", "sentry_dsn",
+];
+const INSTALL_ARGS: &[&str] = &[
+    "install_dir", "allow_downgrade", "install_from", "save_answers", "add_startup_service", "add_startup_task",
+    "per_user", "install_dry_run", "task_delay", "task_triggers", "task_restart_count",
+    "task_restart_interval",
+];
+const UNINSTALL_ARGS: &[&str] = &["install_dir", "wallpaper_engine_path", "bit64", "trace_file", "config"];
+const UPDATE_ARGS: &[&str] = &["update_url", "update_public_key", "install_dir", "portable"];
+
+const SUBCOMMANDS: &[SubcommandSpec] = &[
+    SubcommandSpec {
+        mode: Mode::Run,
+        name: "run",
+        about: "Watch the desktop and pause Wallpaper Engine while it is hidden",
+        implies: &[],
+        args: &[RUNTIME_ARGS, &["dry_run"]],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Monitors,
+        name: "monitors",
+        about: "List all available monitors and their IDs",
+        implies: &["--list-monitors"],
+        args: &[],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Install,
+        name: "install",
+        about: "Install the controller with a startup service or task (interactively unless an install option is given)",
+        implies: &[],
+        args: &[RUNTIME_ARGS, INSTALL_ARGS],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Uninstall,
+        name: "uninstall",
+        about: "Stop and remove the startup service/task, the installed executable and recorded state",
+        implies: &["--uninstall"],
+        args: &[UNINSTALL_ARGS],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Status,
+        name: "status",
+        about: "Show the active profile and settings of the running controller",
+        implies: &["--status"],
+        args: &[&["portable"]],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::SwitchProfile,
+        name: "profile",
+        about: "Switch the running controller to a profile from the config file, or back to the rules with \"auto\"",
+        implies: &["--switch-profile"],
+        args: &[&["config", "portable"]],
+        value: Some("switch_profile"),
+    },
+    SubcommandSpec {
+        mode: Mode::Doctor,
+        name: "doctor",
+        about: "Check the environment (elevation, Wallpaper Engine, installation, running instances, monitors) and suggest fixes",
+        implies: &["--doctor"],
+        args: &[RUNTIME_ARGS],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Explain,
+        name: "explain",
+        about: "Print the last N pause/resume decisions with an explanation of each",
+        implies: &["--explain-last"],
+        args: &[&["trace_file", "config", "portable"]],
+        value: Some("explain_last"),
+    },
+    SubcommandSpec {
+        mode: Mode::Verify,
+        name: "verify",
+        about: "Check the installed service/task, executable, manifest and Wallpaper Engine files and print a pass/fail checklist",
+        implies: &["--verify-install"],
+        args: &[&["repair", "portable"]],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Reconfigure,
+        name: "reconfigure",
+        about: "Change the settings of the installed service/task in the interactive installer",
+        implies: &["--reconfigure"],
+        args: &[&["portable"]],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Update,
+        name: "update",
+        about: "Download the latest release, verify it and replace the installed copy, then restart the service/task",
+        implies: &["--update"],
+        args: &[UPDATE_ARGS],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::CheckUpdate,
+        name: "check-update",
+        about: "Only report whether a newer release than the installed copy is available",
+        implies: &["--check-update"],
+        args: &[UPDATE_ARGS],
+        value: None,
+    },
+    SubcommandSpec {
+        mode: Mode::Simulate,
+        name: "simulate",
+        about: "Run the full monitoring pipeline but only log the Wallpaper Engine commands that would be executed",
+        implies: &["--dry-run"],
+        args: &[RUNTIME_ARGS],
+        value: None,
+    },
+];
+
+// Flat options a subcommand replaces; still accepted, but no longer shown in the help
+const SUPERSEDED_ARGS: &[&str] = &[
+    "list_monitors", "install_tui", "uninstall", "status", "switch_profile", "doctor", "explain_last", "verify_install",
+    "reconfigure", "update", "check_update",
+];
+
+/// A parsed command line
+pub struct Invocation {
+    /// `None` when started without arguments
+    pub mode: Option<Mode>,
+    pub cli: Cli,
+    /// Matches of the flat form, which tell the config layering which options were typed
+    pub matches: ArgMatches,
+    /// The command line in the flat form, program name included
+    pub flat_args: Vec<String>,
+}
+
+/// The flat options and the subcommands, each of which only takes the options that apply to it
+fn command() -> clap::Command {
+    let flat = Cli::command();
+    let subcommands: Vec<clap::Command> = SUBCOMMANDS.iter().map(|spec| {
+        let args = flat.get_arguments()
+            .filter(|arg| spec.args.iter().any(|group| group.contains(&arg.get_id().as_str())))
+            .cloned();
+        let value = spec.value.and_then(|id| flat.get_arguments().find(|arg| arg.get_id() == id))
+            .map(|arg| arg.clone().long(None).short(None).required(true));
+        clap::Command::new(spec.name).about(spec.about).args(args).args(value)
+    }).collect();
+    SUPERSEDED_ARGS.iter().fold(flat.clone(), |command, id| command.mut_arg(*id, |arg| arg.hide(true)))
+        .subcommands(subcommands)
+        .args_conflicts_with_subcommands(true)
+}
+
+/// Parses either form into the flat options; `args` includes the program name
+pub fn parse(args: &[String]) -> Result<Invocation, clap::Error> {
+    let matches = command().try_get_matches_from(args)?;
+    let (mode, flat_args) = match matches.subcommand_name().and_then(|name| SUBCOMMANDS.iter().find(|spec| spec.name == name)) {
+        // Subcommands can't follow flat options, so the name is always the first argument
+        Some(spec) => {
+            let mut rest: Vec<String> = args.iter().skip(2).cloned().collect();
+            // The positional value moves right behind the flag it belongs to
+            let value = spec.value.and_then(|id| {
+                let (_, sub_matches) = matches.subcommand()?;
+                let index = sub_matches.index_of(id)?;
+                Some(rest.remove(index - 1))
+            });
+            let flat_args = args.iter().take(1).cloned()
+                .chain(spec.implies.iter().map(|flag| flag.to_string()))
+                .chain(value)
+                .chain(rest)
+                .collect();
+            (Some(spec.mode), flat_args)
+        }
+        None => (None, args.to_vec()),
+    };
+    let matches = Cli::command().try_get_matches_from(&flat_args)?;
+    let cli = Cli::from_arg_matches(&matches)?;
+    let mode = mode.or_else(|| (flat_args.len() > 1).then(|| cli.flat_mode()));
+    Ok(Invocation { mode, cli, matches, flat_args })
+}
+
+impl Cli {
+    /// The mode a flat invocation stands for
+    fn flat_mode(&self) -> Mode {
+        if self.list_monitors {
+            Mode::Monitors
+        } else if self.status {
+            Mode::Status
+        } else if self.switch_profile.is_some() {
+            Mode::SwitchProfile
+        } else if self.doctor {
+            Mode::Doctor
+        } else if self.explain_last.is_some() {
+            Mode::Explain
+        } else if self.verify_install || self.repair {
+            Mode::Verify
+        } else if self.reconfigure {
+            Mode::Reconfigure
+        } else if self.update {
+            Mode::Update
+        } else if self.check_update {
+            Mode::CheckUpdate
+        } else if self.uninstall {
+            Mode::Uninstall
+        } else if self.install_tui || self.has_install_target() {
+            Mode::Install
+        } else if self.dry_run {
+            Mode::Simulate
         } else {
-            warn!("Invalid monitor index '{}', ignoring", id_str);
+            Mode::Run
         }
     }
-    
-    Some(indices)
-}
\ No newline at end of file
+
+    /// Whether an option says what to install, so the interactive installer isn't needed
+    pub fn has_install_target(&self) -> bool {
+        self.install_dir.is_some() || self.install_from.is_some() || self.add_startup_service
+            || self.add_startup_task || self.per_user || self.install_dry_run.is_some()
+    }
+}
+
+/// Checks a --monitors selector with the parser the config file and the installer use
+fn parse_monitors(value: &str) -> Result<String, String> {
+    value.parse::<MonitorSelector>().map(|_| value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(args: &[&str]) -> Invocation {
+        let args: Vec<String> = std::iter::once("wallpaper-controller").chain(args.iter().copied()).map(String::from).collect();
+        parse(&args).unwrap_or_else(|e| panic!("{:?}: {}", args, e))
+    }
+
+    // Each subcommand invocation and the flat invocation it stands for
+    const EQUIVALENT_FORMS: &[(&[&str], &[&str])] = &[
+        (&["run", "--monitors", "1-2"], &["--monitors", "1-2"]),
+        (&["run", "-m", "primary", "-t", "35", "--per-monitor", "--64bit"], &["-m", "primary", "-t", "35", "--per-monitor", "--64bit"]),
+        (&["run", "--profile", "work", "--config", "c.toml"], &["--profile", "work", "--config", "c.toml"]),
+        (&["monitors"], &["--list-monitors"]),
+        (&["monitors"], &["-L"]),
+        (&["install", "--add-startup-task"], &["--add-startup-task"]),
+        (&["install", "--add-startup-service", "--monitors", "1-2"], &["--add-startup-service", "--monitors", "1-2"]),
+        (&["install", "--per-user", "--task-trigger", "logon,unlock", "--task-delay", "0"], &["--per-user", "--task-trigger", "logon,unlock", "--task-delay", "0"]),
+        (&["install", "--install-dir", "C:\\wpc", "--install-dry-run", "json"], &["--install-dir", "C:\\wpc", "--install-dry-run", "json"]),
+        (&["install", "--install-from", "answers.toml"], &["--install-from", "answers.toml"]),
+        (&["uninstall"], &["--uninstall"]),
+        (&["uninstall", "--install-dir", "C:\\wpc"], &["--uninstall", "--install-dir", "C:\\wpc"]),
+        (&["status"], &["--status"]),
+        (&["status", "--portable"], &["--status", "--portable"]),
+        (&["profile", "gaming"], &["--switch-profile", "gaming"]),
+        (&["simulate", "--monitors", "2"], &["--dry-run", "--monitors", "2"]),
+        (&["doctor"], &["--doctor"]),
+        (&["doctor", "--monitors", "1-2", "--64bit"], &["--doctor", "--monitors", "1-2", "--64bit"]),
+        (&["explain", "5"], &["--explain-last", "5"]),
+        (&["explain", "--trace-file", "t.jsonl", "5"], &["--explain-last", "5", "--trace-file", "t.jsonl"]),
+        (&["verify"], &["--verify-install"]),
+        (&["verify", "--repair"], &["--verify-install", "--repair"]),
+        (&["reconfigure"], &["--reconfigure"]),
+        (&["update"], &["--update"]),
+        (&["update", "--update-url", "file:///r/manifest.json"], &["--update", "--update-url", "file:///r/manifest.json"]),
+        (&["check-update"], &["--check-update"]),
+    ];
+
+    #[test]
+    fn both_forms_parse_to_the_same_options() {
+        for (subcommand, flat) in EQUIVALENT_FORMS {
+            let (subcommand, flat) = (invocation(subcommand), invocation(flat));
+            assert_eq!(format!("{:?}", subcommand.cli), format!("{:?}", flat.cli), "{:?} vs {:?}", subcommand.flat_args, flat.flat_args);
+            assert_eq!(subcommand.mode, flat.mode, "{:?}", flat.flat_args);
+        }
+    }
+
+    #[test]
+    fn subcommands_only_take_their_own_options() {
+        for args in [
+            &["monitors", "--threshold", "5"][..],
+            &["status", "--monitors", "1"],
+            &["verify", "--update"],
+            &["check-update", "--update"],
+            &["explain"],
+            &["explain", "five"],
+            &["--threshold", "5", "run"],
+        ] {
+            let args: Vec<String> = std::iter::once("wallpaper-controller").chain(args.iter().copied()).map(String::from).collect();
+            assert!(parse(&args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn flat_modes() {
+        assert_eq!(invocation(&[]).mode, None);
+        for (args, mode) in [
+            (&["--threshold", "5"][..], Mode::Run),
+            (&["--repair"], Mode::Verify),
+            (&["--dry-run"], Mode::Simulate),
+            (&["--install-dir", "C:\\wpc"], Mode::Install),
+            // Like a bare `install`, which main treats the same way
+            (&["--install-tui"], Mode::Install),
+        ] {
+            assert_eq!(invocation(args).mode, Some(mode), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn the_profile_subcommand_switches_the_running_controller() {
+        for args in [
+            &["profile", "gaming"][..],
+            &["profile", "--portable", "gaming"],
+            &["profile", "gaming", "--portable"],
+            &["--switch-profile", "gaming"],
+        ] {
+            let invocation = invocation(args);
+            assert_eq!(invocation.mode, Some(Mode::SwitchProfile), "{:?}", args);
+            assert_eq!(invocation.cli.switch_profile.as_deref(), Some("gaming"), "{:?}", args);
+            assert_eq!(invocation.cli.portable, args.contains(&"--portable"), "{:?}", args);
+        }
+        assert_eq!(invocation(&["profile", "--portable", "gaming"]).flat_args[1..], ["--switch-profile", "gaming", "--portable"]);
+
+        let args = ["wallpaper-controller", "profile"].map(String::from);
+        assert!(parse(&args).is_err(), "a profile name is required");
+        let args = ["wallpaper-controller", "profile", "gaming", "--threshold", "5"].map(String::from);
+        assert!(parse(&args).is_err(), "runtime options don't apply");
+    }
+}
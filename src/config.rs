@@ -0,0 +1,628 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::cli::Cli;
+use crate::error::ConfigError;
+use crate::install::forward::FORWARDED_MARKER;
+use crate::monitor::MonitorSettings;
+use crate::paths::Paths;
+use crate::profiles::{AggregationMode, Profile, Rule};
+use crate::selector::MonitorSelector;
+use crate::wallpaper::PauseAction;
+
+// Runtime settings are layered: built-in defaults < config file < `WPC_*` environment variables < command
+// line. Only values typed on the command line count as command line; clap's defaults are the bottom layer,
+// so the defaults stay defined in one place (`Cli`). The command line the service/task was registered with
+// sits just above the defaults instead, so editing the config file also changes installed controllers.
+
+const ENV_PREFIX: &str = "WPC_";
+const DEFAULT_THRESHOLD: u8 = 20;
+/// Marker argument the scheduled task starts the controller with
+const TASK_MARKER: &str = "-silent";
+
+/// Who wrote the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgsOrigin {
+    /// Typed by the user
+    User,
+    /// Registered with the service/task by the installer
+    Installed,
+}
+
+impl ArgsOrigin {
+    /// Tells a service/task launch from the markers on the raw command line (before forwarded arguments are
+    /// decoded)
+    pub fn of(raw_args: &[String]) -> Self {
+        if raw_args.iter().any(|a| a == FORWARDED_MARKER || a == TASK_MARKER) {
+            ArgsOrigin::Installed
+        } else {
+            ArgsOrigin::User
+        }
+    }
+}
+
+/// Where an effective setting came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    /// The environment variable that set it
+    Env(String),
+    CommandLine,
+    /// The command line the service/task was registered with
+    Installed,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "config file"),
+            Source::Env(var) => write!(f, "{}", var),
+            Source::CommandLine => write!(f, "command line"),
+            Source::Installed => write!(f, "installed command line"),
+        }
+    }
+}
+
+/// Relative weight of each monitor in the global visibility, written as `1=2,2=0.5` on the command line and
+/// in `WPC_WEIGHTS`. Monitors that aren't listed weigh 1.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MonitorWeights(pub BTreeMap<i64, f64>);
+
+impl FromStr for MonitorWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = BTreeMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (monitor, weight) = entry.split_once('=')
+                .ok_or_else(|| format!("'{}' is not MONITOR=WEIGHT", entry))?;
+            let weight = weight.trim().parse::<f64>().map_err(|_| format!("'{}' is not a number", weight.trim()))?;
+            weights.insert(parse_weight_monitor(monitor)?, check_weight(weight)?);
+        }
+        Ok(Self(weights))
+    }
+}
+
+fn parse_weight_monitor(monitor: &str) -> Result<i64, String> {
+    monitor.trim().parse::<i64>().map_err(|_| format!("'{}' is not a monitor number", monitor.trim()))
+}
+
+fn check_weight(weight: f64) -> Result<f64, String> {
+    if weight.is_finite() && weight >= 0.0 {
+        Ok(weight)
+    } else {
+        Err(format!("weight {} must be a number of at least 0", weight))
+    }
+}
+
+/// The config file as written; every key is optional
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    monitors: Option<String>,
+    threshold: Option<u8>,
+    per_monitor: Option<bool>,
+    update_rate: Option<u64>,
+    wallpaper_engine_path: Option<String>,
+    bit64: Option<bool>,
+    dry_run: Option<bool>,
+    trace_file: Option<String>,
+    disable_sentry: Option<bool>,
+    sentry_dsn: Option<String>,
+    /// Monitor number (as a string, since TOML keys are strings) to weight
+    weights: Option<BTreeMap<String, f64>>,
+    profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// Effective runtime settings after layering, with where each one came from
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub monitors: String,
+    pub threshold: u8,
+    pub per_monitor: bool,
+    pub update_rate: u64,
+    pub wallpaper_engine_path: Option<String>,
+    pub bit64: bool,
+    pub dry_run: bool,
+    pub trace_file: Option<String>,
+    pub disable_sentry: bool,
+    pub sentry_dsn: Option<String>,
+    pub weights: MonitorWeights,
+    /// Profile used when no rule matches
+    pub profile: Option<String>,
+    /// Named profiles, only read from the config file
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profile switching rules in order of precedence, only read from the config file
+    pub rules: Vec<Rule>,
+    /// The config file that was looked for
+    pub config_file: Option<PathBuf>,
+    pub config_file_found: bool,
+    sources: BTreeMap<&'static str, Source>,
+}
+
+/// Values that can be given as `WPC_*` environment variables
+trait EnvValue: Sized {
+    fn parse_env(value: &str) -> Result<Self, String>;
+}
+
+impl EnvValue for String {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        Ok(value.to_string())
+    }
+}
+
+impl EnvValue for bool {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => Err(format!("'{}' is not true or false", value)),
+        }
+    }
+}
+
+impl EnvValue for u8 {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        value.parse().map_err(|_| format!("'{}' is not an integer between 0 and 255", value))
+    }
+}
+
+impl EnvValue for u64 {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        value.parse().map_err(|_| format!("'{}' is not a non-negative integer", value))
+    }
+}
+
+impl EnvValue for MonitorWeights {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl<T: EnvValue> EnvValue for Option<T> {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        T::parse_env(value).map(Some)
+    }
+}
+
+struct Layers<'a> {
+    matches: &'a ArgMatches,
+    origin: ArgsOrigin,
+    /// Reads an environment variable
+    env: &'a dyn Fn(&str) -> Option<String>,
+    sources: BTreeMap<&'static str, Source>,
+}
+
+impl Layers<'_> {
+    /// Picks the value of `key` with the highest precedence: the command line, `WPC_<KEY>`, the config file,
+    /// the installed command line, then `cli`'s default. `key` is the `Cli` field name.
+    fn pick<T: EnvValue>(&mut self, key: &'static str, cli: T, file: Option<T>) -> Result<T, ConfigError> {
+        let var = format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase());
+        let given = self.matches.value_source(key) == Some(ValueSource::CommandLine);
+        let (value, source) = if given && self.origin == ArgsOrigin::User {
+            (cli, Source::CommandLine)
+        } else if let Some(raw) = (self.env)(&var) {
+            let value = T::parse_env(raw.trim())
+                .map_err(|reason| ConfigError::Invalid { key, origin: var.clone(), reason })?;
+            (value, Source::Env(var))
+        } else if let Some(value) = file {
+            (value, Source::File)
+        } else if given {
+            (cli, Source::Installed)
+        } else {
+            (cli, Source::Default)
+        };
+        self.sources.insert(key, source);
+        Ok(value)
+    }
+}
+
+/// Layers the config file (`--config`, or the default location if it exists), `WPC_*` environment variables
+/// and the command line (written by `origin`) into the effective settings
+pub fn load(cli: &Cli, matches: &ArgMatches, origin: ArgsOrigin, paths: &Paths) -> Result<Settings, ConfigError> {
+    load_with_env(cli, matches, origin, paths, &|var| env::var(var).ok())
+}
+
+fn load_with_env(
+    cli: &Cli,
+    matches: &ArgMatches,
+    origin: ArgsOrigin,
+    paths: &Paths,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Settings, ConfigError> {
+    let (path, explicit) = match &cli.config {
+        Some(path) => (Some(PathBuf::from(path)), true),
+        None => (paths.config_file(), false),
+    };
+    let file = match &path {
+        // A missing default config file just means nobody wrote one
+        Some(path) if explicit || path.exists() => read_config_file(path)?,
+        _ => ConfigFile::default(),
+    };
+    let file_weights = file.weights.map(|weights| {
+        weights.into_iter()
+            .map(|(monitor, weight)| Ok((parse_weight_monitor(&monitor)?, check_weight(weight)?)))
+            .collect::<Result<BTreeMap<_, _>, String>>()
+            .map(MonitorWeights)
+            .map_err(|reason| ConfigError::Invalid { key: "weights", origin: Source::File.to_string(), reason })
+    }).transpose()?;
+
+    let mut layers = Layers { matches, origin, env, sources: BTreeMap::new() };
+    let settings = Settings {
+        monitors: layers.pick("monitors", cli.monitors.clone(), file.monitors)?,
+        threshold: layers.pick("threshold", cli.threshold, file.threshold.map(Some))?.unwrap_or(DEFAULT_THRESHOLD),
+        per_monitor: layers.pick("per_monitor", cli.per_monitor, file.per_monitor)?,
+        update_rate: layers.pick("update_rate", cli.update_rate, file.update_rate)?,
+        wallpaper_engine_path: layers.pick("wallpaper_engine_path", cli.wallpaper_engine_path.clone(), file.wallpaper_engine_path.map(Some))?,
+        bit64: layers.pick("bit64", cli.bit64, file.bit64)?,
+        dry_run: layers.pick("dry_run", cli.dry_run, file.dry_run)?,
+        trace_file: layers.pick("trace_file", cli.trace_file.clone(), file.trace_file.map(Some))?,
+        disable_sentry: layers.pick("disable_sentry", cli.disable_sentry, file.disable_sentry)?,
+        sentry_dsn: layers.pick("sentry_dsn", cli.sentry_dsn.clone(), file.sentry_dsn.map(Some))?,
+        weights: layers.pick("weights", cli.weights.clone().unwrap_or_default(), file_weights)?,
+        profile: layers.pick("profile", cli.profile.clone(), file.profile.map(Some))?,
+        profiles: file.profiles,
+        rules: file.rules,
+        config_file_found: path.as_ref().is_some_and(|p| p.exists()),
+        config_file: path,
+        sources: layers.sources,
+    };
+    settings.validate()?;
+    Ok(settings)
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, ConfigError> {
+    let content = fs::read_to_string(path).map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+    toml::from_str(&content).map_err(|e| ConfigError::Parse { path: path.to_path_buf(), reason: e.message().to_string() })
+}
+
+impl Settings {
+    /// Checks what the command line's value parsers would have rejected
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &'static str, reason: &str| ConfigError::Invalid {
+            key,
+            origin: self.source(key).to_string(),
+            reason: reason.to_string(),
+        };
+        if self.threshold > 100 {
+            return Err(invalid("threshold", "must be between 0 and 100"));
+        }
+        if let Err(reason) = self.monitors.parse::<MonitorSelector>() {
+            return Err(invalid("monitors", &reason));
+        }
+        let file_invalid = |key: &'static str, reason: String| ConfigError::Invalid {
+            key,
+            origin: Source::File.to_string(),
+            reason,
+        };
+        for (name, profile) in &self.profiles {
+            if profile.threshold.is_some_and(|threshold| threshold > 100) {
+                return Err(file_invalid("profiles", format!("threshold of profile '{}' must be between 0 and 100", name)));
+            }
+            if let Some(Err(reason)) = profile.monitors.as_ref().map(|monitors| monitors.parse::<MonitorSelector>()) {
+                return Err(file_invalid("profiles", format!("monitors of profile '{}': {}", name, reason)));
+            }
+        }
+        if let Some(name) = &self.profile {
+            if !self.profiles.contains_key(name) {
+                return Err(invalid("profile", &format!("there is no profile '{}' in the config file", name)));
+            }
+        }
+        for rule in &self.rules {
+            if !self.profiles.contains_key(&rule.profile) {
+                return Err(file_invalid("rules", format!("there is no profile '{}' for the {}", rule.profile, rule)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn source(&self, key: &str) -> Source {
+        self.sources.get(key).cloned().unwrap_or(Source::Default)
+    }
+
+    /// The monitor settings with the overrides of profile `active`, if any
+    pub fn monitor_settings(&self, active: Option<&str>) -> MonitorSettings {
+        let profile = self.active_profile(active);
+        MonitorSettings {
+            per_monitor: profile.aggregation.map_or(self.per_monitor, |mode| mode == AggregationMode::PerMonitor),
+            threshold: profile.threshold.unwrap_or(self.threshold),
+            // load() rejected invalid selectors
+            monitors: profile.monitors.as_deref().unwrap_or(&self.monitors).parse().unwrap_or_else(|_| MonitorSelector::all()),
+            weights: self.weights.0.clone(),
+            update_rate_ms: self.update_rate,
+        }
+    }
+
+    /// What to do to paused wallpapers under profile `active`
+    pub fn pause_action(&self, active: Option<&str>) -> PauseAction {
+        self.active_profile(active).action.unwrap_or_default()
+    }
+
+    fn active_profile(&self, active: Option<&str>) -> Profile {
+        active.and_then(|name| self.profiles.get(name)).cloned().unwrap_or_default()
+    }
+
+    /// Writes the effective values back into `cli`, which the rest of startup reads
+    pub fn apply(&self, cli: &mut Cli) {
+        cli.monitors = self.monitors.clone();
+        cli.threshold = Some(self.threshold);
+        cli.per_monitor = self.per_monitor;
+        cli.update_rate = self.update_rate;
+        cli.wallpaper_engine_path = self.wallpaper_engine_path.clone();
+        cli.bit64 = self.bit64;
+        cli.dry_run = self.dry_run;
+        cli.trace_file = self.trace_file.clone();
+        cli.disable_sentry = self.disable_sentry;
+        cli.sentry_dsn = self.sentry_dsn.clone();
+        cli.weights = Some(self.weights.clone());
+        cli.profile = self.profile.clone();
+    }
+
+    /// Prints the effective configuration as a config file, with where each value came from
+    pub fn print(&self) {
+        print!("{}", self.render());
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = match &self.config_file {
+            Some(path) if self.config_file_found => writeln!(out, "# Config file: {}", path.display()),
+            Some(path) => writeln!(out, "# Config file: {} (not found)", path.display()),
+            None => writeln!(out, "# Config file: none (the config folder could not be determined)"),
+        };
+        let mut line = |key: &str, value: Option<toml::Value>| {
+            let _ = match value {
+                Some(value) => writeln!(out, "{:<40} # {}", format!("{} = {}", key, value), self.source(key)),
+                None => writeln!(out, "{:<40} # {}", format!("# {} is not set", key), self.source(key)),
+            };
+        };
+        line("monitors", Some(self.monitors.clone().into()));
+        line("threshold", Some(i64::from(self.threshold).into()));
+        line("per_monitor", Some(self.per_monitor.into()));
+        line("update_rate", Some((self.update_rate as i64).into()));
+        line("wallpaper_engine_path", self.wallpaper_engine_path.clone().map(Into::into));
+        line("bit64", Some(self.bit64.into()));
+        line("dry_run", Some(self.dry_run.into()));
+        line("trace_file", self.trace_file.clone().map(Into::into));
+        line("disable_sentry", Some(self.disable_sentry.into()));
+        line("sentry_dsn", self.sentry_dsn.clone().map(Into::into));
+        line("profile", self.profile.clone().map(Into::into));
+        let _ = writeln!(out, "\n[weights] # {}", self.source("weights"));
+        for (monitor, weight) in &self.weights.0 {
+            let _ = writeln!(out, "\"{}\" = {}", monitor, weight);
+        }
+        // Profiles and rules only come from the file, so they are printed as written there
+        for (name, profile) in &self.profiles {
+            let _ = writeln!(out, "\n[profiles.{}] # config file", toml_key(name));
+            out.push_str(&toml::to_string(profile).unwrap_or_default());
+        }
+        for rule in &self.rules {
+            let _ = writeln!(out, "\n[[rules]] # config file");
+            out.push_str(&toml::to_string(rule).unwrap_or_default());
+        }
+        out
+    }
+}
+
+/// `name` as a TOML key, quoted unless it is a bare key
+fn toml_key(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        name.to_string()
+    } else {
+        toml::Value::from(name).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use clap::{CommandFactory, FromArgMatches, Parser};
+    use crate::paths;
+
+    /// Loads `config` from a temporary file, with `args` on the command line and `vars` as the environment
+    fn load(config: &str, args: &[&str], vars: &[(&str, &str)]) -> Result<Settings, ConfigError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, config).unwrap();
+        load_file(path.to_str().unwrap(), args, vars, ArgsOrigin::User)
+    }
+
+    fn load_file(path: &str, args: &[&str], vars: &[(&str, &str)], origin: ArgsOrigin) -> Result<Settings, ConfigError> {
+        let argv = ["wallpaper-controller", "--config", path].into_iter().chain(args.iter().copied());
+        let matches = Cli::command().get_matches_from(argv);
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        load_with_env(&cli, &matches, origin, paths::current(), &|var| vars.get(var).cloned())
+    }
+
+    /// The key and origin of an `Invalid` error
+    fn rejected(result: Result<Settings, ConfigError>) -> (&'static str, String) {
+        match result {
+            Err(ConfigError::Invalid { key, origin, .. }) => (key, origin),
+            other => panic!("expected an invalid value, got {:?}", other.map(|s| s.sources)),
+        }
+    }
+
+    fn env(var: &str) -> Source {
+        Source::Env(var.to_string())
+    }
+
+    #[test]
+    fn defaults_fill_in_what_nobody_set() {
+        let settings = load("", &[], &[]).unwrap();
+        assert!(settings.config_file_found);
+        assert_eq!(settings.threshold, DEFAULT_THRESHOLD);
+        assert_eq!(settings.monitors, "all");
+        assert_eq!(settings.wallpaper_engine_path, None);
+        assert!(settings.weights.0.is_empty());
+        for key in ["threshold", "monitors", "per_monitor", "update_rate", "weights", "sentry_dsn"] {
+            assert_eq!(settings.source(key), Source::Default, "{}", key);
+        }
+    }
+
+    #[test]
+    fn the_file_beats_the_defaults() {
+        let config = r#"
+threshold = 35
+monitors = "1,2"
+per_monitor = true
+update_rate = 250
+wallpaper_engine_path = 'D:\we'
+sentry_dsn = "https://key@sentry.example/1"
+
+[weights]
+"1" = 2.0
+"#;
+        let settings = load(config, &[], &[]).unwrap();
+        assert_eq!((settings.threshold, settings.monitors.as_str(), settings.per_monitor, settings.update_rate), (35, "1,2", true, 250));
+        assert_eq!(settings.wallpaper_engine_path.as_deref(), Some(r"D:\we"));
+        assert_eq!(settings.sentry_dsn.as_deref(), Some("https://key@sentry.example/1"));
+        assert_eq!(settings.weights, MonitorWeights(BTreeMap::from([(1, 2.0)])));
+        for key in ["threshold", "monitors", "per_monitor", "update_rate", "wallpaper_engine_path", "sentry_dsn", "weights"] {
+            assert_eq!(settings.source(key), Source::File, "{}", key);
+        }
+    }
+
+    #[test]
+    fn the_environment_beats_the_file() {
+        let config = "threshold = 35\nper_monitor = false\nsentry_dsn = \"https://file@sentry.example/1\"\n";
+        let vars = [
+            ("WPC_THRESHOLD", " 40 "),
+            ("WPC_PER_MONITOR", "yes"),
+            ("WPC_WEIGHTS", "2=0.5"),
+            ("WPC_SENTRY_DSN", "https://env@sentry.example/1"),
+        ];
+        let settings = load(config, &[], &vars).unwrap();
+        assert_eq!((settings.threshold, settings.per_monitor), (40, true));
+        assert_eq!(settings.weights, MonitorWeights(BTreeMap::from([(2, 0.5)])));
+        assert_eq!(settings.sentry_dsn.as_deref(), Some("https://env@sentry.example/1"));
+        assert_eq!(settings.source("threshold"), env("WPC_THRESHOLD"));
+        assert_eq!(settings.source("per_monitor"), env("WPC_PER_MONITOR"));
+        assert_eq!(settings.source("weights"), env("WPC_WEIGHTS"));
+        assert_eq!(settings.source("sentry_dsn"), env("WPC_SENTRY_DSN"));
+    }
+
+    #[test]
+    fn the_command_line_beats_everything() {
+        let config = "threshold = 35\nmonitors = \"1\"\n";
+        let vars = [("WPC_THRESHOLD", "40"), ("WPC_MONITORS", "2")];
+        let settings = load(config, &["-t", "50", "-m", "primary"], &vars).unwrap();
+        assert_eq!((settings.threshold, settings.monitors.as_str()), (50, "primary"));
+        assert_eq!(settings.source("threshold"), Source::CommandLine);
+        assert_eq!(settings.source("monitors"), Source::CommandLine);
+
+        let mut cli = Cli::try_parse_from(["wallpaper-controller"]).unwrap();
+        settings.apply(&mut cli);
+        assert_eq!((cli.threshold, cli.monitors.as_str()), (Some(50), "primary"));
+    }
+
+    #[test]
+    fn unparsable_environment_variables_are_rejected() {
+        for (var, value, key) in [
+            ("WPC_THRESHOLD", "lots", "threshold"),
+            ("WPC_THRESHOLD", "256", "threshold"),
+            ("WPC_BIT64", "maybe", "bit64"),
+            ("WPC_UPDATE_RATE", "-1", "update_rate"),
+            ("WPC_WEIGHTS", "1=heavy", "weights"),
+            ("WPC_WEIGHTS", "2", "weights"),
+        ] {
+            assert_eq!(rejected(load("", &[], &[(var, value)])), (key, var.to_string()), "{}={}", var, value);
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected_with_their_origin() {
+        assert_eq!(rejected(load("threshold = 150", &[], &[])), ("threshold", String::from("config file")));
+        assert_eq!(rejected(load("", &[], &[("WPC_THRESHOLD", "101")])), ("threshold", String::from("WPC_THRESHOLD")));
+        assert_eq!(rejected(load("monitors = \"1,,2\"", &[], &[])), ("monitors", String::from("config file")));
+        assert_eq!(rejected(load("", &[], &[("WPC_MONITORS", "!all")])), ("monitors", String::from("WPC_MONITORS")));
+        assert_eq!(rejected(load("[weights]\n\"one\" = 1.0", &[], &[])), ("weights", String::from("config file")));
+        assert_eq!(rejected(load("[weights]\n\"1\" = -1.0", &[], &[])), ("weights", String::from("config file")));
+    }
+
+    #[test]
+    fn profiles_and_rules_must_be_consistent() {
+        assert_eq!(rejected(load("profile = \"work\"", &[], &[])), ("profile", String::from("config file")));
+        assert_eq!(rejected(load("", &["--profile", "work"], &[])), ("profile", String::from("command line")));
+        assert_eq!(rejected(load("[profiles.work]\nthreshold = 200", &[], &[])).0, "profiles");
+        assert_eq!(rejected(load("[profiles.work]\nmonitors = \"\"", &[], &[])).0, "profiles");
+        let rule = "[profiles.work]\n\n[[rules]]\nprofile = \"gaming\"\nprocess = \"game.exe\"\n";
+        assert_eq!(rejected(load(rule, &[], &[])).0, "rules");
+        assert!(load(&rule.replace("gaming", "work"), &["--profile", "work"], &[]).is_ok());
+    }
+
+    #[test]
+    fn broken_or_missing_explicit_files_are_errors() {
+        assert!(matches!(load("threshold = ", &[], &[]), Err(ConfigError::Parse { .. })));
+        assert!(matches!(load("thresold = 30", &[], &[]), Err(ConfigError::Parse { .. })));
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        assert!(matches!(load_file(missing.to_str().unwrap(), &[], &[], ArgsOrigin::User), Err(ConfigError::Read { .. })));
+    }
+
+    #[test]
+    fn printed_config_labels_each_source() {
+        let settings = load("per_monitor = true\n", &["-t", "50"], &[("WPC_UPDATE_RATE", "750")]).unwrap();
+        let printed = settings.render();
+        let label = |key: &str| {
+            let line = printed.lines()
+                .find(|line| line.starts_with(&format!("{} = ", key)) || line.starts_with(&format!("# {} is not set", key)))
+                .unwrap_or_else(|| panic!("{} is not printed:\n{}", key, printed));
+            line.rsplit_once(" # ").map(|(_, label)| label.to_string()).unwrap()
+        };
+        assert!(printed.starts_with("# Config file: "), "{}", printed);
+        assert_eq!(label("threshold"), "command line");
+        assert_eq!(label("update_rate"), "WPC_UPDATE_RATE");
+        assert_eq!(label("per_monitor"), "config file");
+        assert_eq!(label("bit64"), "default");
+        assert_eq!(label("wallpaper_engine_path"), "default");
+        assert!(printed.contains("\n[weights] # default\n"), "{}", printed);
+    }
+
+    #[test]
+    fn installed_command_lines_rank_below_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "threshold = 35\n").unwrap();
+        let installed = |vars: &[(&str, &str)]| {
+            load_file(path.to_str().unwrap(), &["-m", "1", "-t", "70", "-u", "500"], vars, ArgsOrigin::Installed).unwrap()
+        };
+
+        let settings = installed(&[]);
+        assert_eq!((settings.threshold, settings.monitors.as_str(), settings.update_rate), (35, "1", 500));
+        assert_eq!(settings.source("threshold"), Source::File);
+        assert_eq!(settings.source("monitors"), Source::Installed);
+        assert_eq!(settings.source("per_monitor"), Source::Default);
+        assert!(settings.render().contains("# installed command line"));
+
+        let settings = installed(&[("WPC_MONITORS", "2")]);
+        assert_eq!(settings.monitors, "2");
+        assert_eq!(settings.source("monitors"), env("WPC_MONITORS"));
+
+        // Typed by the user, the same flags still win
+        let settings = load_file(path.to_str().unwrap(), &["-t", "70"], &[], ArgsOrigin::User).unwrap();
+        assert_eq!(settings.threshold, 70);
+    }
+
+    #[test]
+    fn service_and_task_launches_are_recognized() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(ArgsOrigin::of(&args(&["wpc", FORWARDED_MARKER, "-t", "70"])), ArgsOrigin::Installed);
+        assert_eq!(ArgsOrigin::of(&args(&["wpc", "-t", "70", "-silent"])), ArgsOrigin::Installed);
+        assert_eq!(ArgsOrigin::of(&args(&["wpc", "-t", "70"])), ArgsOrigin::User);
+    }
+}
@@ -0,0 +1,643 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{anyhow, Result};
+use windows_elevate::check_elevated;
+use windows_service::service::ServiceAccess;
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::cli::Cli;
+use crate::install::answers::StartupMode;
+use crate::install::reconfigure::{decode_installed_cli, read_installed_config};
+use crate::install::{INSTALLED_EXE_NAME, WALLPAPER_ENGINE_SERVICE_NAME, WALLPAPER_SERVICE_32_PATH};
+use crate::monitor::monitor_facts;
+use crate::paths;
+use crate::selector::{MonitorFacts, MonitorSelector};
+use crate::steam::{resolve_wallpaper_engine, PathSource, WallpaperEngineInstall, WALLPAPER_32_EXE, WALLPAPER_64_EXE};
+
+// `--doctor` runs environment checks that explain the usual support questions. Every check only looks at
+// what a `DoctorEnv` reports, so the checks themselves don't touch the system; `SystemEnv` gathers the
+// real data.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    /// Works, but probably not the way the user expects
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Ok => write!(f, "[ OK ]"),
+            Severity::Warn => write!(f, "[WARN]"),
+            Severity::Fail => write!(f, "[FAIL]"),
+        }
+    }
+}
+
+/// Outcome of one check, with a suggested fix unless it passed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub summary: String,
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    fn ok(summary: impl Into<String>) -> Self {
+        Self { severity: Severity::Ok, summary: summary.into(), fix: None }
+    }
+
+    fn warn(summary: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { severity: Severity::Warn, summary: summary.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(summary: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { severity: Severity::Fail, summary: summary.into(), fix: Some(fix.into()) }
+    }
+}
+
+/// The installed startup service/task, as far as the checks care
+#[derive(Clone, Debug)]
+pub struct InstallState {
+    pub startup: StartupMode,
+    pub exe: PathBuf,
+    pub exe_present: bool,
+    pub service_host_present: bool,
+    /// The registered runtime arguments, parsed; the parse error otherwise
+    pub settings: Result<Cli, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunningInstance {
+    pub pid: u32,
+    pub command_line: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MonitorInfo {
+    /// Number shown in Display Settings, as used by `--monitors`
+    pub index: i64,
+    pub max_visible: i64,
+    pub total_area: i64,
+    pub primary: bool,
+}
+
+/// Everything the checks look at
+pub trait DoctorEnv {
+    fn is_elevated(&self) -> bool;
+    fn wallpaper_engine_service_exists(&self) -> bool;
+    /// The Wallpaper Engine folder the controller would use, and what it contains
+    fn wallpaper_engine(&self, explicit_dir: Option<&str>, prefer_64bit: bool) -> (WallpaperEngineInstall, PathSource);
+    fn install_state(&self) -> Option<InstallState>;
+    /// Controller processes other than this one
+    fn other_instances(&self) -> Result<Vec<RunningInstance>>;
+    fn monitors(&self) -> Vec<MonitorInfo>;
+    /// Where the decision trace and other state files are written
+    fn state_dir(&self) -> Option<PathBuf>;
+    /// Whether this copy runs in portable mode (see `paths`)
+    fn is_portable(&self) -> bool;
+    fn check_writable(&self, dir: &Path) -> Result<()>;
+}
+
+pub trait Check {
+    fn name(&self) -> &'static str;
+    /// `settings` are the installed ones when an installation exists, otherwise this command line's
+    fn run(&self, env: &dyn DoctorEnv, settings: &Cli) -> Finding;
+}
+
+pub fn all_checks() -> Vec<Box<dyn Check>> {
+    vec![
+        Box::new(ElevationCheck),
+        Box::new(WallpaperEngineCheck),
+        Box::new(WallpaperEngineServiceCheck),
+        Box::new(InstallStateCheck),
+        Box::new(DuplicateInstanceCheck),
+        Box::new(MonitorCheck),
+        Box::new(StateDirCheck),
+    ]
+}
+
+pub struct ElevationCheck;
+
+impl Check for ElevationCheck {
+    fn name(&self) -> &'static str { "Administrator rights" }
+
+    fn run(&self, env: &dyn DoctorEnv, _settings: &Cli) -> Finding {
+        if env.is_elevated() {
+            return Finding::ok("running elevated");
+        }
+        // Wallpaper Engine in High Priority mode runs elevated and ignores control commands from a normal process
+        if env.wallpaper_engine_service_exists() {
+            Finding::warn(
+                "not elevated, while Wallpaper Engine runs with High Priority",
+                "run the controller as administrator, or install it as a service/task (both run elevated)",
+            )
+        } else {
+            Finding::ok("not elevated (only installing a service or an elevated task needs it)")
+        }
+    }
+}
+
+pub struct WallpaperEngineCheck;
+
+impl Check for WallpaperEngineCheck {
+    fn name(&self) -> &'static str { "Wallpaper Engine path and bitness" }
+
+    fn run(&self, env: &dyn DoctorEnv, settings: &Cli) -> Finding {
+        let (install, source) = env.wallpaper_engine(settings.wallpaper_engine_path.as_deref(), settings.bit64);
+        let dir = install.dir.display();
+        let from = match source {
+            PathSource::CommandLine => "from --wallpaper-engine-path",
+            PathSource::Detected => "detected",
+            PathSource::Default => "default location",
+        };
+        if !install.has_32bit && !install.has_64bit {
+            let fix = match source {
+                PathSource::CommandLine => "point --wallpaper-engine-path at the folder containing wallpaper32.exe",
+                _ => "install Wallpaper Engine from Steam, or pass --wallpaper-engine-path",
+            };
+            return Finding::fail(format!("no Wallpaper Engine executables in {} ({})", dir, from), fix);
+        }
+        let use_64bit = install.use_64bit(settings.bit64);
+        if use_64bit && !install.has_64bit {
+            return Finding::fail(
+                format!("--64bit is set, but {} has no {}", dir, WALLPAPER_64_EXE),
+                format!("drop --64bit to use {}", WALLPAPER_32_EXE),
+            );
+        }
+        Finding::ok(format!("{} ({}, {}-bit)", dir, from, if use_64bit { 64 } else { 32 }))
+    }
+}
+
+pub struct WallpaperEngineServiceCheck;
+
+impl Check for WallpaperEngineServiceCheck {
+    fn name(&self) -> &'static str { "Wallpaper Engine service" }
+
+    fn run(&self, env: &dyn DoctorEnv, _settings: &Cli) -> Finding {
+        if env.wallpaper_engine_service_exists() {
+            Finding::ok(format!("'{}' is installed", WALLPAPER_ENGINE_SERVICE_NAME))
+        } else {
+            Finding::warn(
+                "not installed, so the controller can't be installed as a service",
+                "enable High Priority mode in Wallpaper Engine (Settings → General → Start with Windows), or install as a scheduled task",
+            )
+        }
+    }
+}
+
+pub struct InstallStateCheck;
+
+impl Check for InstallStateCheck {
+    fn name(&self) -> &'static str { "Installation" }
+
+    fn run(&self, env: &dyn DoctorEnv, _settings: &Cli) -> Finding {
+        let Some(state) = env.install_state() else {
+            if env.is_portable() {
+                return Finding::ok("portable mode; nothing is registered, start the controller yourself");
+            }
+            return Finding::warn("no startup service or task is installed", "run the installer (start without arguments or use --install-tui)");
+        };
+        let mode = match state.startup {
+            StartupMode::Service => "service",
+            StartupMode::Task => "scheduled task",
+        };
+        if !state.exe_present {
+            return Finding::fail(format!("the {} runs {}, which is missing", mode, state.exe.display()), "run --repair, or reinstall");
+        }
+        if state.startup == StartupMode::Service && !state.service_host_present {
+            return Finding::fail(
+                format!("installed as a service, but {} is missing", WALLPAPER_SERVICE_32_PATH),
+                "enable High Priority mode in Wallpaper Engine, or switch to a scheduled task with --reconfigure",
+            );
+        }
+        if let Err(e) = &state.settings {
+            return Finding::fail(format!("the {} has invalid arguments: {}", mode, e), "run --reconfigure or reinstall");
+        }
+        Finding::ok(format!("{} running {}", mode, state.exe.display()))
+    }
+}
+
+pub struct DuplicateInstanceCheck;
+
+impl Check for DuplicateInstanceCheck {
+    fn name(&self) -> &'static str { "Running instances" }
+
+    fn run(&self, env: &dyn DoctorEnv, _settings: &Cli) -> Finding {
+        let instances = match env.other_instances() {
+            Ok(instances) => instances,
+            Err(e) => return Finding::warn(format!("could not list processes: {:#}", e), "check Task Manager for wallpaper-controller.exe"),
+        };
+        match instances.as_slice() {
+            [] => Finding::ok("no other instance is running"),
+            [one] => Finding::ok(format!("one instance (PID {}) is running", one.pid)),
+            many => {
+                let list: Vec<String> = many.iter().map(|i| format!("PID {}: {}", i.pid, i.command_line)).collect();
+                Finding::fail(
+                    format!("{} instances are running and will fight over Wallpaper Engine ({})", many.len(), list.join("; ")),
+                    "end the extra ones in Task Manager, and make sure only one of the service or task is installed (--verify-install)",
+                )
+            }
+        }
+    }
+}
+
+pub struct MonitorCheck;
+
+impl Check for MonitorCheck {
+    fn name(&self) -> &'static str { "Monitors" }
+
+    fn run(&self, env: &dyn DoctorEnv, settings: &Cli) -> Finding {
+        let monitors = env.monitors();
+        if monitors.is_empty() {
+            return Finding::fail("no monitors were reported", "check that the desktop is available (not a locked or remote session)");
+        }
+        if let Some(m) = monitors.iter().find(|m| m.max_visible <= 0) {
+            return Finding::warn(format!("monitor {} reports no visible area", m.index), "check the display arrangement in Display Settings");
+        }
+        let available: Vec<String> = monitors.iter().map(|m| m.index.to_string()).collect();
+        let selector = match settings.monitors.parse::<MonitorSelector>() {
+            Ok(selector) => selector,
+            Err(e) => return Finding::fail(format!("--monitors is invalid: {}", e), "see --help for the selector syntax"),
+        };
+        let facts: Vec<MonitorFacts> = monitors.iter()
+            .map(|m| MonitorFacts { index: m.index, area: m.total_area, primary: m.primary })
+            .collect();
+        let selected = selector.resolve(&facts);
+        if selected.is_empty() {
+            return Finding::fail(
+                format!("--monitors '{}' selects none of the connected monitors (available: {})", settings.monitors, available.join(", ")),
+                "pick numbers from --list-monitors, or use --monitors all",
+            );
+        }
+        let selected: Vec<String> = selected.iter().map(|i| i.to_string()).collect();
+        Finding::ok(format!("{} monitor(s): {}; watching {}", monitors.len(), available.join(", "), selected.join(", ")))
+    }
+}
+
+pub struct StateDirCheck;
+
+impl Check for StateDirCheck {
+    fn name(&self) -> &'static str { "State folder is writable" }
+
+    fn run(&self, env: &dyn DoctorEnv, _settings: &Cli) -> Finding {
+        let Some(dir) = env.state_dir() else {
+            return Finding::fail("could not determine the local app data folder", "pass --trace-file to choose where state is written");
+        };
+        match env.check_writable(&dir) {
+            Ok(()) => Finding::ok(dir.display().to_string()),
+            Err(e) => Finding::fail(
+                format!("{}: {:#}", dir.display(), e),
+                "fix the folder's permissions, or pass --trace-file to write elsewhere",
+            ),
+        }
+    }
+}
+
+/// Reads the real system
+pub struct SystemEnv;
+
+impl DoctorEnv for SystemEnv {
+    fn is_elevated(&self) -> bool {
+        check_elevated().unwrap_or(false)
+    }
+
+    fn wallpaper_engine_service_exists(&self) -> bool {
+        ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)
+            .and_then(|manager| manager.open_service(WALLPAPER_ENGINE_SERVICE_NAME, ServiceAccess::QUERY_STATUS))
+            .is_ok()
+    }
+
+    fn wallpaper_engine(&self, explicit_dir: Option<&str>, prefer_64bit: bool) -> (WallpaperEngineInstall, PathSource) {
+        let resolved = resolve_wallpaper_engine(explicit_dir, prefer_64bit);
+        (WallpaperEngineInstall::probe(PathBuf::from(resolved.dir)), resolved.source)
+    }
+
+    fn install_state(&self) -> Option<InstallState> {
+        let installed = read_installed_config()?;
+        Some(InstallState {
+            startup: installed.startup,
+            exe_present: installed.exe.is_file(),
+            service_host_present: Path::new(WALLPAPER_SERVICE_32_PATH).is_file(),
+            settings: decode_installed_cli(&installed.args)
+                .map_err(|e| e.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()),
+            exe: installed.exe,
+        })
+    }
+
+    fn other_instances(&self) -> Result<Vec<RunningInstance>> {
+        let mut names = vec![INSTALLED_EXE_NAME.to_string()];
+        if let Some(name) = std::env::current_exe()?.file_name().map(|n| n.to_string_lossy().to_string()) {
+            if !name.eq_ignore_ascii_case(INSTALLED_EXE_NAME) {
+                names.push(name);
+            }
+        }
+        let filter = names.iter().map(|n| format!("Name='{}'", n.replace('\'', "''"))).collect::<Vec<_>>().join(" OR ");
+        // tasklist doesn't show command lines, which is what tells instances apart
+        let script = format!(
+            "Get-CimInstance Win32_Process -Filter \"{}\" | Select-Object ProcessId,CommandLine | ConvertTo-Json -Compress",
+            filter
+        );
+        let output = Command::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", &script]).output()?;
+        if !output.status.success() {
+            return Err(anyhow!("powershell failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        // A single process is serialized as an object rather than an array
+        let processes = match serde_json::from_str::<serde_json::Value>(&stdout)? {
+            serde_json::Value::Array(items) => items,
+            item => vec![item],
+        };
+        let this_pid = std::process::id();
+        Ok(processes
+            .iter()
+            .filter_map(|p| {
+                let pid = p.get("ProcessId")?.as_u64()? as u32;
+                let command_line = p.get("CommandLine").and_then(|c| c.as_str()).unwrap_or_default().to_string();
+                Some(RunningInstance { pid, command_line })
+            })
+            .filter(|i| i.pid != this_pid)
+            .collect())
+    }
+
+    fn monitors(&self) -> Vec<MonitorInfo> {
+        let (monitors, _, _) = libvisdesk::LibVisInstance::new().get_visible_area();
+        monitors.iter()
+            .zip(monitor_facts(&monitors))
+            .map(|(m, facts)| MonitorInfo {
+                index: m.monitor_index,
+                max_visible: m.max_visible,
+                total_area: facts.area,
+                primary: facts.primary,
+            })
+            .collect()
+    }
+
+    fn state_dir(&self) -> Option<PathBuf> {
+        paths::current().data_dir().map(Path::to_path_buf)
+    }
+
+    fn is_portable(&self) -> bool {
+        paths::current().is_portable()
+    }
+
+    fn check_writable(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let probe = dir.join(".doctor-write-test");
+        fs::write(&probe, b"ok")?;
+        fs::remove_file(&probe)?;
+        Ok(())
+    }
+}
+
+/// Runs every check, prints the findings with their suggested fixes and returns how many failed
+pub fn run_doctor(env: &dyn DoctorEnv, args: &Cli) -> usize {
+    // Diagnose what actually runs at startup when there is an installation
+    let installed = env.install_state().and_then(|state| state.settings.ok());
+    let settings = installed.as_ref().unwrap_or(args);
+
+    println!("\nWallpaper Controller {} doctor:", crate::version::CURRENT_VERSION);
+    let mut failed = 0;
+    for check in all_checks() {
+        let finding = check.run(env, settings);
+        println!("  {} {}: {}", finding.severity, check.name(), finding.summary);
+        if let Some(fix) = &finding.fix {
+            println!("         → {}", fix);
+        }
+        if finding.severity == Severity::Fail {
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => println!("\nNo problems found.\n"),
+        n => println!("\n{} check(s) failed.\n", n),
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    struct FakeEnv {
+        elevated: bool,
+        service_exists: bool,
+        install: WallpaperEngineInstall,
+        source: PathSource,
+        install_state: Option<InstallState>,
+        instances: Result<Vec<RunningInstance>, String>,
+        monitors: Vec<MonitorInfo>,
+        state_dir: Option<PathBuf>,
+        portable: bool,
+        writable: bool,
+    }
+
+    impl Default for FakeEnv {
+        fn default() -> Self {
+            Self {
+                elevated: true,
+                service_exists: true,
+                install: WallpaperEngineInstall { dir: PathBuf::from("we"), has_32bit: true, has_64bit: true },
+                source: PathSource::Detected,
+                install_state: None,
+                instances: Ok(Vec::new()),
+                monitors: vec![monitor(1, 100, true), monitor(2, 200, false)],
+                state_dir: Some(PathBuf::from("state")),
+                portable: false,
+                writable: true,
+            }
+        }
+    }
+
+    impl DoctorEnv for FakeEnv {
+        fn is_elevated(&self) -> bool { self.elevated }
+        fn wallpaper_engine_service_exists(&self) -> bool { self.service_exists }
+        fn wallpaper_engine(&self, _explicit_dir: Option<&str>, _prefer_64bit: bool) -> (WallpaperEngineInstall, PathSource) {
+            (self.install.clone(), self.source)
+        }
+        fn install_state(&self) -> Option<InstallState> { self.install_state.clone() }
+        fn other_instances(&self) -> Result<Vec<RunningInstance>> { self.instances.clone().map_err(|e| anyhow!(e)) }
+        fn monitors(&self) -> Vec<MonitorInfo> { self.monitors.clone() }
+        fn state_dir(&self) -> Option<PathBuf> { self.state_dir.clone() }
+        fn is_portable(&self) -> bool { self.portable }
+        fn check_writable(&self, _dir: &Path) -> Result<()> {
+            if self.writable { Ok(()) } else { Err(anyhow!("access denied")) }
+        }
+    }
+
+    fn monitor(index: i64, total_area: i64, primary: bool) -> MonitorInfo {
+        MonitorInfo { index, max_visible: total_area, total_area, primary }
+    }
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("wallpaper-controller").chain(args.iter().copied())).unwrap()
+    }
+
+    fn run(check: &dyn Check, env: &FakeEnv, args: &[&str]) -> Finding {
+        check.run(env, &cli(args))
+    }
+
+    fn severity(check: &dyn Check, env: &FakeEnv, args: &[&str]) -> Severity {
+        run(check, env, args).severity
+    }
+
+    fn install_state(startup: StartupMode) -> InstallState {
+        InstallState {
+            startup,
+            exe: PathBuf::from("wpc").join(INSTALLED_EXE_NAME),
+            exe_present: true,
+            service_host_present: true,
+            settings: Ok(cli(&[])),
+        }
+    }
+
+    fn instance(pid: u32) -> RunningInstance {
+        RunningInstance { pid, command_line: format!("{} --threshold {}", INSTALLED_EXE_NAME, pid) }
+    }
+
+    #[test]
+    fn elevation() {
+        assert_eq!(severity(&ElevationCheck, &FakeEnv::default(), &[]), Severity::Ok);
+        let env = FakeEnv { elevated: false, ..FakeEnv::default() };
+        assert_eq!(severity(&ElevationCheck, &env, &[]), Severity::Warn);
+        let env = FakeEnv { elevated: false, service_exists: false, ..FakeEnv::default() };
+        assert_eq!(severity(&ElevationCheck, &env, &[]), Severity::Ok);
+    }
+
+    #[test]
+    fn wallpaper_engine_path_and_bitness() {
+        let finding = run(&WallpaperEngineCheck, &FakeEnv::default(), &[]);
+        assert_eq!(finding.severity, Severity::Ok);
+        assert!(finding.summary.contains("32-bit"), "{}", finding.summary);
+
+        let only_32 = WallpaperEngineInstall { has_64bit: false, ..FakeEnv::default().install };
+        let env = FakeEnv { install: only_32, ..FakeEnv::default() };
+        let finding = run(&WallpaperEngineCheck, &env, &["--64bit"]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.fix.unwrap().contains("drop --64bit"));
+
+        let empty = WallpaperEngineInstall { has_32bit: false, has_64bit: false, ..FakeEnv::default().install };
+        let env = FakeEnv { install: empty, source: PathSource::CommandLine, ..FakeEnv::default() };
+        let finding = run(&WallpaperEngineCheck, &env, &[]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.fix.unwrap().contains("--wallpaper-engine-path"));
+    }
+
+    #[test]
+    fn wallpaper_engine_service() {
+        assert_eq!(severity(&WallpaperEngineServiceCheck, &FakeEnv::default(), &[]), Severity::Ok);
+        let env = FakeEnv { service_exists: false, ..FakeEnv::default() };
+        assert_eq!(severity(&WallpaperEngineServiceCheck, &env, &[]), Severity::Warn);
+    }
+
+    #[test]
+    fn install_state_without_an_installation() {
+        assert_eq!(severity(&InstallStateCheck, &FakeEnv::default(), &[]), Severity::Warn);
+        let env = FakeEnv { portable: true, ..FakeEnv::default() };
+        assert_eq!(severity(&InstallStateCheck, &env, &[]), Severity::Ok);
+    }
+
+    #[test]
+    fn install_state_of_an_installation() {
+        for startup in [StartupMode::Service, StartupMode::Task] {
+            let env = FakeEnv { install_state: Some(install_state(startup)), ..FakeEnv::default() };
+            assert_eq!(severity(&InstallStateCheck, &env, &[]), Severity::Ok);
+        }
+
+        let missing_exe = InstallState { exe_present: false, ..install_state(StartupMode::Task) };
+        let env = FakeEnv { install_state: Some(missing_exe), ..FakeEnv::default() };
+        let finding = run(&InstallStateCheck, &env, &[]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.summary.contains("missing") && finding.summary.contains(INSTALLED_EXE_NAME), "{}", finding.summary);
+        assert!(finding.fix.unwrap().contains("--repair"));
+
+        // Without the service host only a service install is broken
+        let no_host = InstallState { service_host_present: false, ..install_state(StartupMode::Service) };
+        let env = FakeEnv { install_state: Some(no_host), ..FakeEnv::default() };
+        assert_eq!(severity(&InstallStateCheck, &env, &[]), Severity::Fail);
+        let no_host = InstallState { service_host_present: false, ..install_state(StartupMode::Task) };
+        let env = FakeEnv { install_state: Some(no_host), ..FakeEnv::default() };
+        assert_eq!(severity(&InstallStateCheck, &env, &[]), Severity::Ok);
+
+        let bad_args = InstallState { settings: Err(String::from("unexpected argument '--bogus'")), ..install_state(StartupMode::Task) };
+        let env = FakeEnv { install_state: Some(bad_args), ..FakeEnv::default() };
+        let finding = run(&InstallStateCheck, &env, &[]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.summary.contains("--bogus"));
+    }
+
+    #[test]
+    fn duplicate_instances() {
+        assert_eq!(severity(&DuplicateInstanceCheck, &FakeEnv::default(), &[]), Severity::Ok);
+        let env = FakeEnv { instances: Ok(vec![instance(10)]), ..FakeEnv::default() };
+        assert_eq!(severity(&DuplicateInstanceCheck, &env, &[]), Severity::Ok);
+
+        let env = FakeEnv { instances: Ok(vec![instance(10), instance(20)]), ..FakeEnv::default() };
+        let finding = run(&DuplicateInstanceCheck, &env, &[]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.summary.starts_with("2 instances"), "{}", finding.summary);
+        assert!(finding.summary.contains("PID 10") && finding.summary.contains("PID 20"));
+
+        let env = FakeEnv { instances: Err(String::from("powershell failed")), ..FakeEnv::default() };
+        assert_eq!(severity(&DuplicateInstanceCheck, &env, &[]), Severity::Warn);
+    }
+
+    #[test]
+    fn monitors() {
+        let finding = run(&MonitorCheck, &FakeEnv::default(), &["--monitors", "largest"]);
+        assert_eq!(finding.severity, Severity::Ok);
+        assert!(finding.summary.ends_with("watching 2"), "{}", finding.summary);
+
+        let finding = run(&MonitorCheck, &FakeEnv::default(), &["--monitors", "3-4"]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.summary.contains("selects none") && finding.summary.contains("available: 1, 2"), "{}", finding.summary);
+
+        let env = FakeEnv { monitors: Vec::new(), ..FakeEnv::default() };
+        assert_eq!(severity(&MonitorCheck, &env, &[]), Severity::Fail);
+
+        let env = FakeEnv { monitors: vec![monitor(1, 100, true), MonitorInfo { max_visible: 0, ..monitor(2, 100, false) }], ..FakeEnv::default() };
+        assert_eq!(severity(&MonitorCheck, &env, &[]), Severity::Warn);
+    }
+
+    #[test]
+    fn monitors_from_an_installation_with_a_stale_selector() {
+        // The doctor checks the installed selector, which can no longer be parsed on the command line
+        let mut settings = cli(&[]);
+        settings.monitors = String::from("1-");
+        assert_eq!(MonitorCheck.run(&FakeEnv::default(), &settings).severity, Severity::Fail);
+    }
+
+    #[test]
+    fn state_dir() {
+        assert_eq!(severity(&StateDirCheck, &FakeEnv::default(), &[]), Severity::Ok);
+        let env = FakeEnv { writable: false, ..FakeEnv::default() };
+        let finding = run(&StateDirCheck, &env, &[]);
+        assert_eq!(finding.severity, Severity::Fail);
+        assert!(finding.summary.contains("access denied"));
+        let env = FakeEnv { state_dir: None, ..FakeEnv::default() };
+        assert_eq!(severity(&StateDirCheck, &env, &[]), Severity::Fail);
+    }
+
+    #[test]
+    fn the_doctor_counts_failures_and_prefers_the_installed_settings() {
+        assert_eq!(run_doctor(&FakeEnv::default(), &cli(&[])), 0);
+
+        // Installed with a selector that matches nothing, run with one that is fine
+        let installed = InstallState { settings: Ok(cli(&["--monitors", "5"])), ..install_state(StartupMode::Task) };
+        let env = FakeEnv {
+            install_state: Some(installed),
+            instances: Ok(vec![instance(10), instance(20)]),
+            ..FakeEnv::default()
+        };
+        assert_eq!(run_doctor(&env, &cli(&["--monitors", "1"])), 2);
+    }
+}
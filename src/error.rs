@@ -0,0 +1,259 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Process exit codes. These are part of the public interface (wrapper scripts and installers rely on
+/// them), so existing values must never be renumbered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ExitCode {
+    Success = 0,
+    /// Copying the executable or registering the startup service/task failed (installs exited with 1 before
+    /// exit codes were documented), or an unexpected failure that doesn't fit any other category
+    Failure = 1,
+    /// An existing service could not be removed (usually because a Services/Task Manager window holds it open)
+    ServiceRemoval = 2,
+    // 3 is left unused so install failures keep their original code
+    /// The process could not be restarted with administrator privileges
+    ElevationFailed = 4,
+    /// Another instance with the same arguments is already running
+    AlreadyRunning = 5,
+    /// The desktop visibility watcher could not be started or stopped
+    MonitorFailed = 6,
+    /// Wallpaper Engine could not be controlled (spawn failure, timeout or non-zero exit)
+    ControlFailed = 7,
+    /// The command line (or an answers file) is invalid or contains conflicting options
+    InvalidArguments = 8,
+    /// The Wallpaper Engine folder or executable could not be found
+    WallpaperEngineNotFound = 9,
+    /// Checking for, downloading, verifying or installing an update failed
+    UpdateFailed = 10,
+    /// `--verify-install` found problems (that `--repair` could not fix)
+    VerifyFailed = 11,
+    /// `--doctor` found at least one failing check
+    DoctorFailed = 12,
+    /// The config file or a `WPC_*` environment variable is invalid
+    InvalidConfig = 13,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// Failure to run a Wallpaper Engine control command
+#[derive(Debug, Error)]
+pub enum ControlError {
+    #[error("failed to spawn {}: {source}", path.display())]
+    Spawn { path: PathBuf, #[source] source: io::Error },
+
+    #[error("failed to wait for {}: {source}", path.display())]
+    Wait { path: PathBuf, #[source] source: io::Error },
+
+    #[error("{} did not exit within {timeout:?}", path.display())]
+    Timeout { path: PathBuf, timeout: Duration },
+
+    #[error("{} exited with {status}", path.display())]
+    NonZeroExit { path: PathBuf, status: ExitStatus },
+}
+
+/// The configured Wallpaper Engine location is unusable
+#[derive(Debug, Error)]
+pub enum WallpaperEngineError {
+    #[error("Wallpaper Engine folder '{}' does not exist", dir.display())]
+    MissingDir { dir: PathBuf },
+
+    #[error("'{}' is not a folder; expected the Wallpaper Engine install folder", dir.display())]
+    NotADirectory { dir: PathBuf },
+
+    #[error("{exe} was not found in '{}'", dir.display())]
+    MissingExecutable { dir: PathBuf, exe: &'static str },
+}
+
+#[derive(Debug, Error)]
+pub enum MonitorError {
+    #[error("visibility monitoring is already running")]
+    AlreadyRunning,
+
+    #[error("visibility monitoring is not running")]
+    NotRunning,
+
+    #[error("failed to initialize the desktop visibility watcher (update rate {update_rate_ms} ms)")]
+    WatcherInit { update_rate_ms: u64 },
+
+    #[error("failed to stop the desktop visibility watcher")]
+    WatcherStop,
+
+    #[error("failed to resume wallpapers on shutdown: {0}")]
+    Resume(#[source] ControlError),
+}
+
+#[derive(Debug, Error)]
+pub enum InstallError {
+    #[error("invalid answers file '{path}': {reason}")]
+    Answers { path: String, reason: String },
+
+    #[error("failed to inspect the current installation: {0:#}")]
+    Probe(#[source] anyhow::Error),
+
+    #[error("failed to prepare the install journal: {0:#}")]
+    Journal(#[source] anyhow::Error),
+
+    #[error("failed to install the executable into '{dir}': {source:#}")]
+    Copy { dir: String, #[source] source: anyhow::Error },
+
+    #[error("version {installed} is installed; refusing to replace it with older version {new} without --allow-downgrade")]
+    Downgrade { installed: String, new: String },
+
+    #[error("failed to delete existing service '{name}': {source}")]
+    ServiceRemoval { name: String, #[source] source: windows_service::Error },
+
+    #[error("failed to set up the startup service: {0:#}")]
+    ServiceSetup(#[source] anyhow::Error),
+
+    #[error("failed to start the startup service: {0}")]
+    ServiceStart(#[source] windows_service::Error),
+
+    #[error("per-user install not possible: {0}")]
+    PerUser(String),
+
+    #[error("failed to set up the startup scheduled task: {0:#}")]
+    TaskSetup(#[source] anyhow::Error),
+
+    #[error("no startup service or scheduled task is installed; install first (e.g. with --install-tui)")]
+    NotInstalled,
+
+    #[error("failed to reconfigure the installation: {0:#}")]
+    Reconfigure(#[source] anyhow::Error),
+
+    #[error("{0} installation check(s) failed; see the checklist above")]
+    VerifyFailed(usize),
+
+    #[error("uninstall could not remove {0} item(s); see the summary above")]
+    UninstallIncomplete(usize),
+}
+
+/// Failure to check for or apply an update
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("failed to fetch {url}: {source:#}")]
+    Fetch { url: String, #[source] source: anyhow::Error },
+
+    #[error("invalid release manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("this build has no release signing key (it was built without WPC_RELEASE_PUBLIC_KEY), so releases \
+             can't be verified; pass the key with --update-public-key or download the release by hand")]
+    NoPublicKey,
+
+    #[error("invalid release signing key: {0}")]
+    InvalidPublicKey(String),
+
+    #[error("the release manifest is not signed by the release signing key")]
+    BadSignature,
+
+    #[error("downloaded executable has blake3 hash {actual}, but the release manifest says {expected}")]
+    HashMismatch { expected: String, actual: String },
+
+    #[error("downloaded executable is version {found}, but the release manifest says {expected}")]
+    VersionMismatch { expected: String, found: String },
+
+    #[error("no installed copy found; install with --install-dir (or point --install-dir at it) first")]
+    NotInstalled,
+
+    #[error("failed to replace '{}': {source:#}", path.display())]
+    Swap { path: PathBuf, #[source] source: anyhow::Error },
+
+    #[error("installed {version} but failed to restart the startup service/task: {source:#}")]
+    Restart { version: String, #[source] source: anyhow::Error },
+}
+
+/// The config file or a `WPC_*` environment variable is unusable
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file '{}': {source}", path.display())]
+    Read { path: PathBuf, #[source] source: io::Error },
+
+    #[error("invalid config file '{}': {reason}", path.display())]
+    Parse { path: PathBuf, reason: String },
+
+    #[error("invalid {key} from {origin}: {reason}")]
+    Invalid { key: &'static str, origin: String, reason: String },
+}
+
+/// Top-level error; every way the process can fail ends up here and maps to a stable exit code
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("invalid arguments: {0}")]
+    InvalidArguments(String),
+
+    #[error("another instance with the same arguments is already running")]
+    AlreadyRunning,
+
+    #[error("failed to elevate process: {0}")]
+    Elevation(String),
+
+    #[error("{0} doctor check(s) failed; see the suggested fixes above")]
+    DoctorFailed(usize),
+
+    #[error(transparent)]
+    Install(#[from] InstallError),
+
+    #[error(transparent)]
+    Update(#[from] UpdateError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Monitor(#[from] MonitorError),
+
+    #[error(transparent)]
+    Control(#[from] ControlError),
+
+    #[error("{0}. Use --wallpaper-engine-path to point at the Wallpaper Engine install folder")]
+    WallpaperEngine(#[from] WallpaperEngineError),
+
+    #[error("{0:#}")]
+    Other(#[from] anyhow::Error),
+}
+
+impl AppError {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            AppError::InvalidArguments(_) => ExitCode::InvalidArguments,
+            AppError::AlreadyRunning => ExitCode::AlreadyRunning,
+            AppError::Elevation(_) => ExitCode::ElevationFailed,
+            AppError::DoctorFailed(_) => ExitCode::DoctorFailed,
+            AppError::Install(InstallError::ServiceRemoval { .. }) => ExitCode::ServiceRemoval,
+            AppError::Install(InstallError::Answers { .. }) => ExitCode::InvalidArguments,
+            AppError::Install(InstallError::VerifyFailed(_)) => ExitCode::VerifyFailed,
+            AppError::Install(_) => ExitCode::Failure,
+            AppError::Update(_) => ExitCode::UpdateFailed,
+            AppError::Config(_) => ExitCode::InvalidConfig,
+            AppError::Monitor(MonitorError::Resume(_)) | AppError::Control(_) => ExitCode::ControlFailed,
+            AppError::Monitor(_) => ExitCode::MonitorFailed,
+            AppError::WallpaperEngine(_) => ExitCode::WallpaperEngineNotFound,
+            AppError::Other(_) => ExitCode::Failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_failures_keep_their_original_exit_codes() {
+        let copy = InstallError::Copy { dir: String::from("C:\\wpc"), source: anyhow::anyhow!("access denied") };
+        assert_eq!(AppError::from(copy).exit_code().code(), 1);
+        assert_eq!(AppError::from(InstallError::TaskSetup(anyhow::anyhow!("schtasks failed"))).exit_code().code(), 1);
+        assert_eq!(AppError::from(InstallError::NotInstalled).exit_code().code(), 1);
+        assert_eq!(AppError::from(InstallError::VerifyFailed(2)).exit_code(), ExitCode::VerifyFailed);
+        let answers = InstallError::Answers { path: String::from("a.toml"), reason: String::from("bad") };
+        assert_eq!(AppError::from(answers).exit_code(), ExitCode::InvalidArguments);
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Number of decisions kept in memory (and mirrored to the trace file)
+pub const DECISION_LOG_CAPACITY: usize = 100;
+// Decisions are appended to the trace file, which is cut back to the newest ones once it holds this many
+const TRACE_FILE_MAX_ENTRIES: usize = DECISION_LOG_CAPACITY * 10;
+
+/// Visibility of a single monitor as reported by libvisdesk
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MonitorSample {
+    pub monitor_index: i64,
+    pub current_visible: i64,
+    pub max_visible: i64,
+    pub visibility_percent: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregation {
+    /// Visible area summed over the selected monitors, divided by their summed maximum visible area
+    GlobalAreaSum,
+    /// Like `GlobalAreaSum`, with each monitor's areas multiplied by its configured weight
+    WeightedAreaSum,
+    /// Each selected monitor compared against the threshold on its own
+    PerMonitor,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Crossing {
+    Below,
+    Above,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Paused,
+    Resumed,
+    /// The computed state did not change, e.g. because another reason still holds the target
+    Unchanged,
+    CommandFailed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RateLimit {
+    pub update_rate_ms: u64,
+    pub since_previous_update_ms: Option<u64>,
+}
+
+/// A pause/resume decision together with everything that went into it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Decision {
+    pub timestamp_ms: u64,
+    pub target: Option<i64>,
+    pub inputs: Vec<MonitorSample>,
+    pub selected_monitors: Vec<i64>,
+    pub aggregation: Aggregation,
+    /// Monitor weights for `WeightedAreaSum`; monitors not listed weigh 1
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub weights: BTreeMap<i64, f64>,
+    pub visibility_percent: u8,
+    pub previous_visibility_percent: Option<u8>,
+    pub threshold: u8,
+    pub crossing: Crossing,
+    pub rate_limit: RateLimit,
+    pub active_reasons: Vec<String>,
+    pub outcome: Outcome,
+}
+
+impl Decision {
+    pub fn explain(&self) -> String {
+        let target = match self.target {
+            Some(index) => format!("Monitor number {}", index),
+            None => String::from("All wallpapers"),
+        };
+        let outcome = match self.outcome {
+            Outcome::Paused => "paused",
+            Outcome::Resumed => "resumed",
+            Outcome::Unchanged => "left unchanged",
+            Outcome::CommandFailed => "command failed",
+        };
+        let crossing = match self.crossing {
+            Crossing::Below => "fell below",
+            Crossing::Above => "rose to or above",
+        };
+        let aggregation = match self.aggregation {
+            Aggregation::GlobalAreaSum => String::from("summed visible area of the selected monitors"),
+            Aggregation::WeightedAreaSum => format!(
+                "weighted visible area of the selected monitors ({})",
+                self.weights.iter().map(|(m, w)| format!("{}={}", m, w)).collect::<Vec<_>>().join(", ")
+            ),
+            Aggregation::PerMonitor => String::from("per-monitor visibility"),
+        };
+        let previous = self.previous_visibility_percent
+            .map(|p| format!("was {}%", p))
+            .unwrap_or_else(|| String::from("first sample"));
+        let join = |v: &[i64]| v.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+        let all_monitors: Vec<i64> = self.inputs.iter().map(|m| m.monitor_index).collect();
+
+        let mut out = format!("[{}] {}: {}\n", format_age(self.timestamp_ms), target, outcome);
+        out += &format!("  Visibility {}% ({}) {} threshold {}% using {}\n",
+                        self.visibility_percent, previous, crossing, self.threshold, aggregation);
+        out += &format!("  Selected monitors: {} (of {})\n", join(&self.selected_monitors), join(&all_monitors));
+        for m in &self.inputs {
+            out += &format!("    Monitor number {}: {}/{} px visible ({}%)\n",
+                            m.monitor_index, m.current_visible, m.max_visible, m.visibility_percent);
+        }
+        out += &format!("  Rate limit: at most one update every {} ms", self.rate_limit.update_rate_ms);
+        match self.rate_limit.since_previous_update_ms {
+            Some(ms) => out += &format!("; previous update {} ms earlier\n", ms),
+            None => out += "\n",
+        }
+        out += &format!("  Active reasons: {}\n",
+                        if self.active_reasons.is_empty() { String::from("none") } else { self.active_reasons.join(", ") });
+        out
+    }
+}
+
+/// Ring buffer of recent decisions, appended to a JSON-lines trace file so other processes (and later runs) can
+/// read them
+pub struct DecisionLog {
+    entries: DecisionHistory,
+    capacity: usize,
+    trace_file: Option<PathBuf>,
+    entries_in_file: usize,
+}
+
+/// Read access to the ring buffer of a running `DecisionLog`
+#[derive(Clone, Default)]
+pub struct DecisionHistory(Arc<Mutex<VecDeque<Decision>>>);
+
+impl DecisionHistory {
+    /// The last `n` decisions, oldest first
+    pub fn recent(&self, n: usize) -> Vec<Decision> {
+        let entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().skip(entries.len().saturating_sub(n)).cloned().collect()
+    }
+}
+
+impl DecisionLog {
+    /// Starts with the newest decisions already in `trace_file`, so a restart keeps the history
+    pub fn new(capacity: usize, trace_file: Option<PathBuf>) -> Self {
+        let recorded = match &trace_file {
+            Some(path) if path.exists() => read_decisions(path).unwrap_or_else(|e| {
+                warn!("Failed to read decision trace {}: {:#}", path.display(), e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+        let entries_in_file = recorded.len();
+        let skip = recorded.len().saturating_sub(capacity);
+        Self {
+            entries: DecisionHistory(Arc::new(Mutex::new(recorded.into_iter().skip(skip).collect()))),
+            capacity,
+            trace_file,
+            entries_in_file,
+        }
+    }
+
+    pub fn history(&self) -> DecisionHistory {
+        self.entries.clone()
+    }
+
+    pub fn record(&mut self, decision: Decision) {
+        let Some(path) = self.trace_file.clone() else {
+            self.push(decision);
+            return;
+        };
+        let result = if self.entries_in_file >= TRACE_FILE_MAX_ENTRIES {
+            self.push(decision);
+            let entries = self.entries.recent(self.capacity);
+            write_trace_file(&path, entries.iter()).map(|()| entries.len())
+        } else {
+            let result = append_to_trace_file(&path, &decision).map(|()| self.entries_in_file + 1);
+            self.push(decision);
+            result
+        };
+        match result {
+            Ok(count) => self.entries_in_file = count,
+            Err(e) => warn!("Failed to write decision trace to {}: {}", path.display(), e),
+        }
+    }
+
+    fn push(&mut self, decision: Decision) {
+        let mut entries = self.entries.0.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(decision);
+    }
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn write_trace_file<'a>(path: &Path, decisions: impl Iterator<Item = &'a Decision>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = String::new();
+    for decision in decisions {
+        out += &serde_json::to_string(decision)?;
+        out.push('\n');
+    }
+    // Write to a sibling file first so readers never observe a half-written trace
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, out)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn append_to_trace_file(path: &Path, decision: &Decision) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(decision)?;
+    line.push('\n');
+    // One write per line, so a reader sees whole lines (a torn last line is skipped as malformed)
+    fs::File::options().create(true).append(true).open(path)?.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Reads the last `n` decisions from a trace file, oldest first
+pub fn read_trace_file(path: &Path, n: usize) -> Result<Vec<Decision>> {
+    let mut decisions = read_decisions(path)?;
+    let skip = decisions.len().saturating_sub(n);
+    Ok(decisions.split_off(skip))
+}
+
+fn read_decisions(path: &Path) -> Result<Vec<Decision>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read decision trace {}", path.display()))?;
+    let mut decisions = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() { continue; }
+        match serde_json::from_str::<Decision>(line) {
+            Ok(d) => decisions.push(d),
+            Err(e) => warn!("Skipping malformed decision on line {}: {}", i + 1, e),
+        }
+    }
+    Ok(decisions)
+}
+
+pub fn print_explanations(path: &Path, n: usize) -> Result<()> {
+    let decisions = read_trace_file(path, n)?;
+    if decisions.is_empty() {
+        println!("No decisions recorded in {}", path.display());
+        return Ok(());
+    }
+    println!("\nLast {} decision(s) from {}:\n", decisions.len(), path.display());
+    for decision in &decisions {
+        println!("{}", decision.explain());
+    }
+    Ok(())
+}
+
+pub(crate) fn format_age(timestamp_ms: u64) -> String {
+    let secs = now_ms().saturating_sub(timestamp_ms) / 1000;
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m {:02}s ago", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m ago", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(timestamp_ms: u64) -> Decision {
+        Decision {
+            timestamp_ms,
+            target: None,
+            inputs: vec![MonitorSample { monitor_index: 1, current_visible: 10, max_visible: 100, visibility_percent: 10 }],
+            selected_monitors: vec![1],
+            aggregation: Aggregation::GlobalAreaSum,
+            weights: BTreeMap::new(),
+            visibility_percent: 10,
+            previous_visibility_percent: Some(30),
+            threshold: 20,
+            crossing: Crossing::Below,
+            rate_limit: RateLimit { update_rate_ms: 500, since_previous_update_ms: Some(600) },
+            active_reasons: vec![String::from("visibility")],
+            outcome: Outcome::Paused,
+        }
+    }
+
+    fn timestamps(decisions: &[Decision]) -> Vec<u64> {
+        decisions.iter().map(|d| d.timestamp_ms).collect()
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_newest_decisions() {
+        let mut log = DecisionLog::new(3, None);
+        for t in 1..=5 {
+            log.record(decision(t));
+        }
+        assert_eq!(timestamps(&log.entries.recent(10)), [3, 4, 5]);
+        assert_eq!(timestamps(&log.entries.recent(2)), [4, 5]);
+    }
+
+    #[test]
+    fn decisions_are_appended_and_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decisions.jsonl");
+        let mut log = DecisionLog::new(3, Some(path.clone()));
+        log.record(decision(1));
+        log.record(decision(2));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let mut restarted = DecisionLog::new(3, Some(path.clone()));
+        assert_eq!(timestamps(&restarted.entries.recent(10)), [1, 2]);
+        restarted.record(decision(3));
+        restarted.record(decision(4));
+        assert_eq!(timestamps(&read_trace_file(&path, 10).unwrap()), [1, 2, 3, 4]);
+        assert_eq!(timestamps(&restarted.entries.recent(10)), [2, 3, 4]);
+    }
+
+    #[test]
+    fn a_full_trace_file_is_cut_back_to_the_ring_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decisions.jsonl");
+        let mut log = DecisionLog::new(DECISION_LOG_CAPACITY, Some(path.clone()));
+        for t in 0..TRACE_FILE_MAX_ENTRIES as u64 {
+            log.record(decision(t));
+        }
+        assert_eq!(read_trace_file(&path, usize::MAX).unwrap().len(), TRACE_FILE_MAX_ENTRIES);
+
+        log.record(decision(TRACE_FILE_MAX_ENTRIES as u64));
+        let kept = read_trace_file(&path, usize::MAX).unwrap();
+        assert_eq!(kept.len(), DECISION_LOG_CAPACITY);
+        assert_eq!(kept.last().unwrap().timestamp_ms, TRACE_FILE_MAX_ENTRIES as u64);
+        assert!(!path.with_extension("jsonl.tmp").exists());
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decisions.jsonl");
+        let line = serde_json::to_string(&decision(7)).unwrap();
+        fs::write(&path, format!("{}\nnot json\n\n{}\n{{\"truncated", line, line)).unwrap();
+        assert_eq!(timestamps(&read_trace_file(&path, 10).unwrap()), [7, 7]);
+        assert_eq!(timestamps(&read_trace_file(&path, 1).unwrap()), [7]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Cli;
+use crate::error::InstallError;
+use crate::install::task_xml::TaskTrigger;
+use crate::install::tui::{
+    validate_install_dir, validate_monitors, validate_restart_count, validate_restart_interval, validate_task_delay,
+    validate_threshold, validate_update_rate, validate_we_path, wallpaper_engine_service_exists,
+};
+
+// An answers file holds what the interactive installer asks for, so the same setup can be deployed
+// unattended with `--install-from`. `--install-tui --save-answers` writes one from the wizard's choices.
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupMode {
+    Service,
+    Task,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Answers {
+    pub startup: StartupMode,
+    pub install_dir: String,
+    #[serde(default = "default_monitors")]
+    pub monitors: String,
+    #[serde(default = "default_threshold")]
+    pub threshold: u8,
+    #[serde(default)]
+    pub per_monitor: bool,
+    #[serde(default = "default_update_rate")]
+    pub update_rate: u64,
+    #[serde(default)]
+    pub bit64: bool,
+    /// Detected from the Steam libraries at every start when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallpaper_engine_path: Option<String>,
+    /// Install for the current user only, without administrator rights (`startup = "task"` only)
+    #[serde(default)]
+    pub per_user: bool,
+    /// Only used for `startup = "task"`
+    #[serde(default)]
+    pub task: TaskAnswers,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TaskAnswers {
+    #[serde(default = "default_task_delay")]
+    pub delay: u64,
+    #[serde(default = "default_task_triggers")]
+    pub triggers: Vec<TaskTrigger>,
+    #[serde(default = "default_restart_count")]
+    pub restart_count: u16,
+    #[serde(default = "default_restart_interval")]
+    pub restart_interval: u64,
+}
+
+// Defaults match the command line's
+fn default_monitors() -> String { String::from("all") }
+fn default_threshold() -> u8 { 20 }
+fn default_update_rate() -> u64 { 500 }
+fn default_task_delay() -> u64 { 75 }
+fn default_task_triggers() -> Vec<TaskTrigger> { vec![TaskTrigger::Logon] }
+fn default_restart_count() -> u16 { 3 }
+fn default_restart_interval() -> u64 { 60 }
+
+impl Default for TaskAnswers {
+    fn default() -> Self {
+        Self {
+            delay: default_task_delay(),
+            triggers: default_task_triggers(),
+            restart_count: default_restart_count(),
+            restart_interval: default_restart_interval(),
+        }
+    }
+}
+
+impl Answers {
+    pub fn load(path: &Path) -> Result<Self, InstallError> {
+        let answers_error = |reason: String| InstallError::Answers { path: path.display().to_string(), reason };
+        let content = fs::read_to_string(path).map_err(|e| answers_error(e.to_string()))?;
+        let answers: Answers = toml::from_str(&content).map_err(|e| answers_error(e.to_string()))?;
+        answers.validate().map_err(|problems| answers_error(problems.join("; ")))?;
+        Ok(answers)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string_pretty(self).context("Failed to serialize the answers")?;
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Adds `path` (made absolute) to `list`, the answers files --uninstall removes
+    pub fn record_saved(list: &Path, path: &Path) -> Result<()> {
+        let path = std::path::absolute(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+        let mut recorded = Self::recorded_saved(list);
+        if recorded.contains(&path) {
+            return Ok(());
+        }
+        recorded.push(path);
+        if let Some(dir) = list.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content: String = recorded.iter().map(|p| format!("{}\n", p.display())).collect();
+        fs::write(list, content).with_context(|| format!("Failed to write {}", list.display()))
+    }
+
+    /// The answers files listed in `list` by `record_saved`
+    pub fn recorded_saved(list: &Path) -> Vec<PathBuf> {
+        fs::read_to_string(list).unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Runs the interactive installer's validators over every answer and returns all problems found
+    pub fn validate(&self) -> std::result::Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut check = |field: &str, result: std::result::Result<(), String>| {
+            if let Err(e) = result {
+                problems.push(format!("{}: {}", field, e));
+            }
+        };
+        check("install_dir", validate_install_dir(&self.install_dir));
+        check("monitors", validate_monitors(&self.monitors));
+        check("threshold", validate_threshold(&self.threshold.to_string()));
+        check("update_rate", validate_update_rate(&self.update_rate.to_string()));
+        if let Some(path) = &self.wallpaper_engine_path {
+            check("wallpaper_engine_path", validate_we_path(path, self.bit64));
+        }
+        match self.startup {
+            StartupMode::Service if self.per_user => check("per_user", Err(String::from(
+                "a service can't be installed per user; use startup = \"task\""
+            ))),
+            StartupMode::Service if !wallpaper_engine_service_exists() => check("startup", Err(String::from(
+                "the Wallpaper Engine service is not installed; enable High Priority mode in Wallpaper Engine or use \"task\""
+            ))),
+            StartupMode::Service => {}
+            StartupMode::Task => {
+                check("task.delay", validate_task_delay(&self.task.delay.to_string()));
+                check("task.restart_count", validate_restart_count(&self.task.restart_count.to_string()));
+                check("task.restart_interval", validate_restart_interval(&self.task.restart_interval.to_string()));
+                if self.task.triggers.is_empty() {
+                    check("task.triggers", Err(String::from("Select at least one trigger")));
+                }
+            }
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    /// The answers the wizard collected into `cli`
+    pub fn from_cli(cli: &Cli) -> Option<Self> {
+        let startup = match (cli.add_startup_service, cli.add_startup_task) {
+            (true, false) => StartupMode::Service,
+            (false, true) => StartupMode::Task,
+            _ => return None,
+        };
+        Some(Self {
+            startup,
+            install_dir: cli.install_dir.clone()?,
+            monitors: cli.monitors.clone(),
+            threshold: cli.threshold.unwrap_or_else(default_threshold),
+            per_monitor: cli.per_monitor,
+            update_rate: cli.update_rate,
+            bit64: cli.bit64,
+            wallpaper_engine_path: cli.wallpaper_engine_path.clone(),
+            per_user: cli.per_user,
+            task: TaskAnswers {
+                delay: cli.task_delay,
+                triggers: cli.task_triggers.clone(),
+                restart_count: cli.task_restart_count,
+                restart_interval: cli.task_restart_interval,
+            },
+        })
+    }
+
+    /// Fills the install and runtime options of `cli` from the answers
+    pub fn apply(&self, cli: &mut Cli) {
+        cli.install_dir = Some(self.install_dir.trim().to_string());
+        cli.add_startup_service = self.startup == StartupMode::Service;
+        cli.add_startup_task = self.startup == StartupMode::Task;
+        cli.monitors = self.monitors.trim().to_string();
+        cli.threshold = Some(self.threshold);
+        cli.per_monitor = self.per_monitor;
+        cli.update_rate = self.update_rate;
+        cli.bit64 = self.bit64;
+        cli.wallpaper_engine_path = self.wallpaper_engine_path.as_ref().map(|p| p.trim().to_string());
+        cli.per_user = cli.per_user || self.per_user;
+        cli.task_delay = self.task.delay;
+        cli.task_triggers = self.task.triggers.clone();
+        cli.task_restart_count = self.task.restart_count;
+        cli.task_restart_interval = self.task.restart_interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::install::task_xml::RESTART_INTERVAL_SECS;
+
+    fn sample_path() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("answers.toml")
+    }
+
+    fn sample() -> Answers {
+        Answers::load(&sample_path()).unwrap()
+    }
+
+    fn problems(answers: &Answers) -> Vec<String> {
+        answers.validate().err().unwrap_or_default()
+    }
+
+    #[test]
+    fn the_sample_answers_file_loads() {
+        let answers = sample();
+        assert_eq!(answers.startup, StartupMode::Task);
+        assert_eq!(answers.install_dir, r"C:\Users\me\.wallpaper-controller");
+        assert_eq!(answers.task, TaskAnswers::default());
+        assert_eq!(answers.wallpaper_engine_path, None);
+    }
+
+    #[test]
+    fn missing_answers_take_the_command_line_defaults() {
+        let answers: Answers = toml::from_str("startup = \"task\"\ninstall_dir = 'C:\\wpc'").unwrap();
+        let defaults = Cli::try_parse_from(["wallpaper-controller"]).unwrap();
+        assert_eq!(answers.monitors, defaults.monitors);
+        assert_eq!(answers.update_rate, defaults.update_rate);
+        assert_eq!(answers.task.delay, defaults.task_delay);
+        assert_eq!(answers.task.triggers, defaults.task_triggers);
+        assert_eq!(answers.task.restart_count, defaults.task_restart_count);
+        assert_eq!(answers.task.restart_interval, defaults.task_restart_interval);
+    }
+
+    #[test]
+    fn the_restart_interval_follows_the_installer_rules() {
+        let mut answers = sample();
+        for secs in [*RESTART_INTERVAL_SECS.start(), 3600, *RESTART_INTERVAL_SECS.end()] {
+            answers.task.restart_interval = secs;
+            assert_eq!(problems(&answers), Vec::<String>::new(), "{}", secs);
+        }
+        for secs in [0, RESTART_INTERVAL_SECS.start() - 1, RESTART_INTERVAL_SECS.end() + 1] {
+            answers.task.restart_interval = secs;
+            assert_eq!(problems(&answers), [format!(
+                "task.restart_interval: {}", validate_restart_interval(&secs.to_string()).unwrap_err()
+            )]);
+        }
+        // The command line accepts exactly the same range
+        for (secs, ok) in [(59, false), (60, true), (2678400, true), (2678401, false)] {
+            let parsed = Cli::try_parse_from(["wallpaper-controller", "--task-restart-interval", &secs.to_string()]);
+            assert_eq!(parsed.is_ok(), ok, "{}", secs);
+            assert_eq!(validate_restart_interval(&secs.to_string()).is_ok(), ok, "{}", secs);
+        }
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut answers = sample();
+        answers.threshold = 101;
+        answers.monitors = String::from("1-");
+        answers.task.restart_count = 1000;
+        answers.task.triggers.clear();
+        let fields: Vec<String> = problems(&answers).iter().map(|p| p.split(':').next().unwrap().to_string()).collect();
+        assert_eq!(fields, ["monitors", "threshold", "task.restart_count", "task.triggers"]);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("answers.toml");
+        fs::write(&path, "startup = \"task\"\ninstall_dir = 'C:\\wpc'\nthreshhold = 20\n").unwrap();
+        let error = Answers::load(&path).unwrap_err().to_string();
+        assert!(error.contains("threshhold"), "{}", error);
+    }
+
+    #[test]
+    fn saved_answers_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("answers.toml");
+        let mut answers = sample();
+        answers.task.triggers = vec![TaskTrigger::Logon, TaskTrigger::Unlock];
+        answers.task.restart_interval = 600;
+        answers.save(&path).unwrap();
+        assert_eq!(Answers::load(&path).unwrap(), answers);
+
+        let mut cli = Cli::try_parse_from(["wallpaper-controller"]).unwrap();
+        answers.apply(&mut cli);
+        assert_eq!(Answers::from_cli(&cli), Some(answers));
+    }
+}
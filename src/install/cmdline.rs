@@ -0,0 +1,205 @@
+use std::ffi::{OsStr, OsString};
+
+// Encoding and decoding of Windows command lines following the MSVC runtime / CommandLineToArgvW rules:
+//  - arguments are separated by spaces or tabs outside of quotes
+//  - 2n backslashes followed by a quote produce n backslashes and toggle quoting
+//  - 2n+1 backslashes followed by a quote produce n backslashes and a literal quote
+//  - backslashes not followed by a quote are literal
+//  - the program name (argv[0]) is special: it ends at the next quote if quoted, at whitespace otherwise,
+//    and backslashes are never escapes in it
+
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty() || s.contains([' ', '\t', '\n', '\x0b', '"'])
+}
+
+/// Quotes a single argument (not the program name) so that it decodes back to exactly `arg`
+pub fn quote_arg(arg: &OsStr) -> OsString {
+    let s = arg.to_string_lossy();
+    if !needs_quotes(&s) {
+        return arg.to_owned();
+    }
+
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    let mut backslashes = 0usize;
+    for c in s.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Escape the preceding backslashes and the quote itself
+                out.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                out.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                out.extend(std::iter::repeat_n('\\', backslashes));
+                out.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Trailing backslashes precede the closing quote, so they must be doubled (C:\Tools\ -> "C:\Tools\\")
+    out.extend(std::iter::repeat_n('\\', backslashes * 2));
+    out.push('"');
+    out.into()
+}
+
+/// Builds a full command line; the program name is only wrapped in quotes since it can't contain any
+pub fn encode_command_line<S: AsRef<OsStr>>(program: &OsStr, args: &[S]) -> OsString {
+    let program_str = program.to_string_lossy();
+    let mut out = OsString::new();
+    if needs_quotes(&program_str) {
+        out.push("\"");
+        out.push(program);
+        out.push("\"");
+    } else {
+        out.push(program);
+    }
+    if !args.is_empty() {
+        out.push(" ");
+        out.push(encode_args(args));
+    }
+    out
+}
+
+/// Joins arguments (without a program name) into the argument part of a command line
+pub fn encode_args<S: AsRef<OsStr>>(args: &[S]) -> OsString {
+    let mut out = OsString::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push(" ");
+        }
+        out.push(quote_arg(arg.as_ref()));
+    }
+    out
+}
+
+/// Splits a command line into argv, program name first
+pub fn decode_command_line(cmdline: &str) -> Vec<String> {
+    let mut argv = Vec::new();
+    let mut chars = cmdline.chars().peekable();
+
+    // Program name
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+    if chars.peek().is_none() {
+        return argv;
+    }
+    let mut program = String::new();
+    if chars.next_if_eq(&'"').is_some() {
+        for c in chars.by_ref() {
+            if c == '"' { break; }
+            program.push(c);
+        }
+    } else {
+        while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t') {
+            program.push(c);
+        }
+    }
+    argv.push(program);
+
+    // Arguments
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut arg = String::new();
+        let mut in_quotes = false;
+        loop {
+            match chars.peek().copied() {
+                None => break,
+                Some(' ') | Some('\t') if !in_quotes => break,
+                Some('\\') => {
+                    let mut backslashes = 0;
+                    while chars.next_if_eq(&'\\').is_some() {
+                        backslashes += 1;
+                    }
+                    if chars.peek() == Some(&'"') {
+                        arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                        if backslashes % 2 == 1 {
+                            arg.push('"');
+                            chars.next();
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat_n('\\', backslashes));
+                    }
+                }
+                Some('"') => {
+                    chars.next();
+                    // Inside quotes, "" is a literal quote (MSVC 2008+ behavior)
+                    if in_quotes && chars.peek() == Some(&'"') {
+                        arg.push('"');
+                        chars.next();
+                    } else {
+                        in_quotes = !in_quotes;
+                    }
+                }
+                Some(c) => {
+                    arg.push(c);
+                    chars.next();
+                }
+            }
+        }
+        argv.push(arg);
+    }
+    argv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(args: &[&str]) {
+        let encoded = encode_command_line(OsStr::new(r"C:\Program Files\wallpaper-controller.exe"), args);
+        let decoded = decode_command_line(&encoded.to_string_lossy());
+        assert_eq!(decoded[0], r"C:\Program Files\wallpaper-controller.exe");
+        assert_eq!(&decoded[1..], args, "encoded as {:?}", encoded);
+    }
+
+    #[test]
+    fn arguments_round_trip() {
+        round_trip(&[]);
+        round_trip(&["--threshold", "80"]);
+        round_trip(&["C:\\Program Files (x86)\\Steam", "two  spaces", " leading", "trailing "]);
+        round_trip(&[r#"say "hi""#, r#"""#, r#""""#, r#"a\"b"#, r#"a\\"b"#]);
+        round_trip(&[r"C:\Tools\", r"C:\Tools dir\", r"\\server\share\\", r"\", r"\\"]);
+        round_trip(&["", "", "x", ""]);
+        round_trip(&["tab\there", "\t", "new\nline", "vertical\x0btab"]);
+        round_trip(&["ünïcødé path", r"D:\SteamLibrary\steamapps\common\wallpaper_engine"]);
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote_arg(OsStr::new(r"C:\Tools\")), OsString::from(r"C:\Tools\"));
+        assert_eq!(quote_arg(OsStr::new(r"C:\My Tools\")), OsString::from(r#""C:\My Tools\\""#));
+        assert_eq!(quote_arg(OsStr::new(r#"a\"b"#)), OsString::from(r#""a\\\"b""#));
+        assert_eq!(quote_arg(OsStr::new("")), OsString::from(r#""""#));
+    }
+
+    #[test]
+    fn decodes_like_the_msvc_runtime() {
+        let args = |cmdline: &str| decode_command_line(&format!("prog.exe {}", cmdline))[1..].to_vec();
+        // 2n+1 backslashes before a quote: n backslashes and a literal quote
+        assert_eq!(args(r#"a\\\"b"#), [r#"a\"b"#]);
+        // An empty pair of quotes is an empty argument
+        assert_eq!(args(r#""""#), [""]);
+        assert_eq!(args(r#"a "" b"#), ["a", "", "b"]);
+        // 2n backslashes before a quote: n backslashes, and the quote toggles quoting
+        assert_eq!(args(r#"a\\"b c" d"#), [r"a\b c", "d"]);
+        // Backslashes not followed by a quote are literal
+        assert_eq!(args(r"a\\b\c"), [r"a\\b\c"]);
+        // "" inside quotes is a literal quote
+        assert_eq!(args(r#""a""b""#), [r#"a"b"#]);
+        // An unterminated quote runs to the end of the line
+        assert_eq!(args(r#""a b"#), ["a b"]);
+        assert_eq!(args("a\t\tb  "), ["a", "b"]);
+    }
+
+    #[test]
+    fn the_program_name_has_no_escapes() {
+        assert_eq!(decode_command_line(r#""C:\dir\"rest"#), [r"C:\dir\", "rest"]);
+        assert_eq!(decode_command_line(r"C:\dir\prog.exe -x"), [r"C:\dir\prog.exe", "-x"]);
+        assert_eq!(decode_command_line("  "), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,157 @@
+use std::ffi::{OsStr, OsString};
+
+// wallpaperservice32.exe starts the controller with the arguments that follow `-p <exe>`, but re-joins them
+// without quoting, so any argument containing whitespace or quotes (e.g. the default Wallpaper Engine path,
+// "C:\Program Files (x86)\...") gets split apart on the way. Such arguments are percent-encoded behind a
+// prefix when the service is registered and decoded again by the controller on startup. The service passes
+// a marker argument first, so only command lines that went through the service are decoded; arguments typed
+// by the user are taken as they are, even if they happen to start with the prefix.
+const ENCODED_PREFIX: &str = "pct:";
+/// Precedes the encoded arguments on the service command line; the controller strips it
+pub const FORWARDED_MARKER: &str = "-forwarded";
+
+fn is_safe_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-_.:\\/,=+@~".contains(&b)
+}
+
+/// Encodes an argument so it contains only characters that survive an unquoted re-join/split
+pub fn encode_forwarded_arg(arg: &OsStr) -> OsString {
+    let s = arg.to_string_lossy();
+    // Empty arguments would vanish in the re-join, so they get the prefix as well
+    if !s.is_empty() && s.bytes().all(is_safe_byte) && !s.starts_with(ENCODED_PREFIX) {
+        return arg.to_owned();
+    }
+
+    let mut out = String::from(ENCODED_PREFIX);
+    for b in s.bytes() {
+        if is_safe_byte(b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out.into()
+}
+
+/// Reverses `encode_forwarded_arg`; arguments without the prefix are returned unchanged
+pub fn decode_forwarded_arg(arg: &str) -> String {
+    let Some(encoded) = arg.strip_prefix(ENCODED_PREFIX) else {
+        return arg.to_string();
+    };
+
+    let bytes = encoded.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decodes a command line (program name included) if it carries `FORWARDED_MARKER`, dropping the marker;
+/// any other command line is returned unchanged
+pub fn decode_forwarded_args<I: IntoIterator<Item = String>>(args: I) -> Vec<String> {
+    let args: Vec<String> = args.into_iter().collect();
+    if !args.iter().any(|a| a == FORWARDED_MARKER) {
+        return args;
+    }
+    args.iter()
+        .filter(|a| *a != FORWARDED_MARKER)
+        .map(|a| decode_forwarded_arg(a))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::cli::Cli;
+
+    fn round_trip(arg: &str) -> String {
+        decode_forwarded_arg(&encode_forwarded_arg(OsStr::new(arg)).to_string_lossy())
+    }
+
+    #[test]
+    fn safe_arguments_are_left_alone() {
+        for arg in ["--monitors", "1-2,3", r"D:\SteamLibrary\wallpaper_engine", "--threshold=80", "C:/x@y~z"] {
+            assert_eq!(encode_forwarded_arg(OsStr::new(arg)), OsString::from(arg));
+        }
+    }
+
+    #[test]
+    fn unsafe_arguments_are_encoded_without_whitespace_or_quotes() {
+        let encoded = encode_forwarded_arg(OsStr::new(r#"C:\Program Files (x86)\Steam "x"	y"#));
+        assert_eq!(encoded, OsString::from(r"pct:C:\Program%20Files%20%28x86%29\Steam%20%22x%22%09y"));
+        assert_eq!(encode_forwarded_arg(OsStr::new("")), OsString::from("pct:"));
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        for arg in [
+            "", " ", "plain", r"C:\Program Files (x86)\Steam\steamapps\common\wallpaper_engine",
+            r#"say "hi""#, "tab\there", "100%", "%41", "ünïcødé", "pct:", "pct:already%20encoded", "pct:pct:",
+        ] {
+            assert_eq!(round_trip(arg), arg, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn arguments_starting_with_the_prefix_get_another_one() {
+        assert_eq!(encode_forwarded_arg(OsStr::new("pct:x")), OsString::from("pct:pct:x"));
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(decode_forwarded_arg("pct:%zz%4"), "%zz%4");
+        assert_eq!(decode_forwarded_arg("pct:%41%"), "A%");
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn user_command_lines_are_not_decoded() {
+        let argv = strings(&["wallpaper-controller.exe", "--wallpaper-engine-path", "pct:D:%20x"]);
+        assert_eq!(decode_forwarded_args(argv.clone()), argv);
+    }
+
+    #[test]
+    fn service_command_lines_are_decoded_and_lose_the_marker() {
+        let user_args = ["--wallpaper-engine-path", r"C:\Program Files\we", "--config", "pct:literal", "--trace-file", ""];
+        let mut argv = strings(&["wallpaper-controller.exe", FORWARDED_MARKER]);
+        argv.extend(user_args.iter().map(|a| encode_forwarded_arg(OsStr::new(a)).to_string_lossy().to_string()));
+        argv.push(String::from("-silent"));
+
+        let mut expected = strings(&["wallpaper-controller.exe"]);
+        expected.extend(strings(&user_args));
+        expected.push(String::from("-silent"));
+        assert_eq!(decode_forwarded_args(argv), expected);
+    }
+
+    #[test]
+    fn decoded_service_command_lines_parse_like_the_original() {
+        let path = r"D:\Steam Library\steamapps\common\wallpaper_engine (100%)";
+        for user_args in [
+            &["--wallpaper-engine-path", path, "-m", "1-2", "--64bit"][..],
+            &["-w", path, "--threshold", "30"],
+            &["-w", "pct:odd", "--config", r"C:\Users\me\wpc config.toml"],
+        ] {
+            let mut argv = strings(&["wallpaper-controller.exe", FORWARDED_MARKER]);
+            argv.extend(user_args.iter().map(|a| encode_forwarded_arg(OsStr::new(a)).to_string_lossy().to_string()));
+            let forwarded = Cli::try_parse_from(decode_forwarded_args(argv)).unwrap();
+            let typed = Cli::try_parse_from(std::iter::once("wallpaper-controller.exe").chain(user_args.iter().copied())).unwrap();
+            assert_eq!(format!("{:?}", forwarded), format!("{:?}", typed), "{:?}", user_args);
+            assert_eq!(forwarded.wallpaper_engine_path.as_deref(), Some(user_args[1]));
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::version::Version;
+
+/// Written next to the installed executable
+pub const MANIFEST_FILE_NAME: &str = "install-manifest.json";
+/// The executable that was replaced by the last upgrade/downgrade, kept for rolling back by hand
+pub const PREVIOUS_EXE_NAME: &str = "wallpaper-controller.previous.exe";
+
+/// What is installed in a directory, and what it replaced
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct InstallManifest {
+    pub version: String,
+    /// blake3 hash of the installed executable
+    pub hash: String,
+    /// Seconds since the Unix epoch
+    pub installed_at: u64,
+    /// Runtime arguments the service/task starts the controller with
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<PreviousInstall>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreviousInstall {
+    /// None if the replaced executable predates version metadata
+    pub version: Option<String>,
+    pub hash: String,
+    pub file: PathBuf,
+}
+
+impl InstallManifest {
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("install manifest is always serializable")
+    }
+}
+
+pub fn manifest_path(install_dir: &Path) -> PathBuf {
+    install_dir.join(MANIFEST_FILE_NAME)
+}
+
+/// Version of an installed executable: from the manifest when it describes this exact file, otherwise from
+/// the version marker embedded in the binary (absent in older builds)
+pub fn installed_version(manifest: Option<&str>, exe_hash: Option<&str>, exe_contents: &[u8]) -> Option<Version> {
+    let from_manifest = manifest
+        .and_then(|m| InstallManifest::parse(m).ok())
+        .filter(|m| exe_hash.is_some_and(|h| h == m.hash))
+        .and_then(|m| Version::parse(&m.version));
+    from_manifest.or_else(|| Version::embedded_in(exe_contents))
+}
@@ -1,10 +1,9 @@
-use std::env;
-use std::io::Read;
+use std::io::Write;
 use std::{fs, thread};
 use std::path::{Path, PathBuf};
 use std::ffi::{OsStr, OsString};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 use anyhow::{Result, bail};
 
@@ -17,21 +16,39 @@ use windows_service::{
     },
     service_manager::{ServiceManager, ServiceManagerAccess},
 };
-use windows_service::service::{Service, ServiceDependency, ServiceInfo};
+use windows_service::service::{Service, ServiceDependency, ServiceInfo, ServiceState};
 use std::process::Command;
 
 use crate::cli::Cli;
-
+use crate::error::InstallError;
+use crate::install::cmdline::decode_command_line;
+use crate::install::plan::{build_plan, PlanFormat, SystemProbe};
+use crate::install::transaction::{run_plan, transaction_dir, SystemRunner, JOURNAL_FILE_NAME};
+use crate::install::task_xml::{command_from_xml, encode_utf16_with_bom};
+
+pub mod answers;
+pub mod cmdline;
+pub mod forward;
+pub mod manifest;
+pub mod plan;
+pub mod reconfigure;
+pub mod task_xml;
+pub mod transaction;
 pub mod tui;
+pub mod uninstall;
+pub mod update;
+pub mod verify;
 
-const SERVICE_NAME: &str = "WallpaperControllerService";
+pub(crate) const SERVICE_NAME: &str = "WallpaperControllerService";
 const SERVICE_DISPLAY_NAME: &str = "Wallpaper Controller Service";
-const WALLPAPER_ENGINE_SERVICE_NAME: &str = "Wallpaper Engine Service";
-const WALLPAPER_SERVICE_32_PATH: &str = "C:\\WINDOWS\\SysWOW64\\wallpaperservice32.exe";
-const TASK_NAME: &str = "WallpaperControllerAtLogon";
+pub(crate) const WALLPAPER_ENGINE_SERVICE_NAME: &str = "Wallpaper Engine Service";
+pub(crate) const WALLPAPER_SERVICE_32_PATH: &str = "C:\\WINDOWS\\SysWOW64\\wallpaperservice32.exe";
+pub(crate) const TASK_NAME: &str = "WallpaperControllerAtLogon";
+/// File name of the installed copy, whatever the downloaded executable was called
+pub(crate) const INSTALLED_EXE_NAME: &str = "wallpaper-controller.exe";
 
 
-pub fn exit_blocking(code: i32) {
+pub fn exit_blocking(code: i32) -> ! {
     println!("Press Enter to exit...");
     let stdin_handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
     if stdin_handle.as_ref().is_ok_and(|h| !h.is_invalid()) {
@@ -53,231 +70,170 @@ pub fn exit_blocking(code: i32) {
     std::process::exit(code);
 }
 
-pub fn handle_installation(args: &Cli) {
-    let mut install_path = None;
-    if let Some(path_str) = &args.install_dir {
-        info!("Starting installation...");
-        match install_executable(path_str) {
-            Ok(path) => {
-                info!("Successfully installed to {}", path.display());
-                install_path = Some(path);
-            }
-            Err(e) => {
-                error!("Installation failed: {:}", e);
-                exit_blocking(1);
-            }
-        }
-    }
+/// Where the interactive installer suggests installing (`~/.wallpaper-controller`)
+pub fn default_install_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("C:\\Users\\Public"))
+        .join(".wallpaper-controller")
+}
 
-    if args.add_startup_service {
-        let exe_path = resolve_exe_path(install_path.clone());
-        let service_args = filtered_passthrough_args();
+/// Runs the non-interactive installation steps requested in `args`; `launch_args` are the runtime arguments
+/// the service/task starts the controller with
+pub fn handle_installation(args: &Cli, launch_args: &[OsString]) -> Result<(), InstallError> {
+    let plan = build_plan(args, launch_args, &SystemProbe)?;
 
-        match setup_startup_service(&exe_path, service_args) {
-            Ok(svc) => {
-                info!("Successfully set up the startup service.");
-                if let Err(e) = svc.start::<&str>(&[]) {
-                    error!("Failed to start the startup service: {}", e);
-                    exit_blocking(1);
-                } else {
-                    info!("Service started successfully.");
-                }
-            },
-            Err(e) => {
-                error!("Failed to set up startup service: {:?}", e);
-                println!("\n\n\t• Setup failed! Please try again.\n\tIf the issue persists please make sure to close all OS windows (including Task Manager, and Services) before retrying.\n");
-                exit_blocking(1);
-            }
+    if let Some(format) = args.install_dry_run {
+        match format {
+            PlanFormat::Text => print!("\nInstall plan (dry run, nothing was changed):\n{}", plan),
+            PlanFormat::Json => println!("{}", plan.to_json()),
         }
+        return Ok(());
     }
 
-    if args.add_startup_task {
-        let exe_path = resolve_exe_path(install_path);
-        let mut task_args = filtered_passthrough_args();
-        // Always add `-silent` for scheduled tasks
-        task_args.push(OsString::from("-silent"));
-
-        match setup_startup_scheduled_task(&exe_path, task_args) {
-            Ok(_) => {
-                info!("Successfully set up the startup scheduled task.");
-            },
-            Err(e) => {
-                error!("Failed to set up startup scheduled task: {:?}", e);
-                exit_blocking(1);
-            }
-        }
-    }
+    info!("Starting installation...");
+    let transaction_dir = transaction_dir().map_err(|e| InstallError::Journal(
+        anyhow::Error::new(e).context("Failed to create the install's temporary folder")
+    ))?;
+    let journal_path = transaction_dir.path().join(JOURNAL_FILE_NAME);
+
+    let mut runner = SystemRunner::new(transaction_dir.path().to_path_buf());
+    run_plan(&plan, &mut runner, Some(&journal_path))?;
 
+    if let Err(e) = transaction_dir.close() {
+        debug!("Could not remove the install's temporary folder: {}", e);
+    }
     info!("Operations completed successfully.");
-    exit_blocking(0);
+    Ok(())
 }
 
-fn install_executable(target: &str) -> Result<PathBuf> {
-    let current_exe = env::current_exe()?;
-    let input_path = PathBuf::from(target);
-
-    fn compute_file_hash(path: &Path) -> Result<blake3::Hash> {
-        let mut file = fs::File::open(path)?;
-        let mut hasher = blake3::Hasher::new();
-        let mut buf = [0u8; 8192];
-        loop {
-            let read = file.read(&mut buf)?;
-            if read == 0 { break; }
-            hasher.update(&buf[..read]);
-        }
-        Ok(hasher.finalize())
-    }
+fn print_service_hint() {
+    println!("\n\n\t• Setup failed! Please try again.\n\tIf the issue persists please make sure to close all OS windows (including Task Manager, and Services) before retrying.\n");
+}
 
-    // Ensure the target is a directory (existing or to be created). We do not accept file paths.
-    if fs::exists(&input_path)? {
-        let meta = fs::metadata(&input_path)?;
-        if meta.is_file() {
-            bail!("Install target '{}' is a file; expected a directory", input_path.display());
-        }
-        // It exists and is a directory
-        fs::create_dir_all(&input_path)?;
-    } else {
-        // If the user passed a path that looks like a file (e.g., ends with .exe), reject it
-        if input_path.extension().is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case("exe")) {
-            bail!("Install target '{}' appears to be a file path; please specify a directory", input_path.display());
-        }
-        fs::create_dir_all(&input_path)?;
-    }
+fn service_setup_failed(e: anyhow::Error) -> InstallError {
+    print_service_hint();
+    InstallError::ServiceSetup(e)
+}
 
-    // Construct the final target file path using the fixed executable name
-    let target_path = input_path.join("wallpaper-controller.exe");
-
-    // If target exists, compare hashes before copying
-    if fs::exists(&target_path)? {
-        match (compute_file_hash(&current_exe), compute_file_hash(&target_path)) {
-            (Ok(src_hash), Ok(dst_hash)) => {
-                if src_hash == dst_hash {
-                    info!("Same version already present at {} (hash {}).", target_path.display(), src_hash.to_hex());
-                    info!("Skipping copy.");
-                    return Ok(target_path);
-                } else {
-                    info!("Different contents detected at {}.", target_path.display());
-                    info!("Updating...");
-                }
-            }
-            (e1, e2) => {
-                warn!("Failed to compute hash for comparison (src: {:?}, dst: {:?}).", e1.err(), e2.err());
-                info!("Proceeding to replace file.");
-            }
-        }
+fn copy_executable(source: &Path, target: &Path) -> Result<()> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::exists(target)? {
         // Remove old file before copy
-        fs::remove_file(&target_path)?;
+        fs::remove_file(target)?;
     } else {
-        info!("Installing new copy to {}", target_path.display());
+        info!("Installing new copy to {}", target.display());
     }
-
-    fs::copy(&current_exe, &target_path)?;
-    Ok(target_path)
+    fs::copy(source, target)?;
+    Ok(())
 }
 
-fn setup_startup_service(exe_path: &Path, launch_args: Vec<OsString>) -> Result<Service> {
-    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all())?;
-
-    ensure_wallpaper_engine_service_present()?;
-
-    // If switching from scheduled task to service, remove the scheduled task first
-    info!("Setting up as a Windows Service.");
-    if let Err(e) = remove_existing_task_if_any() {
-        warn!("Failed while attempting to remove existing scheduled task '{}': {}", TASK_NAME, e);
-    }
-
-    remove_existing_service_if_any(&manager, SERVICE_NAME, Duration::from_secs(6))?;
-
-    let mut wallpaper_service_32_args: Vec<OsString> = vec!["-p".into(), exe_path.into()];
-    wallpaper_service_32_args.extend(launch_args);
-
-    debug!("Executable: {} | Launch args: {:?}", SERVICE_NAME, wallpaper_service_32_args);
-
-    let service_info = ServiceInfo {
-        name: SERVICE_NAME.into(),
+fn service_info(name: &str, executable: &Path, arguments: &[String]) -> ServiceInfo {
+    ServiceInfo {
+        name: name.into(),
         display_name: SERVICE_DISPLAY_NAME.into(),
         service_type: ServiceType::OWN_PROCESS,
         start_type: ServiceStartType::AutoStart,
         error_control: ServiceErrorControl::Normal,
-        executable_path: WALLPAPER_SERVICE_32_PATH.into(),
-        launch_arguments: wallpaper_service_32_args,
+        executable_path: executable.into(),
+        launch_arguments: arguments.iter().map(OsString::from).collect(),
         account_name: None,
         account_password: None,
         dependencies: vec![ServiceDependency::Service(WALLPAPER_ENGINE_SERVICE_NAME.into())],
-    };
+    }
+}
+
+fn create_service(name: &str, executable: &Path, arguments: &[String]) -> Result<Service> {
+    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all())?;
+    let service_info = service_info(name, executable, arguments);
 
     // Try to create the service; if it fails (likely because deletion hasn't finalized), wait and retry once.
-    match manager.create_service(&service_info, ServiceAccess::ALL_ACCESS) {
+    let service = match manager.create_service(&service_info, ServiceAccess::ALL_ACCESS) {
         Ok(service) => {
-            info!("Service '{}' created successfully.", SERVICE_NAME);
-            Ok(service)
+            info!("Service '{}' created successfully.", name);
+            service
         }
         Err(first_err) => {
-            warn!("First attempt to create service '{}' failed: {}", SERVICE_NAME, first_err);
+            warn!("First attempt to create service '{}' failed: {}", name, first_err);
             info!("Waiting ten more seconds before retrying service creation...");
             thread::sleep(Duration::from_secs(10));
             match manager.create_service(&service_info, ServiceAccess::ALL_ACCESS) {
                 Ok(service) => {
-                    info!("Service '{}' created successfully on retry.", SERVICE_NAME);
-                    Ok(service)
+                    info!("Service '{}' created successfully on retry.", name);
+                    service
                 }
                 Err(e) => {
-                    error!("Second attempt to create service '{}' failed: {}", SERVICE_NAME, e);
-                    Err(e.into())
+                    error!("Second attempt to create service '{}' failed: {}", name, e);
+                    return Err(e.into());
                 }
             }
         }
-    }
+    };
+
+    verify_service_command_line(&service, &service_info);
+    Ok(service)
 }
 
-fn quote_arg<S: AsRef<OsStr>>(s: S) -> OsString {
-    let s_ref = s.as_ref();
-    let s_str = s_ref.to_string_lossy();
-    if s_str.chars().any(|c| c.is_whitespace()) || s_str.contains(['"', '^', '&', '|', '>', '<']) {
-        let mut q = OsString::from("\"");
-        q.push(&*s_str.replace('"', "\\\""));
-        q.push("\"");
-        q
-    } else {
-        s_ref.to_owned()
+/// Changes the command line of an existing service in place and restarts it, which avoids the delete/create
+/// round trip (and its waits) when only the arguments change
+fn update_service_arguments(name: &str, executable: &Path, arguments: &[String]) -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        name,
+        ServiceAccess::CHANGE_CONFIG | ServiceAccess::QUERY_CONFIG | ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::START,
+    )?;
+    let service_info = service_info(name, executable, arguments);
+    service.change_config(&service_info)?;
+    verify_service_command_line(&service, &service_info);
+    info!("Service '{}' reconfigured.", name);
+
+    if service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped) {
+        info!("Restarting service '{}'...", name);
+        let _ = service.stop();
+        wait_for_service_stop(&service, Duration::from_secs(10));
     }
+    service.start::<&str>(&[])?;
+    Ok(())
 }
 
-fn setup_startup_scheduled_task(exe_path: &Path, launch_args: Vec<OsString>) -> Result<()> {
-    let username = std::env::var("USERNAME").unwrap_or_else(|_| String::from("%USERNAME%"));
-
-    // If switching from service to scheduled tasks, remove the service first
-    info!("Setting up as a Scheduled Task.");
-    info!("If a startup service installation exists, it will be removed.");
-    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all())?;
-    if let Err(e) = remove_existing_service_if_any(&manager, SERVICE_NAME, Duration::from_secs(6)) {
-        warn!("Failed while attempting to remove existing service '{}': {}", SERVICE_NAME, e);
-    }
+fn start_service(name: &str) -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(name, ServiceAccess::START)?;
+    service.start::<&str>(&[])
+}
 
-    fn build_command_line(exe_path: &Path, args: &[OsString]) -> OsString {
-        let mut full_cmd = OsString::new();
-        full_cmd.push(quote_arg(exe_path.as_os_str()));
-        for a in args {
-            full_cmd.push(" ");
-            full_cmd.push(quote_arg(a));
+// Reads the registered command line back and checks that it splits into exactly the argv we asked for
+fn verify_service_command_line(service: &Service, service_info: &ServiceInfo) {
+    let expected: Vec<String> = std::iter::once(service_info.executable_path.as_os_str())
+        .chain(service_info.launch_arguments.iter().map(|a| a.as_os_str()))
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+
+    match service.query_config() {
+        Ok(config) => {
+            let actual = decode_command_line(&config.executable_path.to_string_lossy());
+            if actual != expected {
+                warn!("Registered service command line decodes to {:?}, expected {:?}", actual, expected);
+            }
         }
-        full_cmd
+        Err(e) => warn!("Could not read back the service configuration: {}", e),
     }
+}
+
+fn register_task(xml: &str) -> Result<()> {
+    debug!("Task definition:\n{}", xml);
+    // schtasks only reads definitions from a file. It gets a fresh, randomly named one (created exclusively,
+    // so nobody can plant or swap it beforehand) that is deleted when `xml_file` is dropped.
+    let mut xml_file = tempfile::Builder::new().prefix(TASK_NAME).suffix(".xml").tempfile()?;
+    xml_file.write_all(&encode_utf16_with_bom(xml))?;
+    xml_file.flush()?;
 
     // Create or update the task
     let output = Command::new("schtasks")
-        .args([
-            "/Create", "/TN", TASK_NAME,
-            "/TR",
-        ])
-        .arg(build_command_line(exe_path, &launch_args))
-        .args([
-            "/SC", "ONLOGON",
-            "/RL", "HIGHEST",
-            "/RU", &username,
-            "/DELAY", "0001:15",
-            "/F",
-        ])
+        .args(["/Create", "/TN", TASK_NAME, "/XML"])
+        .arg(xml_file.path())
+        .arg("/F")
         .output()?;
 
     if !output.status.success() {
@@ -291,38 +247,55 @@ fn setup_startup_scheduled_task(exe_path: &Path, launch_args: Vec<OsString>) ->
     Ok(())
 }
 
-fn resolve_exe_path(install_path: Option<PathBuf>) -> PathBuf {
-    install_path.unwrap_or_else(|| env::current_exe().expect("Failed to get current exe path"))
-}
-
-fn filtered_passthrough_args() -> Vec<OsString> {
+/// Runtime arguments to forward to the service/task, i.e. `args` (without the program name) minus the
+/// installer's own options
+pub(crate) fn filtered_passthrough_args<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
     // List of parameters to skip when passing through to the service/task (second arg is whether it takes a value)
     let skip = [
         (name_of!(install_tui in Cli), false),
         (name_of!(install_dir in Cli), true),
+        (name_of!(install_from in Cli), true),
+        (name_of!(save_answers in Cli), true),
+        (name_of!(allow_downgrade in Cli), false),
+        (name_of!(install_dry_run in Cli), false),
+        (name_of!(reconfigure in Cli), false),
         (name_of!(add_startup_service in Cli), false),
         (name_of!(add_startup_task in Cli), false),
+        (name_of!(per_user in Cli), false),
+        (name_of!(task_delay in Cli), true),
+        (name_of!(task_triggers in Cli), true),
+        (name_of!(task_restart_count in Cli), true),
+        (name_of!(task_restart_interval in Cli), true),
     ];
 
     let cmd = Cli::command();
-    let mut skip_flags: Vec<(String, bool)> = Vec::with_capacity(skip.len());
+    // Third: values an option with an optional value (--install-dry-run [FORMAT]) may be followed by
+    let mut skip_flags: Vec<(String, bool, Vec<String>)> = Vec::with_capacity(skip.len());
     for (field_name, takes_value) in skip {
         if let Some(arg) = cmd.get_arguments().find(|a| a.get_id() == field_name) {
             if let Some(long) = arg.get_long() {
-                skip_flags.push((format!("--{}", long), takes_value));
+                let optional_value = arg.get_num_args().is_some_and(|n| n.min_values() == 0 && n.max_values() > 0);
+                let optional_values = if optional_value {
+                    arg.get_possible_values().iter().map(|v| v.get_name().to_string()).collect()
+                } else {
+                    Vec::new()
+                };
+                skip_flags.push((format!("--{}", long), takes_value, optional_values));
             }
         }
     }
 
     let mut out: Vec<OsString> = vec![];
-    let mut args_iter = std::env::args_os().skip(1);
+    let mut args_iter = args.into_iter().peekable();
     'outer: while let Some(arg) = args_iter.next() {
         let arg_str = arg.to_string_lossy();
-        for (flag, takes_value) in &skip_flags {
+        for (flag, takes_value, optional_values) in &skip_flags {
             let eq_prefix = format!("{}=", flag);
             if &arg_str == flag {
                 if *takes_value {
                     let _ = args_iter.next(); // skip value
+                } else {
+                    let _ = args_iter.next_if(|next| optional_values.iter().any(|v| next == v.as_str()));
                 }
                 continue 'outer;
             } else if arg_str.starts_with(&eq_prefix) {
@@ -334,24 +307,43 @@ fn filtered_passthrough_args() -> Vec<OsString> {
     out
 }
 
-fn ensure_wallpaper_engine_service_present() -> Result<()> {
-    if !fs::exists(&WALLPAPER_SERVICE_32_PATH)? {
-        error!("Running this application as a service requires `wallpaperservice32.exe` to have been installed as part of Wallpaper Engine.");
-        info!("You can try setting Wallpaper Engine to run as a service OR use a scheduled task to run this application on startup.");
-        bail!("wallpaperservice32.exe not found");
+/// Executable the startup service launches (the path after wallpaperservice32's `-p`)
+pub(crate) fn service_exe_path() -> Option<PathBuf> {
+    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT).ok()?;
+    let service = manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_CONFIG).ok()?;
+    let config = service.query_config().ok()?;
+    // wallpaperservice32.exe -p <controller exe> <args...>
+    let argv = decode_command_line(&config.executable_path.to_string_lossy());
+    argv.iter().position(|a| a == "-p").and_then(|i| argv.get(i + 1)).map(PathBuf::from)
+}
+
+/// Executable the startup task runs
+pub(crate) fn task_exe_path() -> Option<PathBuf> {
+    let output = Command::new("schtasks").args(["/Query", "/TN", TASK_NAME, "/XML"]).output().ok()?;
+    if !output.status.success() {
+        return None;
     }
-    Ok(())
+    command_from_xml(&String::from_utf8_lossy(&output.stdout)).map(PathBuf::from)
 }
 
-fn remove_existing_service_if_any(manager: &ServiceManager, name: &str, wait_after_delete: Duration) -> Result<()> {
+/// Polls until the service reports Stopped or `timeout` passes
+pub(crate) fn wait_for_service_stop(service: &Service, timeout: Duration) {
+    let started = Instant::now();
+    while started.elapsed() < timeout
+        && service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped)
+    {
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+fn remove_existing_service_if_any(manager: &ServiceManager, name: &str, wait_after_delete: Duration) -> Result<(), InstallError> {
     if let Ok(service) = manager.open_service(name, ServiceAccess::all()) {
         info!("Service '{}' already exists. Trying to delete it.", name);
         let _ = service.stop();
-        if let Err(e) = service.delete() {
+        if let Err(source) = service.delete() {
             error!("Failed to delete service '{}'.", name);
             error!("You might need to close Services and Task Manager windows and/or log out from or restart your computer to proceed");
-            error!("Error: {}", e);
-            exit_blocking(2);
+            return Err(InstallError::ServiceRemoval { name: name.to_string(), source });
         } else {
             info!("Service '{}' was marked for deletion successfully.", name);
             info!("Waiting several seconds before continuing...");
@@ -387,3 +379,30 @@ fn remove_existing_task_if_any() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(args: &[&str]) -> Vec<String> {
+        filtered_passthrough_args(args.iter().map(OsString::from))
+            .into_iter()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn installer_options_are_not_passed_through() {
+        assert_eq!(filtered(&[
+            "--install-dir", r"C:\Tools", "--threshold", "80", "--add-startup-task", "--task-delay=30",
+            "--task-trigger", "logon,unlock", "--monitors", "1-2", "--allow-downgrade",
+        ]), ["--threshold", "80", "--monitors", "1-2"]);
+    }
+
+    #[test]
+    fn dry_run_is_not_passed_through_with_or_without_a_format() {
+        assert_eq!(filtered(&["--install-dry-run", "--threshold", "80"]), ["--threshold", "80"]);
+        assert_eq!(filtered(&["--install-dry-run", "json", "--threshold", "80"]), ["--threshold", "80"]);
+        assert_eq!(filtered(&["--threshold", "80", "--install-dry-run=text"]), ["--threshold", "80"]);
+    }
+}
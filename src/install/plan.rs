@@ -0,0 +1,619 @@
+use std::cmp::Ordering;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use windows_service::service::ServiceAccess;
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::cli::Cli;
+use crate::error::InstallError;
+use crate::install::cmdline::{encode_args, encode_command_line};
+use crate::install::forward::{encode_forwarded_arg, FORWARDED_MARKER};
+use crate::install::manifest::{installed_version, manifest_path, InstallManifest, PreviousInstall, PREVIOUS_EXE_NAME};
+use crate::install::task_xml::{RestartPolicy, TaskDefinition};
+use crate::install::{default_install_dir, INSTALLED_EXE_NAME, SERVICE_NAME, TASK_NAME, WALLPAPER_SERVICE_32_PATH};
+use crate::version::Version;
+
+// Installation is split in two: `build_plan` inspects the system (through `InstallProbe`) and decides
+// what to do, and the installer executes the resulting steps. The plan can be printed (--install-dry-run),
+// shown in the TUI summary, or serialized to JSON.
+
+/// One operation the installer will perform, in execution order
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum InstallStep {
+    /// Copy the running executable into the install directory; a replaced executable is kept as `keep_previous`
+    CopyExecutable { source: PathBuf, target: PathBuf, change: VersionChange, keep_previous: Option<PathBuf> },
+    /// The installed copy already has the same blake3 hash as the running executable
+    SkipCopy { target: PathBuf, hash: String, version: String },
+    /// Record version, hash, install time and arguments next to the installed executable
+    WriteManifest { path: PathBuf, manifest: InstallManifest },
+    RemoveTask { name: String },
+    RemoveService { name: String },
+    /// Register a service running wallpaperservice32.exe, which in turn starts the controller
+    CreateService { name: String, executable: PathBuf, arguments: Vec<String>, command_line: String },
+    StartService { name: String },
+    /// Register (or overwrite) the scheduled task from a Task Scheduler XML document
+    CreateTask { name: String, command_line: String, replaces_existing: bool, elevated: bool, xml: String },
+}
+
+impl fmt::Display for InstallStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallStep::CopyExecutable { source, target, change, keep_previous } => {
+                write!(f, "{}: copy {} to {}", change, source.display(), target.display())?;
+                if let Some(previous) = keep_previous {
+                    write!(f, " (keeping the replaced version as {})", previous.display())?;
+                }
+                Ok(())
+            }
+            InstallStep::SkipCopy { target, hash, version } =>
+                write!(f, "Keep {} (same build of {}, blake3 {})", target.display(), version, &hash[..hash.len().min(16)]),
+            InstallStep::WriteManifest { path, manifest } =>
+                write!(f, "Write install manifest for {} to {}", manifest.version, path.display()),
+            InstallStep::RemoveTask { name } => write!(f, "Remove existing scheduled task '{}'", name),
+            InstallStep::RemoveService { name } => write!(f, "Stop and remove existing service '{}'", name),
+            InstallStep::CreateService { name, command_line, .. } =>
+                write!(f, "Create service '{}' running: {}", name, command_line),
+            InstallStep::StartService { name } => write!(f, "Start service '{}'", name),
+            InstallStep::CreateTask { name, command_line, replaces_existing, elevated, .. } =>
+                write!(f, "{} {} scheduled task '{}' running: {}",
+                       if *replaces_existing { "Replace" } else { "Create" },
+                       if *elevated { "elevated" } else { "non-elevated" },
+                       name, command_line),
+        }
+    }
+}
+
+/// How the executable being installed relates to the one already in the install directory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum VersionChange {
+    Fresh { version: String },
+    Upgrade { from: String, to: String },
+    Downgrade { from: String, to: String },
+    /// Same version number, different build
+    Reinstall { version: String },
+    /// The installed executable carries no version metadata
+    ReplaceUnknown { to: String },
+}
+
+impl fmt::Display for VersionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionChange::Fresh { version } => write!(f, "Installing {}", version),
+            VersionChange::Upgrade { from, to } => write!(f, "Upgrading {} → {}", from, to),
+            VersionChange::Downgrade { from, to } => write!(f, "Downgrading {} → {}", from, to),
+            VersionChange::Reinstall { version } => write!(f, "Reinstalling {} (different build)", version),
+            VersionChange::ReplaceUnknown { to } => write!(f, "Replacing an unversioned build with {}", to),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstallPlan {
+    pub steps: Vec<InstallStep>,
+}
+
+impl InstallPlan {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("install plan is always serializable")
+    }
+}
+
+impl fmt::Display for InstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return writeln!(f, "  (nothing to do)");
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(f, "  {}. {}", i + 1, step)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output format of --install-dry-run
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+/// Read-only view of the system state the plan depends on
+pub trait InstallProbe {
+    fn current_exe(&self) -> Result<PathBuf>;
+    fn is_file(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    fn file_hash(&self, path: &Path) -> Result<blake3::Hash>;
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>>;
+    /// Seconds since the Unix epoch
+    fn now(&self) -> u64;
+    fn service_exists(&self, name: &str) -> bool;
+    fn task_exists(&self, name: &str) -> bool;
+    /// Account the scheduled task runs as, e.g. `DOMAIN\user`
+    fn user_id(&self) -> String;
+}
+
+/// Probe backed by the real file system, service manager and Task Scheduler
+pub struct SystemProbe;
+
+impl InstallProbe for SystemProbe {
+    fn current_exe(&self) -> Result<PathBuf> {
+        Ok(env::current_exe()?)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn file_hash(&self, path: &Path) -> Result<blake3::Hash> {
+        compute_file_hash(path)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
+
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    fn service_exists(&self, name: &str) -> bool {
+        ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)
+            .and_then(|manager| manager.open_service(name, ServiceAccess::QUERY_STATUS))
+            .is_ok()
+    }
+
+    fn task_exists(&self, name: &str) -> bool {
+        Command::new("schtasks")
+            .args(["/Query", "/TN", name])
+            .output()
+            .is_ok_and(|out| out.status.success())
+    }
+
+    fn user_id(&self) -> String {
+        let username = env::var("USERNAME").unwrap_or_else(|_| String::from("%USERNAME%"));
+        match env::var("USERDOMAIN") {
+            Ok(domain) if !domain.is_empty() => format!("{}\\{}", domain, username),
+            _ => username,
+        }
+    }
+}
+
+pub fn compute_file_hash(path: &Path) -> Result<blake3::Hash> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 { break; }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Works out the steps for the installation requested in `args`. `launch_args` are the runtime arguments
+/// the installed service/task should start the controller with.
+pub fn build_plan(args: &Cli, launch_args: &[OsString], probe: &dyn InstallProbe) -> Result<InstallPlan, InstallError> {
+    let mut plan = InstallPlan::default();
+
+    let source = probe.current_exe().map_err(InstallError::Probe)?;
+    let mut exe_path = source.clone();
+    let mut manifest_step = None;
+
+    if args.per_user {
+        // Everything below must work without administrator rights
+        if args.add_startup_service {
+            return Err(InstallError::PerUser(String::from("a service can't be installed per user; use a scheduled task")));
+        }
+        if probe.service_exists(SERVICE_NAME) {
+            return Err(InstallError::PerUser(format!(
+                "service '{}' is installed; remove it with --uninstall (as administrator) first", SERVICE_NAME
+            )));
+        }
+    }
+
+    // Per-user installs always go into the user profile
+    let install_dir = args.install_dir.clone()
+        .or_else(|| args.per_user.then(|| default_install_dir().to_string_lossy().to_string()));
+    if let Some(dir) = &install_dir {
+        let target = install_target(dir, probe).map_err(|source| InstallError::Copy { dir: dir.clone(), source })?;
+        let (copy, manifest) = copy_steps(&source, &target, launch_args, probe);
+        if let InstallStep::CopyExecutable { change: VersionChange::Downgrade { from, to }, .. } = &copy {
+            if !args.allow_downgrade {
+                return Err(InstallError::Downgrade { installed: from.clone(), new: to.clone() });
+            }
+        }
+        plan.steps.push(copy);
+        manifest_step = Some(manifest);
+        exe_path = target;
+    }
+
+    if args.add_startup_service {
+        if !probe.is_file(Path::new(WALLPAPER_SERVICE_32_PATH)) {
+            return Err(InstallError::ServiceSetup(anyhow!(
+                "wallpaperservice32.exe not found. Running as a service requires Wallpaper Engine's service \
+                 (High Priority mode); enable it or use a scheduled task instead"
+            )));
+        }
+
+        // If switching from scheduled task to service, remove the scheduled task first
+        if probe.task_exists(TASK_NAME) {
+            plan.steps.push(InstallStep::RemoveTask { name: TASK_NAME.into() });
+        }
+        if probe.service_exists(SERVICE_NAME) {
+            plan.steps.push(InstallStep::RemoveService { name: SERVICE_NAME.into() });
+        }
+
+        let arguments = service_arguments(&exe_path, launch_args);
+        plan.steps.push(InstallStep::CreateService {
+            name: SERVICE_NAME.into(),
+            executable: PathBuf::from(WALLPAPER_SERVICE_32_PATH),
+            command_line: encode_command_line(OsStr::new(WALLPAPER_SERVICE_32_PATH), &arguments).to_string_lossy().to_string(),
+            arguments: arguments.iter().map(|a| a.to_string_lossy().to_string()).collect(),
+        });
+        plan.steps.push(InstallStep::StartService { name: SERVICE_NAME.into() });
+    }
+
+    if args.add_startup_task {
+        // If switching from service to scheduled task, remove the service first
+        if probe.service_exists(SERVICE_NAME) {
+            plan.steps.push(InstallStep::RemoveService { name: SERVICE_NAME.into() });
+        }
+
+        let mut task_args = launch_args.to_vec();
+        // Always add `-silent` for scheduled tasks
+        task_args.push(OsString::from("-silent"));
+        let task = task_definition(args, &exe_path, &task_args, probe.user_id());
+        plan.steps.push(InstallStep::CreateTask {
+            name: TASK_NAME.into(),
+            command_line: encode_command_line(exe_path.as_os_str(), &task_args).to_string_lossy().to_string(),
+            replaces_existing: probe.task_exists(TASK_NAME),
+            elevated: task.elevated,
+            xml: task.to_xml(),
+        });
+    }
+
+    // Last, so the manifest only claims an installation that fully succeeded
+    plan.steps.extend(manifest_step);
+    Ok(plan)
+}
+
+fn install_target(dir: &str, probe: &dyn InstallProbe) -> Result<PathBuf> {
+    let input_path = PathBuf::from(dir);
+    // Ensure the target is a directory (existing or to be created). We do not accept file paths.
+    if probe.is_file(&input_path) {
+        bail!("Install target '{}' is a file; expected a directory", input_path.display());
+    }
+    // If the user passed a path that looks like a file (e.g., ends with .exe), reject it
+    if !probe.is_dir(&input_path)
+        && input_path.extension().is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case("exe"))
+    {
+        bail!("Install target '{}' appears to be a file path; please specify a directory", input_path.display());
+    }
+    // Construct the final target file path using the fixed executable name
+    Ok(input_path.join(INSTALLED_EXE_NAME))
+}
+
+fn copy_steps(source: &Path, target: &Path, launch_args: &[OsString], probe: &dyn InstallProbe) -> (InstallStep, InstallStep) {
+    let new_version = Version::current();
+    let source_hash = probe.file_hash(source);
+    let manifest_path = manifest_path(target.parent().unwrap_or(target));
+    let existing_manifest = probe.read_file(&manifest_path).ok().map(|m| String::from_utf8_lossy(&m).into_owned());
+
+    let mut manifest = InstallManifest {
+        version: new_version.to_string(),
+        hash: source_hash.as_ref().map(|h| h.to_hex().to_string()).unwrap_or_default(),
+        installed_at: probe.now(),
+        args: launch_args.iter().map(|a| a.to_string_lossy().to_string()).collect(),
+        previous: None,
+    };
+
+    let copy = if !probe.is_file(target) {
+        InstallStep::CopyExecutable {
+            source: source.into(),
+            target: target.into(),
+            change: VersionChange::Fresh { version: new_version.to_string() },
+            keep_previous: None,
+        }
+    } else {
+        // If target exists, compare hashes before copying
+        let target_hash = probe.file_hash(target);
+        match (&source_hash, &target_hash) {
+            (Ok(src_hash), Ok(dst_hash)) if src_hash == dst_hash => {
+                // Nothing changes, so whatever the previous install replaced is still the previous version
+                manifest.previous = existing_manifest
+                    .as_deref()
+                    .and_then(|m| InstallManifest::parse(m).ok())
+                    .and_then(|m| m.previous);
+                InstallStep::SkipCopy {
+                    target: target.into(),
+                    hash: src_hash.to_hex().to_string(),
+                    version: new_version.to_string(),
+                }
+            }
+            _ => {
+                if let (Err(e), _) | (_, Err(e)) = (&source_hash, &target_hash) {
+                    warn!("Failed to compute hash for comparison ({}); proceeding to replace file.", e);
+                }
+                let target_hash = target_hash.ok().map(|h| h.to_hex().to_string());
+                let installed = installed_version(
+                    existing_manifest.as_deref(),
+                    target_hash.as_deref(),
+                    &probe.read_file(target).unwrap_or_default(),
+                );
+                let previous_file = target.with_file_name(PREVIOUS_EXE_NAME);
+                manifest.previous = Some(PreviousInstall {
+                    version: installed.as_ref().map(|v| v.to_string()),
+                    hash: target_hash.unwrap_or_default(),
+                    file: previous_file.clone(),
+                });
+                InstallStep::CopyExecutable {
+                    source: source.into(),
+                    target: target.into(),
+                    change: version_change(installed, &new_version),
+                    keep_previous: Some(previous_file),
+                }
+            }
+        }
+    };
+
+    (copy, InstallStep::WriteManifest { path: manifest_path, manifest })
+}
+
+fn version_change(installed: Option<Version>, new: &Version) -> VersionChange {
+    let to = new.to_string();
+    match installed {
+        None => VersionChange::ReplaceUnknown { to },
+        Some(from) => match from.cmp(new) {
+            Ordering::Less => VersionChange::Upgrade { from: from.to_string(), to },
+            Ordering::Greater => VersionChange::Downgrade { from: from.to_string(), to },
+            Ordering::Equal => VersionChange::Reinstall { version: to },
+        },
+    }
+}
+
+/// Arguments for wallpaperservice32.exe: `-p <controller exe>` followed by the runtime arguments, encoded
+/// to survive its re-join (see `forward`)
+pub(crate) fn service_arguments(exe_path: &Path, launch_args: &[OsString]) -> Vec<OsString> {
+    let mut arguments: Vec<OsString> = vec!["-p".into(), exe_path.into(), FORWARDED_MARKER.into()];
+    arguments.extend(launch_args.iter().map(|a| encode_forwarded_arg(a)));
+    arguments
+}
+
+pub(crate) fn task_definition(args: &Cli, exe_path: &Path, launch_args: &[OsString], user_id: String) -> TaskDefinition {
+    TaskDefinition {
+        uri: format!("\\{}", TASK_NAME),
+        description: String::from("Pauses Wallpaper Engine while the desktop is covered."),
+        user_id,
+        command: exe_path.to_string_lossy().to_string(),
+        arguments: encode_args(launch_args).to_string_lossy().to_string(),
+        triggers: args.task_triggers.clone(),
+        delay: Duration::from_secs(args.task_delay),
+        restart: (args.task_restart_count > 0).then(|| RestartPolicy {
+            count: args.task_restart_count,
+            interval: Duration::from_secs(args.task_restart_interval),
+        }),
+        elevated: !args.per_user,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use clap::Parser;
+    use serde_json::{json, Value};
+    use crate::version::CURRENT_VERSION;
+
+    const USER: &str = r"DESKTOP-1\ada";
+
+    #[derive(Default)]
+    struct FakeProbe {
+        files: HashMap<PathBuf, Vec<u8>>,
+        dirs: HashSet<PathBuf>,
+        services: HashSet<&'static str>,
+        tasks: HashSet<&'static str>,
+    }
+
+    impl FakeProbe {
+        fn new() -> Self {
+            let mut probe = Self::default();
+            probe.files.insert(source(), b"new build".to_vec());
+            probe
+        }
+
+        fn with_file(mut self, path: impl Into<PathBuf>, contents: &[u8]) -> Self {
+            self.files.insert(path.into(), contents.to_vec());
+            self
+        }
+    }
+
+    impl InstallProbe for FakeProbe {
+        fn current_exe(&self) -> Result<PathBuf> { Ok(source()) }
+        fn is_file(&self, path: &Path) -> bool { self.files.contains_key(path) }
+        fn is_dir(&self, path: &Path) -> bool { self.dirs.contains(path) }
+        fn file_hash(&self, path: &Path) -> Result<blake3::Hash> {
+            self.files.get(path).map(|c| blake3::hash(c)).ok_or_else(|| anyhow!("{} not found", path.display()))
+        }
+        fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+            self.files.get(path).cloned().ok_or_else(|| anyhow!("{} not found", path.display()))
+        }
+        fn now(&self) -> u64 { 1_700_000_000 }
+        fn service_exists(&self, name: &str) -> bool { self.services.contains(name) }
+        fn task_exists(&self, name: &str) -> bool { self.tasks.contains(name) }
+        fn user_id(&self) -> String { String::from(USER) }
+    }
+
+    fn source() -> PathBuf {
+        PathBuf::from("downloads").join("wallpaper-controller.exe")
+    }
+
+    fn install_dir() -> PathBuf {
+        PathBuf::from("programs").join("wpc")
+    }
+
+    fn target() -> PathBuf {
+        install_dir().join(INSTALLED_EXE_NAME)
+    }
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("wallpaper-controller").chain(args.iter().copied())).unwrap()
+    }
+
+    fn launch_args() -> Vec<OsString> {
+        vec![OsString::from("--threshold"), OsString::from("80"), OsString::from(r"C:\Program Files\we")]
+    }
+
+    fn plan_json(args: &[&str], probe: &FakeProbe) -> Value {
+        let plan = build_plan(&cli(args), &launch_args(), probe).unwrap();
+        serde_json::from_str(&plan.to_json()).unwrap()
+    }
+
+    fn actions(plan: &Value) -> Vec<&str> {
+        plan["steps"].as_array().unwrap().iter().map(|s| s["action"].as_str().unwrap()).collect()
+    }
+
+    fn step<'a>(plan: &'a Value, action: &str) -> &'a Value {
+        plan["steps"].as_array().unwrap().iter().find(|s| s["action"] == action).unwrap()
+    }
+
+    fn marked_binary(version: &str) -> Vec<u8> {
+        format!("MZ...wallpaper-controller-version={}\0...", version).into_bytes()
+    }
+
+    #[test]
+    fn service_install_replaces_an_existing_task() {
+        let mut probe = FakeProbe::new().with_file(WALLPAPER_SERVICE_32_PATH, b"");
+        probe.tasks.insert(TASK_NAME);
+        let dir = install_dir().to_string_lossy().to_string();
+        let plan = plan_json(&["--install-dir", &dir, "--add-startup-service"], &probe);
+
+        assert_eq!(actions(&plan), [
+            "copy-executable", "remove-task", "create-service", "start-service", "write-manifest",
+        ]);
+        assert_eq!(step(&plan, "copy-executable"), &json!({
+            "action": "copy-executable",
+            "source": source(),
+            "target": target(),
+            "change": { "kind": "fresh", "version": CURRENT_VERSION },
+            "keep_previous": null,
+        }));
+        assert_eq!(step(&plan, "create-service")["arguments"], json!([
+            "-p", target(), FORWARDED_MARKER, "--threshold", "80", r"pct:C:\Program%20Files\we",
+        ]));
+        assert_eq!(step(&plan, "write-manifest")["manifest"], json!({
+            "version": CURRENT_VERSION,
+            "hash": blake3::hash(b"new build").to_hex().to_string(),
+            "installed_at": 1_700_000_000,
+            "args": ["--threshold", "80", r"C:\Program Files\we"],
+        }));
+    }
+
+    #[test]
+    fn service_install_needs_the_wallpaper_engine_service() {
+        let result = build_plan(&cli(&["--add-startup-service"]), &launch_args(), &FakeProbe::new());
+        assert!(matches!(result, Err(InstallError::ServiceSetup(_))));
+    }
+
+    #[test]
+    fn task_install_replaces_an_existing_service() {
+        let mut probe = FakeProbe::new();
+        probe.services.insert(SERVICE_NAME);
+        let plan = plan_json(&["--add-startup-task", "--task-delay", "45"], &probe);
+
+        assert_eq!(actions(&plan), ["remove-service", "create-task"]);
+        let task = step(&plan, "create-task");
+        assert_eq!(task["replaces_existing"], json!(false));
+        assert_eq!(task["elevated"], json!(true));
+        let command_line = encode_command_line(source().as_os_str(), &[
+            "--threshold", "80", r"C:\Program Files\we", "-silent",
+        ]);
+        assert_eq!(task["command_line"], json!(command_line.to_string_lossy()));
+        let xml = task["xml"].as_str().unwrap();
+        assert!(xml.contains("<Delay>PT45S</Delay>") && xml.contains(&format!("<UserId>{}</UserId>", USER)));
+    }
+
+    #[test]
+    fn per_user_install_uses_the_profile_and_a_limited_task() {
+        let mut probe = FakeProbe::new();
+        probe.tasks.insert(TASK_NAME);
+        let plan = plan_json(&["--per-user", "--add-startup-task"], &probe);
+
+        assert_eq!(actions(&plan), ["copy-executable", "create-task", "write-manifest"]);
+        assert_eq!(step(&plan, "copy-executable")["target"], json!(default_install_dir().join(INSTALLED_EXE_NAME)));
+        let task = step(&plan, "create-task");
+        assert_eq!(task["replaces_existing"], json!(true));
+        assert_eq!(task["elevated"], json!(false));
+        assert!(task["xml"].as_str().unwrap().contains("<RunLevel>LeastPrivilege</RunLevel>"));
+    }
+
+    #[test]
+    fn per_user_install_refuses_services() {
+        let result = build_plan(&cli(&["--per-user", "--add-startup-service"]), &launch_args(), &FakeProbe::new());
+        assert!(matches!(result, Err(InstallError::PerUser(_))));
+
+        let mut probe = FakeProbe::new();
+        probe.services.insert(SERVICE_NAME);
+        let result = build_plan(&cli(&["--per-user", "--add-startup-task"]), &launch_args(), &probe);
+        assert!(matches!(result, Err(InstallError::PerUser(message)) if message.contains("--uninstall")));
+    }
+
+    #[test]
+    fn upgrade_keeps_the_previous_executable() {
+        let old = marked_binary("0.0.1");
+        let probe = FakeProbe::new().with_file(target(), &old);
+        let dir = install_dir().to_string_lossy().to_string();
+        let plan = plan_json(&["--install-dir", &dir], &probe);
+
+        assert_eq!(actions(&plan), ["copy-executable", "write-manifest"]);
+        let previous = install_dir().join(PREVIOUS_EXE_NAME);
+        assert_eq!(step(&plan, "copy-executable")["change"], json!({ "kind": "upgrade", "from": "0.0.1", "to": CURRENT_VERSION }));
+        assert_eq!(step(&plan, "copy-executable")["keep_previous"], json!(previous));
+        assert_eq!(step(&plan, "write-manifest")["manifest"]["previous"], json!({
+            "version": "0.0.1",
+            "hash": blake3::hash(&old).to_hex().to_string(),
+            "file": previous,
+        }));
+    }
+
+    #[test]
+    fn downgrade_needs_allow_downgrade() {
+        let probe = FakeProbe::new().with_file(target(), &marked_binary("99.0.0"));
+        let dir = install_dir().to_string_lossy().to_string();
+        let result = build_plan(&cli(&["--install-dir", &dir]), &launch_args(), &probe);
+        assert!(matches!(result, Err(InstallError::Downgrade { installed, .. }) if installed == "99.0.0"));
+
+        let plan = plan_json(&["--install-dir", &dir, "--allow-downgrade"], &probe);
+        assert_eq!(step(&plan, "copy-executable")["change"], json!({ "kind": "downgrade", "from": "99.0.0", "to": CURRENT_VERSION }));
+    }
+
+    #[test]
+    fn the_same_build_is_not_copied_again() {
+        let probe = FakeProbe::new().with_file(target(), b"new build");
+        let dir = install_dir().to_string_lossy().to_string();
+        let plan = plan_json(&["--install-dir", &dir], &probe);
+        assert_eq!(actions(&plan), ["skip-copy", "write-manifest"]);
+        assert_eq!(step(&plan, "skip-copy")["hash"], json!(blake3::hash(b"new build").to_hex().to_string()));
+    }
+
+    #[test]
+    fn an_executable_path_is_not_an_install_directory() {
+        let result = build_plan(&cli(&["--install-dir", "programs/wpc.exe"]), &launch_args(), &FakeProbe::new());
+        assert!(matches!(result, Err(InstallError::Copy { .. })));
+    }
+}
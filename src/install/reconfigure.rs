@@ -0,0 +1,398 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use tracing::{info, warn};
+use windows_service::service::ServiceAccess;
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::cli::{self, Cli};
+use crate::error::InstallError;
+use crate::install::answers::StartupMode;
+use crate::install::cmdline::decode_command_line;
+use crate::install::forward::{decode_forwarded_arg, FORWARDED_MARKER};
+use crate::install::manifest::{manifest_path, InstallManifest};
+use crate::install::plan::{build_plan, service_arguments, task_definition, InstallProbe, SystemProbe};
+use crate::install::task_xml::{arguments_from_xml, command_from_xml, settings_from_xml};
+use crate::install::tui::{prompt_settings, relaunch_args};
+use crate::install::{
+    filtered_passthrough_args, register_task, update_service_arguments, SERVICE_NAME, TASK_NAME,
+    WALLPAPER_SERVICE_32_PATH,
+};
+
+// Marker arguments the controller strips before parsing its command line (see main)
+const MARKER_ARGS: [&str; 3] = ["-safe", "-silent", "-service"];
+
+/// The startup service or task as currently registered
+#[derive(Clone)]
+pub struct InstalledConfig {
+    pub startup: StartupMode,
+    pub exe: PathBuf,
+    /// Runtime arguments, decoded and without installer options or marker arguments
+    pub args: Vec<OsString>,
+    /// The registered task document, for task installs
+    pub task_xml: Option<String>,
+}
+
+/// Reads the service command line, or failing that the scheduled task's
+pub fn read_installed_config() -> Option<InstalledConfig> {
+    read_service_config().or_else(read_task_config)
+}
+
+impl InstalledConfig {
+    /// Whether changing it takes administrator rights; a per-user task can be changed by its user
+    pub fn needs_elevation(&self) -> bool {
+        match &self.task_xml {
+            Some(xml) => settings_from_xml(xml).elevated,
+            None => true,
+        }
+    }
+}
+
+fn read_service_config() -> Option<InstalledConfig> {
+    let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT).ok()?;
+    let service = manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_CONFIG).ok()?;
+    let config = service.query_config().ok()?;
+    service_config(&config.executable_path.to_string_lossy())
+}
+
+/// The installed setup from the service's command line: wallpaperservice32.exe -p <controller exe> <args...>
+fn service_config(command_line: &str) -> Option<InstalledConfig> {
+    let argv = decode_command_line(command_line);
+    let exe_index = argv.iter().position(|a| a == "-p")? + 1;
+    Some(InstalledConfig {
+        startup: StartupMode::Service,
+        exe: PathBuf::from(argv.get(exe_index)?),
+        args: runtime_args(&service_runtime_args(&argv[exe_index + 1..])),
+        task_xml: None,
+    })
+}
+
+fn read_task_config() -> Option<InstalledConfig> {
+    let output = Command::new("schtasks").args(["/Query", "/TN", TASK_NAME, "/XML"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    task_config(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The installed setup from the registered task document
+fn task_config(xml: String) -> Option<InstalledConfig> {
+    let exe = command_from_xml(&xml)?;
+    // Split behind a quoted program name so the first argument gets argument (not program) quoting rules
+    let argv = decode_command_line(&format!("\"{}\" {}", exe, arguments_from_xml(&xml).unwrap_or_default()));
+    Some(InstalledConfig {
+        startup: StartupMode::Task,
+        exe: PathBuf::from(exe),
+        args: runtime_args(&argv[1..]),
+        task_xml: Some(xml),
+    })
+}
+
+// Every service argument was encoded when the service was registered (see forward), including on services
+// registered before the marker existed, so they are all decoded here
+fn service_runtime_args(argv: &[String]) -> Vec<String> {
+    argv.iter().filter(|a| *a != FORWARDED_MARKER).map(|a| decode_forwarded_arg(a)).collect()
+}
+
+fn runtime_args(argv: &[String]) -> Vec<OsString> {
+    filtered_passthrough_args(argv.iter().map(OsString::from))
+        .into_iter()
+        .filter(|a| !MARKER_ARGS.iter().any(|m| a == m))
+        .collect()
+}
+
+/// Parses installed runtime arguments the way the controller does when the service/task starts it
+pub fn decode_installed_cli(args: &[OsString]) -> Result<Cli, clap::Error> {
+    let argv: Vec<String> = std::iter::once(String::from("wallpaper-controller"))
+        .chain(args.iter().map(|a| a.to_string_lossy().to_string()))
+        .collect();
+    cli::parse(&argv).map(|invocation| invocation.cli)
+}
+
+/// The installed setup as a `Cli` the interactive installer can start from
+pub(crate) fn current_settings(installed: &InstalledConfig) -> Result<Cli, InstallError> {
+    let mut current = decode_installed_cli(&installed.args).map_err(|e| InstallError::Reconfigure(anyhow!(
+        "the installed command line no longer parses ({}); reinstall with --install-tui", e.kind()
+    )))?;
+    current.install_dir = installed.exe.parent().map(|dir| dir.to_string_lossy().to_string());
+    current.add_startup_service = installed.startup == StartupMode::Service;
+    current.add_startup_task = installed.startup == StartupMode::Task;
+    if let Some(xml) = &installed.task_xml {
+        let settings = settings_from_xml(xml);
+        current.per_user = !settings.elevated;
+        if !settings.triggers.is_empty() {
+            current.task_triggers = settings.triggers;
+        }
+        if let Some(delay) = settings.delay {
+            current.task_delay = delay.as_secs();
+        }
+        match settings.restart {
+            Some(restart) => {
+                current.task_restart_count = restart.count;
+                current.task_restart_interval = restart.interval.as_secs();
+            }
+            None => current.task_restart_count = 0,
+        }
+    }
+    Ok(current)
+}
+
+/// Human-readable list of the settings that differ
+fn describe_changes(old: &Cli, new: &Cli) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut compare = |name: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("{}: {} → {}", name, old, new));
+        }
+    };
+    let startup = |cli: &Cli| String::from(if cli.add_startup_service { "Windows Service" } else { "Scheduled Task" });
+    let we_path = |cli: &Cli| cli.wallpaper_engine_path.clone().unwrap_or_else(|| String::from("detect from Steam libraries"));
+    compare("Startup", startup(old), startup(new));
+    compare("Install dir", old.install_dir.clone().unwrap_or_default(), new.install_dir.clone().unwrap_or_default());
+    compare("Monitors", old.monitors.clone(), new.monitors.clone());
+    compare("Threshold", format!("{:?}", old.threshold), format!("{:?}", new.threshold));
+    compare("Per-monitor", old.per_monitor.to_string(), new.per_monitor.to_string());
+    compare("Update rate (ms)", old.update_rate.to_string(), new.update_rate.to_string());
+    compare("WE 64-bit", old.bit64.to_string(), new.bit64.to_string());
+    compare("WE path", we_path(old), we_path(new));
+    if new.add_startup_task {
+        let triggers = |cli: &Cli| cli.task_triggers.iter().map(|t| t.name()).collect::<Vec<_>>().join(" + ");
+        compare("Task triggers", triggers(old), triggers(new));
+        compare("Task delay (s)", old.task_delay.to_string(), new.task_delay.to_string());
+        compare("Task restarts", old.task_restart_count.to_string(), new.task_restart_count.to_string());
+        compare("Task restart interval (s)", old.task_restart_interval.to_string(), new.task_restart_interval.to_string());
+    }
+    changes
+}
+
+/// Opens the interactive installer pre-filled with the installed settings and applies only what changed:
+/// the service's command line is changed in place and the task is overwritten, instead of removing and
+/// recreating them
+pub fn handle_reconfigure(base: Cli) -> Result<(), InstallError> {
+    let installed = read_installed_config().ok_or(InstallError::NotInstalled)?;
+    let mut current = current_settings(&installed)?;
+    // Keep the diagnostics options of this invocation rather than resetting them to their defaults
+    current.disable_sentry = current.disable_sentry || base.disable_sentry;
+
+    let theme = ColorfulTheme::default();
+    println!("\nWallpaper Controller - Reconfigure\n");
+    println!("Installed at {} as a {}.\n", installed.exe.display(), match installed.startup {
+        StartupMode::Service => "Windows Service",
+        StartupMode::Task => "Scheduled Task",
+    });
+    let new = prompt_settings(&theme, current.clone()).map_err(InstallError::Reconfigure)?;
+
+    let changes = describe_changes(&current, &new);
+    if changes.is_empty() {
+        println!("\nNothing changed.");
+        return Ok(());
+    }
+    println!("\nChanges:");
+    for change in &changes {
+        println!("  • {}", change);
+    }
+
+    let moved = new.add_startup_service != current.add_startup_service || new.install_dir != current.install_dir;
+    if moved {
+        // A different startup mode or location needs the full install (copy, remove old, create new)
+        let plan = build_plan(&new, &filtered_passthrough_args(relaunch_args(&new)), &SystemProbe)?;
+        println!("\nThe startup mode or install directory changed, so the installer will:\n{}", plan);
+    }
+    let proceed = Confirm::with_theme(&theme)
+        .with_prompt("Apply these changes?")
+        .default(true)
+        .interact()
+        .map_err(|e| InstallError::Reconfigure(e.into()))?;
+    if !proceed {
+        println!("Nothing was changed.");
+        return Ok(());
+    }
+
+    if moved {
+        let exe = std::env::current_exe().map_err(|e| InstallError::Reconfigure(e.into()))?;
+        Command::new(exe).args(relaunch_args(&new)).spawn().map_err(|e| InstallError::Reconfigure(e.into()))?;
+        return Ok(());
+    }
+
+    apply_in_place(&installed, &new)?;
+    info!("Reconfiguration completed successfully.");
+    Ok(())
+}
+
+/// Re-registers the installed service/task with the runtime (and task) settings of `new`, keeping its
+/// startup mode and executable, and restarts it
+pub(crate) fn apply_in_place(installed: &InstalledConfig, new: &Cli) -> Result<(), InstallError> {
+    let launch_args = filtered_passthrough_args(relaunch_args(new));
+    match installed.startup {
+        StartupMode::Service => {
+            let arguments: Vec<String> = service_arguments(&installed.exe, &launch_args)
+                .iter()
+                .map(|a| a.to_string_lossy().to_string())
+                .collect();
+            update_service_arguments(SERVICE_NAME, Path::new(WALLPAPER_SERVICE_32_PATH), &arguments)
+                .map_err(InstallError::ServiceSetup)?;
+        }
+        StartupMode::Task => {
+            let mut task_args = launch_args.clone();
+            task_args.push(OsString::from("-silent"));
+            let task = task_definition(new, &installed.exe, &task_args, SystemProbe.user_id());
+            // /Create /F overwrites the task in place
+            register_task(&task.to_xml()).map_err(InstallError::TaskSetup)?;
+            restart_task().map_err(InstallError::TaskSetup)?;
+        }
+    }
+
+    if let Err(e) = update_manifest_args(&installed.exe, &launch_args) {
+        warn!("Could not update the install manifest: {:#}", e);
+    }
+    Ok(())
+}
+
+// The running controller keeps its old arguments until it is started again
+pub(crate) fn restart_task() -> Result<()> {
+    let _ = Command::new("schtasks").args(["/End", "/TN", TASK_NAME]).output();
+    std::thread::sleep(Duration::from_secs(1));
+    let output = Command::new("schtasks").args(["/Run", "/TN", TASK_NAME]).output()?;
+    if !output.status.success() {
+        bail!("schtasks /Run failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+fn update_manifest_args(exe: &Path, launch_args: &[OsString]) -> Result<()> {
+    let Some(dir) = exe.parent() else { return Ok(()) };
+    let path = manifest_path(dir);
+    let Ok(content) = fs::read_to_string(&path) else { return Ok(()) };
+    let mut manifest = InstallManifest::parse(&content)?;
+    manifest.args = launch_args.iter().map(|a| a.to_string_lossy().to_string()).collect();
+    fs::write(&path, manifest.to_json()).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::install::cmdline::encode_command_line;
+    use crate::install::INSTALLED_EXE_NAME;
+
+    const USER: &str = r"DESKTOP-1\ada";
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("wallpaper-controller").chain(args.iter().copied())).unwrap()
+    }
+
+    fn exe() -> PathBuf {
+        PathBuf::from(r"C:\Program Files (x86)\Wallpaper Controller").join(INSTALLED_EXE_NAME)
+    }
+
+    fn runtime_flags() -> Vec<&'static str> {
+        vec!["-m", "1,2", "-t", "70", "-p", "-u", "500", "-w", r"D:\Steam Library\wallpaper_engine (x64)", "--64bit"]
+    }
+
+    /// The service's command line as the installer registers it
+    fn registered_service(new: &Cli) -> String {
+        let launch_args = filtered_passthrough_args(relaunch_args(new));
+        encode_command_line(OsStr::new(WALLPAPER_SERVICE_32_PATH), &service_arguments(&exe(), &launch_args))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// The task document as the installer registers it
+    fn registered_task(new: &Cli) -> String {
+        let mut task_args = filtered_passthrough_args(relaunch_args(new));
+        task_args.push(OsString::from("-silent"));
+        task_definition(new, &exe(), &task_args, String::from(USER)).to_xml()
+    }
+
+    fn installed(args: &[&str]) -> Cli {
+        let mut new = cli(args);
+        new.install_dir = exe().parent().map(|dir| dir.to_string_lossy().to_string());
+        new
+    }
+
+    #[test]
+    fn service_settings_survive_the_round_trip() {
+        let mut args = vec!["--add-startup-service"];
+        args.extend(runtime_flags());
+        let new = installed(&args);
+
+        let config = service_config(&registered_service(&new)).unwrap();
+        assert_eq!(config.startup, StartupMode::Service);
+        assert_eq!(config.exe, exe());
+        assert!(config.needs_elevation());
+        let current = current_settings(&config).unwrap();
+        assert_eq!(describe_changes(&new, &current), Vec::<String>::new());
+        assert!(current.add_startup_service && !current.add_startup_task);
+    }
+
+    #[test]
+    fn task_settings_survive_the_round_trip() {
+        let mut args = vec![
+            "--add-startup-task", "--per-user", "--task-trigger", "logon,unlock", "--task-delay", "45",
+            "--task-restart-count", "5", "--task-restart-interval", "120",
+        ];
+        args.extend(runtime_flags());
+        let new = installed(&args);
+
+        let config = task_config(registered_task(&new)).unwrap();
+        assert_eq!(config.startup, StartupMode::Task);
+        assert_eq!(config.exe, exe());
+        assert!(!config.args.iter().any(|a| a == "-silent"));
+        assert!(!config.needs_elevation());
+        let current = current_settings(&config).unwrap();
+        assert_eq!(describe_changes(&new, &current), Vec::<String>::new());
+        assert!(current.per_user && current.add_startup_task);
+        assert_eq!(current.task_triggers, new.task_triggers);
+    }
+
+    #[test]
+    fn elevated_tasks_without_restarts_are_read_back_as_such() {
+        let new = installed(&["--add-startup-task", "--task-restart-count", "0"]);
+        let config = task_config(registered_task(&new)).unwrap();
+        assert!(config.needs_elevation());
+        let current = current_settings(&config).unwrap();
+        assert!(!current.per_user);
+        assert_eq!(current.task_restart_count, 0);
+    }
+
+    #[test]
+    fn command_lines_that_no_longer_parse_are_reported() {
+        let config = InstalledConfig {
+            startup: StartupMode::Service,
+            exe: exe(),
+            args: vec![OsString::from("--threshold"), OsString::from("lots")],
+            task_xml: None,
+        };
+        assert!(matches!(current_settings(&config), Err(InstallError::Reconfigure(_))));
+        assert!(service_config(r"C:\wallpaperservice32.exe --no-controller").is_none());
+    }
+
+    #[test]
+    fn only_changed_settings_are_described() {
+        let old = installed(&["--add-startup-service", "-m", "1", "-t", "70"]);
+        assert!(describe_changes(&old, &old).is_empty());
+
+        let mut new = old.clone();
+        new.threshold = Some(90);
+        new.monitors = String::from("all");
+        assert_eq!(describe_changes(&old, &new), [
+            String::from("Monitors: 1 → all"),
+            String::from("Threshold: Some(70) → Some(90)"),
+        ]);
+
+        // Task settings are only compared when the new setup is a task
+        let mut new = old.clone();
+        new.task_delay = 90;
+        assert!(describe_changes(&old, &new).is_empty());
+        new.add_startup_service = false;
+        new.add_startup_task = true;
+        assert_eq!(describe_changes(&old, &new), [
+            String::from("Startup: Windows Service → Scheduled Task"),
+            format!("Task delay (s): {} → 90", old.task_delay),
+        ]);
+    }
+}
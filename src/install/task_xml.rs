@@ -0,0 +1,377 @@
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// Task Scheduler XML (schema version 1.2, Windows 7+) generated from a typed definition and registered with
+// `schtasks /Create /XML`, since the plain schtasks flags can't express battery, restart or unlock settings.
+
+const TASK_NAMESPACE: &str = "http://schemas.microsoft.com/windows/2004/02/mit/task";
+/// Restart intervals Task Scheduler accepts, in seconds (1 minute to 31 days)
+pub const RESTART_INTERVAL_SECS: RangeInclusive<u64> = 60..=2678400;
+
+/// Events that start the scheduled task
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskTrigger {
+    /// When the user logs on
+    Logon,
+    /// When the user unlocks the workstation (restarts the controller if it isn't running)
+    Unlock,
+}
+
+impl TaskTrigger {
+    /// Name as accepted by `--task-trigger`
+    pub fn name(self) -> &'static str {
+        match self {
+            TaskTrigger::Logon => "logon",
+            TaskTrigger::Unlock => "unlock",
+        }
+    }
+}
+
+/// Restart the task when it exits with an error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartPolicy {
+    pub count: u16,
+    /// Within `RESTART_INTERVAL_SECS`
+    pub interval: Duration,
+}
+
+/// Everything that goes into the generated task; settings not listed here are fixed (see `to_xml`)
+#[derive(Clone, Debug)]
+pub struct TaskDefinition {
+    pub uri: String,
+    pub description: String,
+    /// Account the task runs as, e.g. `DOMAIN\user`
+    pub user_id: String,
+    pub command: String,
+    pub arguments: String,
+    pub triggers: Vec<TaskTrigger>,
+    /// Delay between the trigger firing and the controller starting
+    pub delay: Duration,
+    pub restart: Option<RestartPolicy>,
+    /// Run with the user's full (administrator) token; per-user installs run with the limited one
+    pub elevated: bool,
+}
+
+impl TaskDefinition {
+    /// Renders the task document. Fixed settings: runs only while the user is logged on (interactive token),
+    /// keeps running on battery, has no execution time limit and never starts
+    /// a second instance.
+    pub fn to_xml(&self) -> String {
+        let user = xml_escape(&self.user_id);
+        let delay = iso8601_duration(self.delay);
+
+        let mut xml = String::new();
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-16"?>"#);
+        let _ = writeln!(xml, r#"<Task version="1.2" xmlns="{}">"#, TASK_NAMESPACE);
+
+        let _ = writeln!(xml, "  <RegistrationInfo>");
+        let _ = writeln!(xml, "    <Description>{}</Description>", xml_escape(&self.description));
+        let _ = writeln!(xml, "    <URI>{}</URI>", xml_escape(&self.uri));
+        let _ = writeln!(xml, "  </RegistrationInfo>");
+
+        let _ = writeln!(xml, "  <Triggers>");
+        for trigger in &self.triggers {
+            match trigger {
+                TaskTrigger::Logon => {
+                    let _ = writeln!(xml, "    <LogonTrigger>");
+                    let _ = writeln!(xml, "      <Enabled>true</Enabled>");
+                    let _ = writeln!(xml, "      <UserId>{}</UserId>", user);
+                    let _ = writeln!(xml, "      <Delay>{}</Delay>", delay);
+                    let _ = writeln!(xml, "    </LogonTrigger>");
+                }
+                TaskTrigger::Unlock => {
+                    let _ = writeln!(xml, "    <SessionStateChangeTrigger>");
+                    let _ = writeln!(xml, "      <Enabled>true</Enabled>");
+                    let _ = writeln!(xml, "      <UserId>{}</UserId>", user);
+                    let _ = writeln!(xml, "      <Delay>{}</Delay>", delay);
+                    let _ = writeln!(xml, "      <StateChange>SessionUnlock</StateChange>");
+                    let _ = writeln!(xml, "    </SessionStateChangeTrigger>");
+                }
+            }
+        }
+        let _ = writeln!(xml, "  </Triggers>");
+
+        let _ = writeln!(xml, "  <Principals>");
+        let _ = writeln!(xml, r#"    <Principal id="Author">"#);
+        let _ = writeln!(xml, "      <UserId>{}</UserId>", user);
+        let _ = writeln!(xml, "      <LogonType>InteractiveToken</LogonType>");
+        let run_level = if self.elevated { "HighestAvailable" } else { "LeastPrivilege" };
+        let _ = writeln!(xml, "      <RunLevel>{}</RunLevel>", run_level);
+        let _ = writeln!(xml, "    </Principal>");
+        let _ = writeln!(xml, "  </Principals>");
+
+        let _ = writeln!(xml, "  <Settings>");
+        let _ = writeln!(xml, "    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>");
+        let _ = writeln!(xml, "    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>");
+        let _ = writeln!(xml, "    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>");
+        let _ = writeln!(xml, "    <AllowHardTerminate>true</AllowHardTerminate>");
+        let _ = writeln!(xml, "    <StartWhenAvailable>false</StartWhenAvailable>");
+        let _ = writeln!(xml, "    <RunOnlyIfNetworkAvailable>false</RunOnlyIfNetworkAvailable>");
+        let _ = writeln!(xml, "    <IdleSettings>");
+        let _ = writeln!(xml, "      <StopOnIdleEnd>false</StopOnIdleEnd>");
+        let _ = writeln!(xml, "      <RestartOnIdle>false</RestartOnIdle>");
+        let _ = writeln!(xml, "    </IdleSettings>");
+        let _ = writeln!(xml, "    <AllowStartOnDemand>true</AllowStartOnDemand>");
+        let _ = writeln!(xml, "    <Enabled>true</Enabled>");
+        let _ = writeln!(xml, "    <Hidden>false</Hidden>");
+        let _ = writeln!(xml, "    <RunOnlyIfIdle>false</RunOnlyIfIdle>");
+        let _ = writeln!(xml, "    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>");
+        let _ = writeln!(xml, "    <Priority>7</Priority>");
+        if let Some(restart) = &self.restart {
+            let _ = writeln!(xml, "    <RestartOnFailure>");
+            let _ = writeln!(xml, "      <Interval>{}</Interval>", iso8601_duration(restart.interval));
+            let _ = writeln!(xml, "      <Count>{}</Count>", restart.count);
+            let _ = writeln!(xml, "    </RestartOnFailure>");
+        }
+        let _ = writeln!(xml, "  </Settings>");
+
+        let _ = writeln!(xml, r#"  <Actions Context="Author">"#);
+        let _ = writeln!(xml, "    <Exec>");
+        let _ = writeln!(xml, "      <Command>{}</Command>", xml_escape(&self.command));
+        if !self.arguments.is_empty() {
+            let _ = writeln!(xml, "      <Arguments>{}</Arguments>", xml_escape(&self.arguments));
+        }
+        let _ = writeln!(xml, "    </Exec>");
+        let _ = writeln!(xml, "  </Actions>");
+        let _ = writeln!(xml, "</Task>");
+        xml
+    }
+}
+
+/// schtasks only reads the XML correctly as UTF-16 with a byte order mark, matching the declared encoding
+pub fn encode_utf16_with_bom(xml: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(xml.encode_utf16().flat_map(|u| u.to_le_bytes()));
+    bytes
+}
+
+/// Formats a duration the way Task Scheduler expects it, e.g. 75s -> `PT1M15S`
+pub fn iso8601_duration(duration: Duration) -> String {
+    let total = duration.as_secs();
+    if total == 0 {
+        return String::from("PT0S");
+    }
+
+    let (days, hours, minutes, seconds) = (total / 86400, total / 3600 % 24, total / 60 % 60, total % 60);
+    let mut out = String::from("P");
+    if days > 0 { let _ = write!(out, "{}D", days); }
+    if hours > 0 || minutes > 0 || seconds > 0 {
+        out.push('T');
+        if hours > 0 { let _ = write!(out, "{}H", hours); }
+        if minutes > 0 { let _ = write!(out, "{}M", minutes); }
+        if seconds > 0 { let _ = write!(out, "{}S", seconds); }
+    }
+    out
+}
+
+/// Executable of the first `Exec` action in a task document, e.g. from `schtasks /Query /XML`
+pub fn command_from_xml(xml: &str) -> Option<String> {
+    // Task Scheduler accepts (and the UI writes) the command wrapped in quotes
+    element_text(xml, "Command").map(|command| command.trim_matches('"').to_string())
+}
+
+/// Arguments of the first `Exec` action, as a single command-line string
+pub fn arguments_from_xml(xml: &str) -> Option<String> {
+    element_text(xml, "Arguments")
+}
+
+/// Trigger, delay and restart settings read back from a task document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskSettings {
+    pub triggers: Vec<TaskTrigger>,
+    /// Delay of the first trigger that has one
+    pub delay: Option<Duration>,
+    pub restart: Option<RestartPolicy>,
+    pub elevated: bool,
+}
+
+/// Reads back the settings `TaskDefinition::to_xml` writes; anything else in the document is ignored
+pub fn settings_from_xml(xml: &str) -> TaskSettings {
+    let mut triggers = Vec::new();
+    if xml.contains("<LogonTrigger") {
+        triggers.push(TaskTrigger::Logon);
+    }
+    if xml.contains("<SessionStateChangeTrigger") {
+        triggers.push(TaskTrigger::Unlock);
+    }
+    let restart = element_text(xml, "RestartOnFailure").and_then(|section| {
+        Some(RestartPolicy {
+            count: element_text(&section, "Count")?.parse().ok()?,
+            interval: parse_iso8601_duration(&element_text(&section, "Interval")?)?,
+        })
+    });
+    TaskSettings {
+        triggers,
+        delay: element_text(xml, "Delay").and_then(|d| parse_iso8601_duration(&d)),
+        restart,
+        elevated: element_text(xml, "RunLevel").is_none_or(|level| level != "LeastPrivilege"),
+    }
+}
+
+/// Parses the durations `iso8601_duration` produces (days, hours, minutes and whole seconds)
+pub fn parse_iso8601_duration(s: &str) -> Option<Duration> {
+    let rest = s.trim().strip_prefix('P')?;
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let mut total = 0u64;
+    for (part, units) in [(date, &[('D', 86400u64)][..]), (time, &[('H', 3600), ('M', 60), ('S', 1)][..])] {
+        let mut number = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let (_, factor) = units.iter().find(|(unit, _)| *unit == c)?;
+            total += number.parse::<u64>().ok()? * factor;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(Duration::from_secs(total))
+}
+
+// Unescaped, trimmed text of the first `<tag>...</tag>` element
+fn element_text(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml_unescape(xml[start..end].trim()))
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Expected documents live in tests/fixtures/task_xml; run with UPDATE_SNAPSHOTS=1 to rewrite them
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/task_xml").join(format!("{}.xml", name));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(actual, expected, "{} differs from the snapshot", name);
+    }
+
+    fn definition(triggers: &[TaskTrigger], delay: u64, restart: Option<RestartPolicy>, elevated: bool) -> TaskDefinition {
+        TaskDefinition {
+            uri: String::from(r"\WallpaperControllerAtLogon"),
+            description: String::from("Pauses Wallpaper Engine while the desktop is covered."),
+            user_id: String::from(r"DESKTOP-1\Ada & Bob"),
+            command: String::from(r"C:\Program Files\WallpaperController\wallpaper-controller.exe"),
+            arguments: String::from(r#"--wallpaper-engine-dir "D:\Steam <lib>\wallpaper_engine" -silent"#),
+            triggers: triggers.to_vec(),
+            delay: Duration::from_secs(delay),
+            restart,
+            elevated,
+        }
+    }
+
+    #[test]
+    fn task_documents_match_the_snapshots() {
+        let restart = RestartPolicy { count: 3, interval: Duration::from_secs(90) };
+        let trigger_sets: [(&str, &[TaskTrigger]); 3] = [
+            ("logon", &[TaskTrigger::Logon]),
+            ("unlock", &[TaskTrigger::Unlock]),
+            ("logon_unlock", &[TaskTrigger::Logon, TaskTrigger::Unlock]),
+        ];
+        for (triggers_name, triggers) in trigger_sets {
+            for (delay_name, delay) in [("no_delay", 0), ("delay", 30)] {
+                for (restart_name, restart) in [("no_restart", None), ("restart", Some(restart))] {
+                    let name = format!("{}_{}_{}", triggers_name, delay_name, restart_name);
+                    let task = definition(triggers, delay, restart, true);
+                    let xml = task.to_xml();
+                    assert_snapshot(&name, &xml);
+
+                    // What the reconfigure flow reads back is what was written
+                    let settings = settings_from_xml(&xml);
+                    assert_eq!(settings.triggers, triggers, "{}", name);
+                    assert_eq!(settings.delay, Some(Duration::from_secs(delay)), "{}", name);
+                    assert_eq!(settings.restart, restart, "{}", name);
+                    assert!(settings.elevated, "{}", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn per_user_tasks_run_with_the_limited_token_and_no_arguments_are_omitted() {
+        let mut task = definition(&[TaskTrigger::Logon], 0, None, false);
+        task.arguments.clear();
+        let xml = task.to_xml();
+        assert_snapshot("per_user_without_arguments", &xml);
+        assert!(!settings_from_xml(&xml).elevated);
+        assert_eq!(arguments_from_xml(&xml), None);
+    }
+
+    #[test]
+    fn command_and_arguments_survive_escaping() {
+        let task = definition(&[TaskTrigger::Logon], 0, None, true);
+        let xml = task.to_xml();
+        assert_eq!(command_from_xml(&xml), Some(task.command.clone()));
+        assert_eq!(arguments_from_xml(&xml), Some(task.arguments.clone()));
+        assert_eq!(command_from_xml("<Command>\"C:\\a b\\c.exe\"</Command>").as_deref(), Some(r"C:\a b\c.exe"));
+    }
+
+    #[test]
+    fn durations_are_formatted_for_task_scheduler() {
+        for (seconds, expected) in [
+            (0, "PT0S"),
+            (1, "PT1S"),
+            (59, "PT59S"),
+            (60, "PT1M"),
+            (75, "PT1M15S"),
+            (3600, "PT1H"),
+            (3661, "PT1H1M1S"),
+            (86400, "P1D"),
+            (90061, "P1DT1H1M1S"),
+            (2678400, "P31D"),
+        ] {
+            assert_eq!(iso8601_duration(Duration::from_secs(seconds)), expected);
+            assert_eq!(parse_iso8601_duration(expected), Some(Duration::from_secs(seconds)));
+        }
+        // Fractions of a second are dropped
+        assert_eq!(iso8601_duration(Duration::from_millis(1500)), "PT1S");
+    }
+
+    #[test]
+    fn malformed_durations_are_rejected() {
+        for bad in ["", "T1S", "P1", "PT1X", "P1H", "PTS", "PT-1S"] {
+            assert_eq!(parse_iso8601_duration(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn utf16_encoding_has_a_byte_order_mark() {
+        assert_eq!(encode_utf16_with_bom("<é"), [0xFF, 0xFE, b'<', 0, 0xE9, 0]);
+    }
+}
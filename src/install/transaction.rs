@@ -0,0 +1,441 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use windows_service::service::{ServiceAccess, ServiceState};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::error::InstallError;
+use crate::install::cmdline::decode_command_line;
+use crate::install::plan::{InstallPlan, InstallStep};
+use crate::install::{
+    copy_executable, create_service, register_task, remove_existing_service_if_any, remove_existing_task_if_any,
+    print_service_hint, service_setup_failed, start_service, INSTALLED_EXE_NAME,
+};
+
+// Installs run as a transaction: every completed step is recorded in a journal together with how to undo
+// it, and when a step fails the journal is unwound in reverse order so the previous setup (old executable,
+// service or task) is restored. The journal and backups live in a temporary folder that is removed once
+// the install (or rollback) has finished.
+
+const TRANSACTION_DIR_PREFIX: &str = "wallpaper-controller-install-";
+pub const JOURNAL_FILE_NAME: &str = "journal.json";
+
+/// How to revert a completed step
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "undo", rename_all = "kebab-case")]
+pub enum Undo {
+    Nothing,
+    /// Put the backed-up executable back, or delete the new copy if there was none
+    RestoreExecutable { target: PathBuf, backup: Option<PathBuf> },
+    /// Put back the previous install manifest, or delete the new one if there was none
+    RestoreManifest { path: PathBuf, previous: Option<String> },
+    /// Re-register a task that was removed or overwritten, from its exported XML
+    RestoreTask { name: String, xml: String },
+    DeleteTask { name: String },
+    /// Recreate a removed service with its previous command line
+    RestoreService { name: String, executable: PathBuf, arguments: Vec<String>, was_running: bool },
+    DeleteService { name: String },
+    StopService { name: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub step: InstallStep,
+    pub undo: Undo,
+}
+
+/// Steps completed so far, oldest first
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    pub completed: Vec<JournalEntry>,
+}
+
+/// Performs and reverts individual steps; `SystemRunner` does the real work, tests can substitute their own
+pub trait StepRunner {
+    fn apply(&mut self, step: &InstallStep) -> Result<Undo, InstallError>;
+    fn undo(&mut self, undo: &Undo) -> Result<()>;
+}
+
+/// Runs every step of `plan`, rolling back the completed ones if any step fails. The journal is mirrored
+/// to `journal_path` after each step when given.
+pub fn run_plan(plan: &InstallPlan, runner: &mut dyn StepRunner, journal_path: Option<&Path>) -> Result<(), InstallError> {
+    let mut journal = Journal::default();
+
+    for step in &plan.steps {
+        info!("{}", step);
+        match runner.apply(step) {
+            Ok(undo) => {
+                journal.completed.push(JournalEntry { step: step.clone(), undo });
+                if let Some(path) = journal_path {
+                    if let Err(e) = write_journal(path, &journal) {
+                        warn!("Failed to write install journal {}: {:#}", path.display(), e);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Step failed: {}", e);
+                let failed = rollback(&journal, runner);
+                if failed == 0 {
+                    info!("Rolled back {} completed step(s); the previous setup was restored.", journal.completed.len());
+                } else {
+                    error!("{} step(s) could not be rolled back; the previous setup may be incomplete.", failed);
+                    if let Some(path) = journal_path {
+                        error!("The install journal and backups were kept in {}", path.parent().unwrap_or(path).display());
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reverts the journal newest first and returns how many undo actions failed
+pub fn rollback(journal: &Journal, runner: &mut dyn StepRunner) -> usize {
+    let mut failed = 0;
+    for entry in journal.completed.iter().rev() {
+        if entry.undo == Undo::Nothing {
+            continue;
+        }
+        info!("Rolling back: {}", entry.step);
+        if let Err(e) = runner.undo(&entry.undo) {
+            error!("Rollback of '{}' failed: {:#}", entry.step, e);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+fn write_journal(path: &Path, journal: &Journal) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(journal)?)?;
+    Ok(())
+}
+
+/// Fresh, uniquely named folder holding the journal and backups of the install in progress; it is deleted
+/// when dropped
+pub fn transaction_dir() -> std::io::Result<tempfile::TempDir> {
+    tempfile::Builder::new().prefix(TRANSACTION_DIR_PREFIX).tempdir()
+}
+
+/// Executes steps against the real system, backing up whatever a step replaces into `backup_dir`
+pub struct SystemRunner {
+    backup_dir: PathBuf,
+}
+
+impl SystemRunner {
+    pub fn new(backup_dir: PathBuf) -> Self {
+        Self { backup_dir }
+    }
+}
+
+impl StepRunner for SystemRunner {
+    fn apply(&mut self, step: &InstallStep) -> Result<Undo, InstallError> {
+        match step {
+            InstallStep::CopyExecutable { source, target, change, keep_previous } => {
+                info!("{}", change);
+                let copy_error = |source| InstallError::Copy {
+                    dir: target.parent().unwrap_or(target).display().to_string(),
+                    source,
+                };
+                let backup = if target.is_file() {
+                    // The replaced version is kept next to the new one, which doubles as the rollback backup
+                    let backup = keep_previous.clone().unwrap_or_else(|| self.backup_dir.join(INSTALLED_EXE_NAME));
+                    fs::copy(target, &backup)
+                        .with_context(|| format!("Failed to back up {}", target.display()))
+                        .map_err(copy_error)?;
+                    Some(backup)
+                } else {
+                    None
+                };
+                copy_executable(source, target).map_err(copy_error)?;
+                info!("Successfully installed to {}", target.display());
+                Ok(Undo::RestoreExecutable { target: target.clone(), backup })
+            }
+            InstallStep::SkipCopy { target, .. } => {
+                info!("Same version already present at {}; skipping copy.", target.display());
+                Ok(Undo::Nothing)
+            }
+            InstallStep::WriteManifest { path, manifest } => {
+                let previous = fs::read_to_string(path).ok();
+                fs::write(path, manifest.to_json())
+                    .with_context(|| format!("Failed to write {}", path.display()))
+                    .map_err(|source| InstallError::Copy {
+                        dir: path.parent().unwrap_or(path).display().to_string(),
+                        source,
+                    })?;
+                Ok(Undo::RestoreManifest { path: path.clone(), previous })
+            }
+            InstallStep::RemoveTask { name } => {
+                let xml = export_task(name);
+                match remove_existing_task_if_any() {
+                    Ok(()) => Ok(match xml {
+                        Ok(xml) => Undo::RestoreTask { name: name.clone(), xml },
+                        Err(e) => {
+                            warn!("Could not export scheduled task '{}'; it can't be restored on failure: {:#}", name, e);
+                            Undo::Nothing
+                        }
+                    }),
+                    Err(e) => {
+                        warn!("Failed while attempting to remove existing scheduled task '{}': {}", name, e);
+                        Ok(Undo::Nothing)
+                    }
+                }
+            }
+            InstallStep::RemoveService { name } => {
+                let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all())
+                    .map_err(|e| service_setup_failed(e.into()))?;
+                let snapshot = snapshot_service(&manager, name);
+                remove_existing_service_if_any(&manager, name, Duration::from_secs(6)).inspect_err(|_| print_service_hint())?;
+                Ok(match snapshot {
+                    Ok(undo) => undo,
+                    Err(e) => {
+                        warn!("Could not read service '{}' before removal; it can't be restored on failure: {:#}", name, e);
+                        Undo::Nothing
+                    }
+                })
+            }
+            InstallStep::CreateService { name, executable, arguments, .. } => {
+                create_service(name, executable, arguments).map_err(service_setup_failed)?;
+                info!("Successfully set up the startup service.");
+                Ok(Undo::DeleteService { name: name.clone() })
+            }
+            InstallStep::StartService { name } => {
+                start_service(name).map_err(InstallError::ServiceStart)?;
+                info!("Service started successfully.");
+                Ok(Undo::StopService { name: name.clone() })
+            }
+            InstallStep::CreateTask { name, xml, replaces_existing, .. } => {
+                let undo = match replaces_existing.then(|| export_task(name)) {
+                    Some(Ok(previous)) => Undo::RestoreTask { name: name.clone(), xml: previous },
+                    Some(Err(e)) => {
+                        warn!("Could not export scheduled task '{}'; it can't be restored on failure: {:#}", name, e);
+                        Undo::DeleteTask { name: name.clone() }
+                    }
+                    None => Undo::DeleteTask { name: name.clone() },
+                };
+                register_task(xml).map_err(InstallError::TaskSetup)?;
+                info!("Successfully set up the startup scheduled task.");
+                Ok(undo)
+            }
+        }
+    }
+
+    fn undo(&mut self, undo: &Undo) -> Result<()> {
+        match undo {
+            Undo::Nothing => {}
+            Undo::RestoreExecutable { target, backup: Some(backup) } => {
+                fs::copy(backup, target).with_context(|| format!("Failed to restore {}", target.display()))?;
+            }
+            Undo::RestoreExecutable { target, backup: None } => {
+                fs::remove_file(target).with_context(|| format!("Failed to remove {}", target.display()))?;
+            }
+            Undo::RestoreManifest { path, previous: Some(previous) } => {
+                fs::write(path, previous).with_context(|| format!("Failed to restore {}", path.display()))?;
+            }
+            Undo::RestoreManifest { path, previous: None } => {
+                fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            Undo::RestoreTask { xml, .. } => register_task(xml)?,
+            Undo::DeleteTask { name } => {
+                let output = Command::new("schtasks").args(["/Delete", "/TN", name, "/F"]).output()?;
+                if !output.status.success() {
+                    bail!("schtasks /Delete failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+                }
+            }
+            Undo::RestoreService { name, executable, arguments, was_running } => {
+                create_service(name, executable, arguments)?;
+                if *was_running {
+                    start_service(name)?;
+                }
+            }
+            Undo::DeleteService { name } => {
+                let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all())?;
+                remove_existing_service_if_any(&manager, name, Duration::from_secs(6))?;
+            }
+            Undo::StopService { name } => {
+                let manager = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)?;
+                manager.open_service(name, ServiceAccess::STOP)?.stop()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn export_task(name: &str) -> Result<String> {
+    let output = Command::new("schtasks").args(["/Query", "/TN", name, "/XML"]).output()?;
+    if !output.status.success() {
+        bail!("schtasks /Query failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn snapshot_service(manager: &ServiceManager, name: &str) -> Result<Undo> {
+    let service = manager.open_service(name, ServiceAccess::QUERY_CONFIG | ServiceAccess::QUERY_STATUS)?;
+    let config = service.query_config()?;
+    let was_running = service.query_status().is_ok_and(|s| s.current_state == ServiceState::Running);
+
+    let mut argv = decode_command_line(&config.executable_path.to_string_lossy()).into_iter();
+    let executable = argv.next().ok_or_else(|| anyhow!("service '{}' has an empty command line", name))?;
+    Ok(Undo::RestoreService {
+        name: name.to_string(),
+        executable: PathBuf::from(executable),
+        arguments: argv.collect(),
+        was_running,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::install::TASK_NAME;
+
+    /// Applies steps until step `fail_at` (1-based), recording what it was asked to do
+    struct FakeRunner {
+        fail_at: Option<usize>,
+        failing_undo: Option<String>,
+        applied: Vec<String>,
+        undone: Vec<Undo>,
+    }
+
+    impl FakeRunner {
+        fn new(fail_at: Option<usize>) -> Self {
+            Self { fail_at, failing_undo: None, applied: Vec::new(), undone: Vec::new() }
+        }
+    }
+
+    impl StepRunner for FakeRunner {
+        fn apply(&mut self, step: &InstallStep) -> Result<Undo, InstallError> {
+            if self.fail_at == Some(self.applied.len() + 1) {
+                return Err(InstallError::TaskSetup(anyhow!("step {} failed", self.applied.len() + 1)));
+            }
+            self.applied.push(step.to_string());
+            Ok(match step {
+                InstallStep::SkipCopy { .. } => Undo::Nothing,
+                InstallStep::RemoveService { name } | InstallStep::CreateService { name, .. } => {
+                    Undo::DeleteService { name: name.clone() }
+                }
+                InstallStep::StartService { name } => Undo::StopService { name: name.clone() },
+                InstallStep::RemoveTask { name } | InstallStep::CreateTask { name, .. } => {
+                    Undo::DeleteTask { name: name.clone() }
+                }
+                InstallStep::CopyExecutable { target, .. } => Undo::RestoreExecutable { target: target.clone(), backup: None },
+                InstallStep::WriteManifest { path, .. } => Undo::RestoreManifest { path: path.clone(), previous: None },
+            })
+        }
+
+        fn undo(&mut self, undo: &Undo) -> Result<()> {
+            self.undone.push(undo.clone());
+            match (&self.failing_undo, undo) {
+                (Some(failing), Undo::DeleteService { name }) if failing == name => bail!("could not delete {}", name),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn plan() -> InstallPlan {
+        InstallPlan {
+            steps: vec![
+                InstallStep::SkipCopy { target: PathBuf::from("wpc.exe"), hash: String::from("00"), version: String::from("1.0.0") },
+                InstallStep::RemoveTask { name: TASK_NAME.into() },
+                InstallStep::CreateService {
+                    name: String::from("svc"),
+                    executable: PathBuf::from("wallpaperservice32.exe"),
+                    arguments: Vec::new(),
+                    command_line: String::from("wallpaperservice32.exe"),
+                },
+                InstallStep::StartService { name: String::from("svc") },
+            ],
+        }
+    }
+
+    fn read_journal(path: &Path) -> Journal {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_successful_run_journals_every_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join(JOURNAL_FILE_NAME);
+        let mut runner = FakeRunner::new(None);
+        run_plan(&plan(), &mut runner, Some(&journal_path)).unwrap();
+
+        assert_eq!(runner.applied.len(), 4);
+        assert!(runner.undone.is_empty());
+        let journal = read_journal(&journal_path);
+        let steps: Vec<InstallStep> = journal.completed.into_iter().map(|e| e.step).collect();
+        assert_eq!(steps, plan().steps);
+    }
+
+    #[test]
+    fn a_failing_step_undoes_the_completed_ones_newest_first() {
+        for fail_at in 1..=4 {
+            let dir = tempfile::tempdir().unwrap();
+            let journal_path = dir.path().join(JOURNAL_FILE_NAME);
+            let mut runner = FakeRunner::new(Some(fail_at));
+            let result = run_plan(&plan(), &mut runner, Some(&journal_path));
+            assert!(matches!(result, Err(InstallError::TaskSetup(_))), "step {}", fail_at);
+
+            // Steps 1..N-1 ran; step N never did
+            let expected_applied: Vec<String> = plan().steps[..fail_at - 1].iter().map(|s| s.to_string()).collect();
+            assert_eq!(runner.applied, expected_applied, "step {}", fail_at);
+
+            // Undone newest first, skipping steps that changed nothing (the skipped copy)
+            let expected_undone: Vec<Undo> = [
+                Undo::Nothing,
+                Undo::DeleteTask { name: TASK_NAME.into() },
+                Undo::DeleteService { name: String::from("svc") },
+                Undo::StopService { name: String::from("svc") },
+            ][..fail_at - 1]
+                .iter()
+                .rev()
+                .filter(|u| **u != Undo::Nothing)
+                .cloned()
+                .collect();
+            assert_eq!(runner.undone, expected_undone, "step {}", fail_at);
+
+            // The journal lists exactly the completed steps, in order, with their undo actions
+            if fail_at == 1 {
+                assert!(!journal_path.exists());
+            } else {
+                let journal = read_journal(&journal_path);
+                let steps: Vec<InstallStep> = journal.completed.iter().map(|e| e.step.clone()).collect();
+                assert_eq!(steps, plan().steps[..fail_at - 1], "step {}", fail_at);
+                let undos: Vec<Undo> = journal.completed.into_iter().rev().map(|e| e.undo).filter(|u| *u != Undo::Nothing).collect();
+                assert_eq!(undos, expected_undone, "step {}", fail_at);
+            }
+        }
+    }
+
+    #[test]
+    fn rollback_continues_past_a_failing_undo_and_counts_it() {
+        let mut runner = FakeRunner::new(None);
+        runner.failing_undo = Some(String::from("svc"));
+        let journal = Journal {
+            completed: plan().steps.iter().map(|step| JournalEntry { step: step.clone(), undo: runner.apply(step).unwrap() }).collect(),
+        };
+        assert_eq!(rollback(&journal, &mut runner), 1);
+        assert_eq!(runner.undone, [
+            Undo::StopService { name: String::from("svc") },
+            Undo::DeleteService { name: String::from("svc") },
+            Undo::DeleteTask { name: TASK_NAME.into() },
+        ]);
+    }
+
+    #[test]
+    fn each_install_gets_its_own_transaction_dir() {
+        let first = transaction_dir().unwrap();
+        let second = transaction_dir().unwrap();
+        assert_ne!(first.path(), second.path());
+        fs::write(first.path().join(JOURNAL_FILE_NAME), "{}").unwrap();
+
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+        assert!(second.path().exists());
+    }
+}
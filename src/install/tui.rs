@@ -1,14 +1,24 @@
-use std::path::{Path, PathBuf};
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use std::ffi::OsString;
+use std::path::Path;
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
 use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
 use windows_service::service::ServiceAccess;
 use anyhow::{anyhow, Result};
-use tracing::error;
+use tracing::{error, warn};
 use crate::cli::Cli;
-use crate::install::WALLPAPER_ENGINE_SERVICE_NAME;
-
-fn wallpaper_engine_service_exists() -> bool {
-    match ServiceManager::local_computer(None::<&std::ffi::OsStr>, ServiceManagerAccess::all()) {
+use crate::install::answers::Answers;
+use crate::install::filtered_passthrough_args;
+use crate::install::plan::{build_plan, SystemProbe};
+use crate::install::task_xml::{TaskTrigger, RESTART_INTERVAL_SECS};
+use crate::install::{default_install_dir, WALLPAPER_ENGINE_SERVICE_NAME};
+use crate::paths;
+use crate::selector::MonitorSelector;
+use crate::steam::{
+    find_wallpaper_engine_installs, validate_wallpaper_engine_dir, WallpaperEngineInstall, DEFAULT_WALLPAPER_ENGINE_DIR,
+};
+
+pub(crate) fn wallpaper_engine_service_exists() -> bool {
+    match ServiceManager::local_computer(None::<&std::ffi::OsStr>, ServiceManagerAccess::CONNECT) {
         Ok(manager) => manager
             .open_service(WALLPAPER_ENGINE_SERVICE_NAME, ServiceAccess::QUERY_STATUS)
             .is_ok(),
@@ -19,7 +29,7 @@ fn wallpaper_engine_service_exists() -> bool {
     }
 }
 
-fn validate_install_dir(s: &str) -> std::result::Result<(), String> {
+pub(crate) fn validate_install_dir(s: &str) -> std::result::Result<(), String> {
     fn looks_like_file_path(p: &str) -> bool {
         Path::new(p)
             .extension()
@@ -35,7 +45,7 @@ fn validate_install_dir(s: &str) -> std::result::Result<(), String> {
     Ok(())
 }
 
-fn validate_threshold(s: &str) -> std::result::Result<(), String> {
+pub(crate) fn validate_threshold(s: &str) -> std::result::Result<(), String> {
     if s.trim().is_empty() { return Err("Please enter a number between 0 and 100".into()); }
     match s.trim().parse::<u8>() {
         Ok(v) if v <= 100 => Ok(()),
@@ -43,60 +53,91 @@ fn validate_threshold(s: &str) -> std::result::Result<(), String> {
     }
 }
 
-fn validate_monitors(s: &str) -> std::result::Result<(), String> {
-    let t = s.trim().to_lowercase();
-    if t == "all" { return Ok(()); }
-    if t.is_empty() { return Err("Enter 'all' or a list like 1,2,3".into()); }
-    for part in t.split(',') {
-        let p = part.trim();
-        if p.is_empty() { return Err("Invalid list: contains empty entries".into()); }
-        match p.parse::<u32>() {
-            Ok(n) if n >= 1 => (),
-            _ => return Err(format!("'{}' is not a valid monitor number (must be >= 1)", p)),
-        }
-    }
-    Ok(())
+pub(crate) fn validate_monitors(s: &str) -> std::result::Result<(), String> {
+    s.parse::<MonitorSelector>().map(|_| ())
 }
 
-fn validate_update_rate(s: &str) -> std::result::Result<(), String> {
+pub(crate) fn validate_update_rate(s: &str) -> std::result::Result<(), String> {
     match s.trim().parse::<u64>() {
         Ok(ms) if (100..=60000).contains(&ms) => Ok(()),
         _ => Err("Enter an integer between 100 and 60000 ms".into()),
     }
 }
 
-// fn validate_we_path(s: &str, require_64: bool) -> std::result::Result<(), String> {
-//     let p = Path::new(s.trim());
-//     if !p.exists() || !p.is_dir() { return Err("Path must exist and be a folder".into()); }
-//     let ok = if require_64 {
-//         p.join("wallpaper64.exe").exists()
-//     } else {
-//         p.join("wallpaper32.exe").exists() || p.join("wallpaper64.exe").exists()
-//     };
-//     if !ok {
-//         return Err(if require_64 {
-//             "Could not find wallpaper64.exe in this folder".into()
-//         } else {
-//             "Could not find wallpaper32.exe or wallpaper64.exe in this folder".into()
-//         });
-//     }
-//     Ok(())
-// }
+pub(crate) fn validate_task_delay(s: &str) -> std::result::Result<(), String> {
+    match s.trim().parse::<u64>() {
+        Ok(secs) if secs <= 86400 => Ok(()),
+        _ => Err("Enter an integer between 0 and 86400 seconds".into()),
+    }
+}
+
+pub(crate) fn validate_restart_count(s: &str) -> std::result::Result<(), String> {
+    match s.trim().parse::<u16>() {
+        Ok(n) if n <= 999 => Ok(()),
+        _ => Err("Enter an integer between 0 and 999".into()),
+    }
+}
+
+pub(crate) fn validate_restart_interval(s: &str) -> std::result::Result<(), String> {
+    match s.trim().parse::<u64>() {
+        Ok(secs) if RESTART_INTERVAL_SECS.contains(&secs) => Ok(()),
+        _ => Err(format!(
+            "Enter an integer between {} and {} seconds", RESTART_INTERVAL_SECS.start(), RESTART_INTERVAL_SECS.end()
+        )),
+    }
+}
+
+pub(crate) fn validate_we_path(s: &str, prefer_64bit: bool) -> std::result::Result<(), String> {
+    let dir = Path::new(s.trim());
+    // Same executable choice as at runtime: the 64-bit one is fine if it's the only one installed
+    let use_64bit = WallpaperEngineInstall::probe(dir.to_path_buf()).use_64bit(prefer_64bit);
+    validate_wallpaper_engine_dir(dir, use_64bit).map_err(|e| e.to_string())
+}
 
 pub fn run_install_tui_and_relaunch(base: Cli) -> Result<()> {
     // Run the wizard to collect all settings
     let new_cli = run_install_tui(base)?;
 
-    // Build argv and relaunch current executable
+    if let Some(path) = &new_cli.save_answers {
+        let answers = Answers::from_cli(&new_cli).ok_or_else(|| anyhow!("The wizard did not choose a startup mode"))?;
+        answers.save(Path::new(path))?;
+        // Listed so --uninstall can remove the file again
+        if let Some(list) = paths::current().saved_answers_list() {
+            if let Err(e) = Answers::record_saved(&list, Path::new(path)) {
+                warn!("Could not record the answers file for --uninstall: {:#}", e);
+            }
+        }
+        println!("\nSaved your answers to {}; install elsewhere with --install-from {}", path, path);
+    }
+
+    // Relaunch current executable with the collected settings
     let exe = std::env::current_exe()?;
-    let mut args: Vec<std::ffi::OsString> = Vec::new();
+    std::process::Command::new(exe).args(relaunch_args(&new_cli)).spawn()?;
 
+    Ok(())
+}
+
+/// Command line that performs the installation configured in the wizard
+pub(crate) fn relaunch_args(new_cli: &Cli) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
     if let Some(dir) = &new_cli.install_dir {
         args.push("--install-dir".into());
         args.push(dir.clone().into());
     }
     if new_cli.add_startup_service { args.push("--add-startup-service".into()); }
-    if new_cli.add_startup_task { args.push("--add-startup-task".into()); }
+    if new_cli.per_user { args.push("--per-user".into()); }
+    if new_cli.add_startup_task {
+        args.push("--add-startup-task".into());
+        args.push("--task-delay".into());
+        args.push(new_cli.task_delay.to_string().into());
+        let triggers: Vec<&str> = new_cli.task_triggers.iter().map(|t| t.name()).collect();
+        args.push("--task-trigger".into());
+        args.push(triggers.join(",").into());
+        args.push("--task-restart-count".into());
+        args.push(new_cli.task_restart_count.to_string().into());
+        args.push("--task-restart-interval".into());
+        args.push(new_cli.task_restart_interval.to_string().into());
+    }
 
     // runtime flags
     args.push("-m".into());
@@ -108,142 +149,260 @@ pub fn run_install_tui_and_relaunch(base: Cli) -> Result<()> {
     args.push("-u".into());
     args.push(new_cli.update_rate.to_string().into());
 
-    // TODO: This won't work with wallpaperservice32.exe
-    // if !new_cli.wallpaper_engine_path.is_empty() {
-    //     args.push("-w".into());
-    //     args.push(format!("'{}'", new_cli.wallpaper_engine_path).into());
-    // }
+    // Arguments with spaces/parentheses are encoded for the wallpaperservice32.exe hop by the installer
+    if let Some(path) = &new_cli.wallpaper_engine_path {
+        args.push("-w".into());
+        args.push(path.clone().into());
+    }
 
     if new_cli.bit64 { args.push("--64bit".into()); }
 
     if new_cli.disable_sentry { args.push("--disable-sentry".into()); }
     if let Some(dsn) = &new_cli.sentry_dsn { args.push("--sentry-dsn".into()); args.push(dsn.clone().into()); }
-    std::process::Command::new(exe).args(args).spawn()?;
-
-    Ok(())
+    args
 }
 
-pub fn run_install_tui(mut base: Cli) -> Result<Cli> {
+pub fn run_install_tui(base: Cli) -> Result<Cli> {
     let theme = ColorfulTheme::default();
 
     println!("\nWallpaper Controller - Interactive Installer\n");
+    let base = prompt_settings(&theme, base)?;
+    print_summary(&base);
+
+    // Same plan the relaunched installer will build and execute
+    let launch_args = filtered_passthrough_args(relaunch_args(&base));
+    let plan = build_plan(&base, &launch_args, &SystemProbe)?;
+    println!("\nThe installer will:\n{}", plan);
 
+    let proceed = Confirm::with_theme(&theme)
+        .with_prompt("Proceed with installation?")
+        .default(true)
+        .interact()?;
+    if !proceed { return Err(anyhow!("User cancelled")); }
+
+    Ok(base)
+}
+
+/// Asks for every install and runtime setting, using the values already in `base` as defaults, and returns
+/// `base` with the answers (including the install directory and startup mode) filled in
+pub(crate) fn prompt_settings(theme: &ColorfulTheme, mut base: Cli) -> Result<Cli> {
     // Detect Wallpaper Engine Service state
     let we_service = wallpaper_engine_service_exists();
 
     // Explain startup modes
-    println!("Startup mode choices:\n  • Windows Service: Starts early (before user logon). Requires Wallpaper Engine's 'High Priority (Run as service)'.\n    Use this if you rely on the WE service and want earliest startup.\n  • Scheduled Task: Starts at user logon with highest privileges. Works even if the WE service is disabled.\n");
+    println!("Startup mode choices:\n  • Windows Service: Starts early (before user logon). Requires Wallpaper Engine's 'High Priority (Run as service)'.\n    Use this if you rely on the WE service and want earliest startup.\n  • Scheduled Task: Starts at user logon with highest privileges. Works even if the WE service is disabled.\n  • For me only: A Scheduled Task without highest privileges, installed into your user profile.\n    Needs no administrator rights, but can't see elevated windows or pause an elevated Wallpaper Engine.\n\nThe service and the elevated task ask for administrator rights when the installer runs.\n");
 
+    const SERVICE: &str = "Install as Windows Service (recommended, needs administrator rights)";
+    const TASK: &str = "Install as Scheduled Task at logon (needs administrator rights)";
+    const PER_USER: &str = "Install for me only: Scheduled Task at logon, no administrator rights";
+    const RETRY: &str = "I will enable Wallpaper Engine's High Priority service and retry";
     let modes: Vec<&str> = if we_service {
-        vec![
-            "Install as Windows Service (recommended)",
-            "Install as Scheduled Task at logon",
-        ]
+        vec![SERVICE, TASK, PER_USER]
     } else {
         println!("Note: Wallpaper Engine Service not detected.");
         println!("Either enable it in WE settings (General → Start with Windows → High Priority) and rerun, or choose a Scheduled Task.");
-        vec![
-            "Install as Scheduled Task at logon (recommended)",
-            "I will enable Wallpaper Engine's High Priority service and retry",
-        ]
+        vec![TASK, PER_USER, RETRY]
+    };
+    let current = if base.per_user {
+        PER_USER
+    } else if we_service && base.add_startup_task {
+        TASK
+    } else {
+        modes[0]
     };
 
-    let mode_idx = Select::with_theme(&theme)
+    let mode_idx = Select::with_theme(theme)
         .with_prompt(if we_service { "Choose how Wallpaper Controller should run on startup" } else { "Service not detected. How would you like to proceed?" })
         .items(&modes)
-        .default(0)
+        .default(modes.iter().position(|m| *m == current).unwrap_or(0))
         .interact()?;
 
-    if !we_service && mode_idx == 1 {
+    if modes[mode_idx] == RETRY {
         println!("\nOpen Wallpaper Engine → Settings → General and set 'Start with Windows' to High Priority (Run as service). After enabling, run this installer again.\n");
         return Err(anyhow!("User opted to enable service and retry"));
     }
 
     // Derive startup choice
-    let install_as_service = we_service && mode_idx == 0;
+    let install_as_service = modes[mode_idx] == SERVICE;
     let install_as_task = !install_as_service;
+    base.per_user = modes[mode_idx] == PER_USER;
 
     // Install directory (validated)
-    let default_dir_str = dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("C:\\Users\\Public"))
-        .join(".wallpaper-controller")
-        .to_string_lossy().to_string();
+    let default_dir_str = base.install_dir.clone()
+        .unwrap_or_else(|| default_install_dir().to_string_lossy().to_string());
 
     println!("\n• Install location: Press enter to accept the default location");
-    let install_dir: String = Input::with_theme(&theme)
+    let install_dir: String = Input::with_theme(theme)
         .with_prompt("Install directory")
         .default(default_dir_str)
         .validate_with(|s: &String| validate_install_dir(s))
         .interact_text()?;
 
     // Monitors (validated)
-    println!("\n• Monitors: Specify which monitors should be considered when calculating desktop visibility.\n   Specify 'all' to monitor all displays\n   Or specify a comma-separated list of display numbers as shown in Windows Display Settings (e.g., 1,2)");
-    base.monitors = Input::with_theme(&theme)
-        .with_prompt("Monitors to watch ('all' or e.g. '2' or '1,2')")
+    println!("\n• Monitors: Specify which monitors should be considered when calculating desktop visibility.\n   Specify 'all' to monitor all displays\n   Or specify a comma-separated list of display numbers as shown in Windows Display Settings (e.g., 1,2), ranges (1-3),\n   'primary', 'largest' or 'smallest', and exclude monitors with '!' (e.g., all,!2)");
+    base.monitors = Input::with_theme(theme)
+        .with_prompt("Monitors to watch ('all' or e.g. '2', '1,2' or 'primary')")
         .default(base.monitors.clone())
         .validate_with(|s: &String| validate_monitors(s))
         .interact_text()?;
 
     // Threshold (mandatory)
     println!("\n• Visibility threshold: Percentage of the desktop (across enabled monitors) that must remain visible before wallpapers are paused.");
-    let th_str: String = Input::with_theme(&theme)
+    let th_str: String = Input::with_theme(theme)
         .with_prompt("Visibility threshold (0–100)")
-        .default("20".into())
+        .default(base.threshold.unwrap_or(20).to_string())
         .validate_with(|s: &String| validate_threshold(s))
         .interact_text()?;
     base.threshold = Some(th_str.trim().parse::<u8>()?);
 
     // Advanced options
     println!();
-    let advanced = Confirm::with_theme(&theme)
+    let advanced = Confirm::with_theme(theme)
         .with_prompt("Open advanced configuration?")
         .default(false)
         .interact()?;
 
     if advanced {
         println!("\n• Update rate: Minimum time between visibility recalculations (in milliseconds).\n   Lower = more responsive, higher CPU and more frequent pause/resume for Wallpaper Engine.");
-        let upd_str: String = Input::with_theme(&theme)
+        let upd_str: String = Input::with_theme(theme)
             .with_prompt("Update rate in ms (100–60000)")
             .default(base.update_rate.to_string())
             .validate_with(|s: &String| validate_update_rate(s))
             .interact_text()?;
         base.update_rate = upd_str.trim().parse::<u64>()?;
 
-        base.bit64 = Confirm::with_theme(&theme)
+        base.bit64 = Confirm::with_theme(theme)
             .with_prompt("• Use 64-bit Wallpaper Engine (wallpaper64.exe)?")
             .default(base.bit64)
             .interact()?;
 
-        // TODO: See TODO note in run_install_tui_and_relaunch
-        // println!("\n• Wallpaper Engine folder:");
-        // base.wallpaper_engine_path = Input::with_theme(&theme)
-        //     .with_prompt("Wallpaper Engine install path")
-        //     .default(base.wallpaper_engine_path.clone())
-        //     .validate_with(|s: &String| validate_we_path(s, base.bit64))
-        //     .interact_text()?;
+        base.wallpaper_engine_path = prompt_wallpaper_engine_path(theme, &base)?;
+
+        if install_as_task {
+            prompt_task_settings(theme, &mut base)?;
+        }
     }
 
-    // Summary & confirmation
+    // Fill internal fields consumed by the installer
+    base.install_dir = Some(install_dir);
+    base.add_startup_service = install_as_service;
+    base.add_startup_task = install_as_task;
+    Ok(base)
+}
+
+fn print_summary(base: &Cli) {
+    let startup = if base.add_startup_service {
+        String::from("Windows Service")
+    } else {
+        let triggers: Vec<&str> = base.task_triggers.iter().map(|t| t.name()).collect();
+        let restart = if base.task_restart_count == 0 {
+            String::from("no restart")
+        } else {
+            format!("restart up to {}x every {} s", base.task_restart_count, base.task_restart_interval)
+        };
+        let scope = if base.per_user { "for you only, no administrator rights" } else { "elevated" };
+        format!("Scheduled Task ({}, {}, {} s delay, {})", scope, triggers.join(" + "), base.task_delay, restart)
+    };
     println!(
-        "\nSummary:\n  Startup: {}\n  Install dir: {}\n  Threshold: {}\n  Monitors: {}\n  Update rate: {} ms\n  WE 64-bit: {}\n  WE path: {}\n",
-        if install_as_service { "Windows Service" } else { "Scheduled Task at logon" },
-        install_dir,
-        base.threshold.unwrap(),
+        "\nSummary:\n  Startup: {}\n  Install dir: {}\n  Threshold: {}\n  Monitors: {}\n  Update rate: {} ms\n  WE 64-bit: {}\n  WE path: {}",
+        startup,
+        base.install_dir.as_deref().unwrap_or_default(),
+        base.threshold.unwrap_or(20),
         base.monitors,
         base.update_rate,
         base.bit64,
-        base.wallpaper_engine_path,
+        base.wallpaper_engine_path.as_deref().unwrap_or("detect from Steam libraries"),
     );
-    let proceed = Confirm::with_theme(&theme)
-        .with_prompt("Proceed with installation?")
-        .default(true)
+}
+
+fn prompt_wallpaper_engine_path(theme: &ColorfulTheme, base: &Cli) -> Result<Option<String>> {
+    println!("\n• Wallpaper Engine folder: Where wallpaper32.exe/wallpaper64.exe are installed.");
+
+    // Only offer detected installs that contain the executable we are going to use
+    let installs: Vec<_> = find_wallpaper_engine_installs()
+        .into_iter()
+        .filter(|i| if base.bit64 { i.has_64bit } else { i.has_32bit || i.has_64bit })
+        .collect();
+
+    let mut items = vec![String::from("Detect automatically from Steam libraries each time it starts (recommended)")];
+    items.extend(installs.iter().map(|i| format!("Always use {}", i.dir.display())));
+    items.push(String::from("Enter a folder manually"));
+
+    let default = match &base.wallpaper_engine_path {
+        None => 0,
+        Some(current) => installs.iter()
+            .position(|i| i.dir == Path::new(current))
+            .map(|p| p + 1)
+            .unwrap_or(items.len() - 1),
+    };
+
+    let choice = Select::with_theme(theme)
+        .with_prompt("Wallpaper Engine folder")
+        .items(&items)
+        .default(default)
         .interact()?;
-    if !proceed { return Err(anyhow!("User cancelled")); }
 
-    // Fill internal fields consumed by the installer
-    base.install_dir = Some(install_dir);
-    base.add_startup_service = install_as_service;
-    base.add_startup_task = install_as_task;
+    if choice == 0 {
+        return Ok(None);
+    }
+    if let Some(install) = installs.get(choice - 1) {
+        return Ok(Some(install.dir.to_string_lossy().to_string()));
+    }
 
-    Ok(base)
+    let path: String = Input::with_theme(theme)
+        .with_prompt("Wallpaper Engine install path")
+        .default(base.wallpaper_engine_path.clone().unwrap_or_else(|| DEFAULT_WALLPAPER_ENGINE_DIR.to_string()))
+        .validate_with(|s: &String| validate_we_path(s, base.bit64))
+        .interact_text()?;
+    Ok(Some(path.trim().to_string()))
+}
+
+fn prompt_task_settings(theme: &ColorfulTheme, base: &mut Cli) -> Result<()> {
+    println!("\n• Scheduled task: When the task starts the controller and what happens if it fails.");
+    let delay_str: String = Input::with_theme(theme)
+        .with_prompt("Start delay in seconds (0–86400)")
+        .default(base.task_delay.to_string())
+        .validate_with(|s: &String| validate_task_delay(s))
+        .interact_text()?;
+    base.task_delay = delay_str.trim().parse::<u64>()?;
+
+    let items = ["At logon", "When the workstation is unlocked (starts the controller again if it isn't running)"];
+    let defaults = [
+        base.task_triggers.contains(&TaskTrigger::Logon),
+        base.task_triggers.contains(&TaskTrigger::Unlock),
+    ];
+    loop {
+        let chosen = MultiSelect::with_theme(theme)
+            .with_prompt("Start the task (space to toggle, enter to confirm)")
+            .items(&items)
+            .defaults(&defaults)
+            .interact()?;
+        if chosen.is_empty() {
+            println!("Select at least one trigger.");
+            continue;
+        }
+        base.task_triggers = chosen.into_iter()
+            .map(|i| if i == 0 { TaskTrigger::Logon } else { TaskTrigger::Unlock })
+            .collect();
+        break;
+    }
+
+    let count_str: String = Input::with_theme(theme)
+        .with_prompt("Restart attempts after a failure (0 to disable)")
+        .default(base.task_restart_count.to_string())
+        .validate_with(|s: &String| validate_restart_count(s))
+        .interact_text()?;
+    base.task_restart_count = count_str.trim().parse::<u16>()?;
+
+    if base.task_restart_count > 0 {
+        let interval_str: String = Input::with_theme(theme)
+            .with_prompt(format!(
+                "Seconds between restart attempts ({}–{})", RESTART_INTERVAL_SECS.start(), RESTART_INTERVAL_SECS.end()
+            ))
+            .default(base.task_restart_interval.to_string())
+            .validate_with(|s: &String| validate_restart_interval(s))
+            .interact_text()?;
+        base.task_restart_interval = interval_str.trim().parse::<u64>()?;
+    }
+    Ok(())
 }
@@ -0,0 +1,474 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use windows::Win32::System::Threading::CREATE_NO_WINDOW;
+use windows_service::service::{ServiceAccess, ServiceState};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::cli::Cli;
+use crate::error::InstallError;
+use crate::install::answers::Answers;
+use crate::install::manifest::{MANIFEST_FILE_NAME, PREVIOUS_EXE_NAME};
+use crate::install::{
+    default_install_dir, service_exe_path, task_exe_path, wait_for_service_stop, INSTALLED_EXE_NAME, SERVICE_NAME, TASK_NAME,
+};
+use crate::paths;
+use crate::steam::resolve_wallpaper_engine;
+use crate::wallpaper::WallpaperController;
+
+const SERVICE_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+enum Outcome {
+    Removed,
+    Done,
+    NotFound,
+    /// The file is in use by this process and is deleted once it exits
+    Scheduled,
+    /// Left alone on purpose (e.g. a folder that still contains other files)
+    Skipped(String),
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Removed => write!(f, "removed"),
+            Outcome::Done => write!(f, "done"),
+            Outcome::NotFound => write!(f, "not found"),
+            Outcome::Scheduled => write!(f, "will be removed when this window closes"),
+            Outcome::Skipped(why) => write!(f, "skipped ({})", why),
+            Outcome::Failed(why) => write!(f, "FAILED: {}", why),
+        }
+    }
+}
+
+/// What was (or wasn't) removed, in the order it was attempted
+#[derive(Debug, Default)]
+struct UninstallSummary {
+    items: Vec<(String, Outcome)>,
+}
+
+impl UninstallSummary {
+    fn add(&mut self, item: impl Into<String>, outcome: Outcome) {
+        let item = item.into();
+        match &outcome {
+            Outcome::Failed(why) => warn!("{}: {}", item, why),
+            other => info!("{}: {}", item, other),
+        }
+        self.items.push((item, outcome));
+    }
+
+    fn failures(&self) -> usize {
+        self.items.iter().filter(|(_, o)| matches!(o, Outcome::Failed(_))).count()
+    }
+
+    fn print(&self) {
+        println!("\nUninstall summary:");
+        for (item, outcome) in &self.items {
+            println!("  • {}: {}", item, outcome);
+        }
+        println!();
+    }
+}
+
+/// Removes the startup service/task, stops running instances, resumes wallpapers and deletes the installed
+/// files along with the config file, saved answers files, logs and decision trace
+pub async fn handle_uninstall(args: &Cli) -> Result<(), InstallError> {
+    info!("Uninstalling Wallpaper Controller...");
+    let mut summary = UninstallSummary::default();
+
+    // Find out where we were installed before the service/task that points there is gone
+    let install_dirs = installed_dirs(args);
+
+    remove_service(&mut summary);
+    remove_task(&mut summary);
+
+    match crate::kill_other_instances() {
+        Ok(()) => summary.add("Stop running instances", Outcome::Done),
+        Err(e) => summary.add("Stop running instances", Outcome::Failed(format!("{:#}", e))),
+    }
+
+    // A terminated instance had no chance to resume the wallpapers it paused
+    resume_wallpapers(args, &mut summary).await;
+
+    for dir in &install_dirs {
+        remove_install_dir(dir, &mut summary);
+    }
+    if install_dirs.is_empty() {
+        summary.add("Install directory", Outcome::NotFound);
+    }
+
+    let paths = paths::current();
+    remove_log_files(paths.log_file(), paths.rotated_log_file(), &mut summary);
+    // Normally removed by the controller, but a terminated one leaves them behind
+    for path in [paths.status_file(), paths.profile_request_file()].into_iter().flatten() {
+        remove_file(&path, false, &mut summary);
+    }
+    if let Some(list) = paths.saved_answers_list() {
+        remove_saved_answers(&list, &mut summary);
+    }
+    match args.config.as_ref().map(PathBuf::from).or_else(|| paths.config_file()) {
+        Some(path) => remove_config_file(&path, &mut summary),
+        None => summary.add("Config file", Outcome::NotFound),
+    }
+    let trace_file = args.trace_file.as_ref().map(PathBuf::from).or_else(|| paths.trace_file());
+    match trace_file {
+        Some(path) => remove_state_files(&path, &mut summary),
+        None => summary.add("Decision trace", Outcome::NotFound),
+    }
+
+    summary.print();
+    match summary.failures() {
+        0 => Ok(()),
+        failed => Err(InstallError::UninstallIncomplete(failed)),
+    }
+}
+
+fn remove_service(summary: &mut UninstallSummary) {
+    let item = format!("Service '{}'", SERVICE_NAME);
+    let manager = match ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::all()) {
+        Ok(manager) => manager,
+        Err(e) => return summary.add(item, Outcome::Failed(format!("cannot open the service manager: {}", e))),
+    };
+    let Ok(service) = manager.open_service(SERVICE_NAME, ServiceAccess::all()) else {
+        return summary.add(item, Outcome::NotFound);
+    };
+
+    // Stop first so the controller it launched can resume wallpapers on the way out
+    if service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped) {
+        info!("Stopping service '{}'...", SERVICE_NAME);
+        if let Err(e) = service.stop() {
+            warn!("Failed to stop service '{}': {}", SERVICE_NAME, e);
+        }
+        wait_for_service_stop(&service, SERVICE_STOP_TIMEOUT);
+    }
+
+    match service.delete() {
+        Ok(()) => summary.add(item, Outcome::Removed),
+        Err(e) => summary.add(item, Outcome::Failed(format!(
+            "{} (close Services and Task Manager windows, then retry)", e
+        ))),
+    }
+}
+
+fn remove_task(summary: &mut UninstallSummary) {
+    let item = format!("Scheduled task '{}'", TASK_NAME);
+    match Command::new("schtasks").args(["/Query", "/TN", TASK_NAME]).output() {
+        Ok(query) if !query.status.success() => return summary.add(item, Outcome::NotFound),
+        Ok(_) => {}
+        Err(e) => return summary.add(item, Outcome::Failed(format!("cannot run schtasks: {}", e))),
+    }
+
+    // Ending the task terminates the controller it started
+    let _ = Command::new("schtasks").args(["/End", "/TN", TASK_NAME]).output();
+
+    match Command::new("schtasks").args(["/Delete", "/TN", TASK_NAME, "/F"]).output() {
+        Ok(out) if out.status.success() => summary.add(item, Outcome::Removed),
+        Ok(out) => summary.add(item, Outcome::Failed(String::from_utf8_lossy(&out.stderr).trim().to_string())),
+        Err(e) => summary.add(item, Outcome::Failed(e.to_string())),
+    }
+}
+
+async fn resume_wallpapers(args: &Cli, summary: &mut UninstallSummary) {
+    let item = "Resume wallpapers";
+    let wallpaper_engine = resolve_wallpaper_engine(args.wallpaper_engine_path.as_deref(), args.bit64);
+    let mut controller = match WallpaperController::new(wallpaper_engine.dir, wallpaper_engine.use_64bit, false) {
+        Ok(controller) => controller,
+        Err(e) => return summary.add(item, Outcome::Skipped(format!("Wallpaper Engine not found: {}", e))),
+    };
+    match controller.play(None).await {
+        Ok(()) => summary.add(item, Outcome::Done),
+        Err(e) => summary.add(item, Outcome::Failed(e.to_string())),
+    }
+}
+
+/// Directories holding an installed copy: from the service and task command lines, `--install-dir`, and
+/// the installer's default location
+fn installed_dirs(args: &Cli) -> Vec<PathBuf> {
+    let mut exes: Vec<PathBuf> = Vec::new();
+    exes.extend(service_exe_path());
+    exes.extend(task_exe_path());
+    if let Some(dir) = &args.install_dir {
+        exes.push(Path::new(dir).join(INSTALLED_EXE_NAME));
+    }
+    exes.push(default_install_dir().join(INSTALLED_EXE_NAME));
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for exe in exes {
+        debug!("Checking for an installed copy at {}", exe.display());
+        // A service/task pointing at the downloaded executable (no --install-dir) doesn't own its folder
+        let is_installed_copy = exe.file_name().is_some_and(|n| n.eq_ignore_ascii_case(INSTALLED_EXE_NAME));
+        if !is_installed_copy || !exe.is_file() {
+            continue;
+        }
+        if let Some(dir) = exe.parent() {
+            if !dirs.iter().any(|d| d == dir) {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+    }
+    dirs
+}
+
+fn remove_install_dir(dir: &Path, summary: &mut UninstallSummary) {
+    // Files the installer writes next to the executable
+    for name in [MANIFEST_FILE_NAME, PREVIOUS_EXE_NAME] {
+        remove_file(&dir.join(name), false, summary);
+    }
+
+    let exe = dir.join(INSTALLED_EXE_NAME);
+    let running_from_here = std::env::current_exe().is_ok_and(|current| current == exe);
+
+    if running_from_here {
+        // Windows won't delete a running executable; let a detached shell do it after we exit
+        let outcome = match schedule_delete_after_exit(dir) {
+            Ok(()) => Outcome::Scheduled,
+            Err(e) => Outcome::Failed(format!("{:#}", e)),
+        };
+        return summary.add(exe.display().to_string(), outcome);
+    }
+
+    if remove_file(&exe, true, summary) {
+        remove_dir_if_empty(dir, summary);
+    }
+}
+
+/// Removes `file` and reports it; a missing file is only reported if `report_missing`. Returns whether the
+/// file is gone.
+fn remove_file(file: &Path, report_missing: bool, summary: &mut UninstallSummary) -> bool {
+    let item = file.display().to_string();
+    match fs::remove_file(file) {
+        Ok(()) => summary.add(item, Outcome::Removed),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if report_missing {
+                summary.add(item, Outcome::NotFound);
+            }
+        }
+        Err(e) => {
+            summary.add(item, Outcome::Failed(e.to_string()));
+            return false;
+        }
+    }
+    true
+}
+
+// Only folders left empty are removed, so installing into a shared folder (e.g. C:\Tools) is safe to undo
+fn remove_dir_if_empty(dir: &Path, summary: &mut UninstallSummary) {
+    let item = dir.display().to_string();
+    match fs::read_dir(dir).map(|mut entries| entries.next().is_none()) {
+        Ok(true) => match fs::remove_dir(dir) {
+            Ok(()) => summary.add(item, Outcome::Removed),
+            Err(e) => summary.add(item, Outcome::Failed(e.to_string())),
+        },
+        Ok(false) => summary.add(item, Outcome::Skipped(String::from("contains other files"))),
+        Err(_) => summary.add(item, Outcome::NotFound),
+    }
+}
+
+// The paths reach cmd as environment variables expanded inside quotes: cmd expands `%VAR%` only once, so a
+// `%` in a path stays literal, and the quotes keep characters like `&` from being parsed
+const DELETE_AFTER_EXIT_SCRIPT: &str =
+    r#"/D /V:OFF /S /C "ping -n 4 127.0.0.1 >nul & del /F /Q "%WPC_UNINSTALL_EXE%" & rmdir "%WPC_UNINSTALL_DIR%"""#;
+
+fn schedule_delete_after_exit(dir: &Path) -> Result<()> {
+    Command::new("cmd")
+        .raw_arg(DELETE_AFTER_EXIT_SCRIPT)
+        .env("WPC_UNINSTALL_EXE", dir.join(INSTALLED_EXE_NAME))
+        .env("WPC_UNINSTALL_DIR", dir)
+        .creation_flags(CREATE_NO_WINDOW.0)
+        .spawn()
+        .map_err(|e| anyhow!("failed to schedule removal: {}", e))?;
+    Ok(())
+}
+
+// Logs are written by the running controller only, which was stopped above
+fn remove_log_files(log_file: Option<PathBuf>, rotated: Option<PathBuf>, summary: &mut UninstallSummary) {
+    let Some(log_file) = log_file else { return };
+    for file in [Some(log_file.clone()), rotated].into_iter().flatten() {
+        remove_file(&file, false, summary);
+    }
+    if let Some(dir) = log_file.parent().filter(|d| d.is_dir()) {
+        remove_dir_if_empty(dir, summary);
+    }
+}
+
+fn remove_state_files(trace_file: &Path, summary: &mut UninstallSummary) {
+    remove_file(trace_file, true, summary);
+    // The temporary file only exists mid-write; don't clutter the summary with it
+    remove_file(&trace_file.with_extension("jsonl.tmp"), false, summary);
+    if let Some(dir) = trace_file.parent().filter(|d| d.is_dir()) {
+        remove_dir_if_empty(dir, summary);
+    }
+}
+
+fn remove_config_file(config_file: &Path, summary: &mut UninstallSummary) {
+    remove_file(config_file, true, summary);
+    if let Some(dir) = config_file.parent().filter(|d| d.is_dir()) {
+        remove_dir_if_empty(dir, summary);
+    }
+}
+
+// Answers files can be saved anywhere, so the installer lists them
+fn remove_saved_answers(list: &Path, summary: &mut UninstallSummary) {
+    for file in Answers::recorded_saved(list) {
+        remove_file(&file, true, summary);
+    }
+    remove_file(list, false, summary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The summary as (file name, outcome) pairs
+    fn items(summary: &UninstallSummary) -> Vec<(String, String)> {
+        summary.items.iter()
+            .map(|(item, outcome)| {
+                let name = Path::new(item).file_name().map_or(item.clone(), |name| name.to_string_lossy().to_string());
+                (name, outcome.to_string())
+            })
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(item, outcome)| (item.to_string(), outcome.to_string())).collect()
+    }
+
+    #[test]
+    fn only_empty_folders_are_removed() {
+        let root = tempfile::tempdir().unwrap();
+        let empty = root.path().join("empty");
+        let shared = root.path().join("shared");
+        fs::create_dir(&empty).unwrap();
+        fs::create_dir(&shared).unwrap();
+        fs::write(shared.join("other.txt"), "keep").unwrap();
+
+        let mut summary = UninstallSummary::default();
+        remove_dir_if_empty(&empty, &mut summary);
+        remove_dir_if_empty(&shared, &mut summary);
+        remove_dir_if_empty(&root.path().join("missing"), &mut summary);
+        assert_eq!(items(&summary), pairs(&[
+            ("empty", "removed"),
+            ("shared", "skipped (contains other files)"),
+            ("missing", "not found"),
+        ]));
+        assert!(!empty.exists());
+        assert!(shared.join("other.txt").exists());
+        assert_eq!(summary.failures(), 0);
+    }
+
+    #[test]
+    fn removing_files_reports_each_outcome() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join("a.txt");
+        fs::write(&file, "").unwrap();
+
+        let mut summary = UninstallSummary::default();
+        assert!(remove_file(&file, true, &mut summary));
+        assert!(remove_file(&root.path().join("quiet.txt"), false, &mut summary));
+        assert!(remove_file(&root.path().join("loud.txt"), true, &mut summary));
+        // A folder can't be removed as a file
+        assert!(!remove_file(root.path(), true, &mut summary));
+
+        let items = items(&summary);
+        assert_eq!(items[..2], pairs(&[("a.txt", "removed"), ("loud.txt", "not found")]));
+        assert!(items[2].1.starts_with("FAILED: "), "{:?}", items[2]);
+        assert_eq!(summary.failures(), 1);
+    }
+
+    #[test]
+    fn install_dirs_lose_the_installer_files_and_only_then_the_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let install = |name: &str, extra: Option<&str>| {
+            let dir = root.path().join(name);
+            fs::create_dir(&dir).unwrap();
+            for file in [INSTALLED_EXE_NAME, MANIFEST_FILE_NAME, PREVIOUS_EXE_NAME].into_iter().chain(extra) {
+                fs::write(dir.join(file), "").unwrap();
+            }
+            dir
+        };
+        let own = install("wpc", None);
+        let shared = install("tools", Some("other.exe"));
+
+        let mut summary = UninstallSummary::default();
+        remove_install_dir(&own, &mut summary);
+        remove_install_dir(&shared, &mut summary);
+        assert_eq!(items(&summary), pairs(&[
+            (MANIFEST_FILE_NAME, "removed"),
+            (PREVIOUS_EXE_NAME, "removed"),
+            (INSTALLED_EXE_NAME, "removed"),
+            ("wpc", "removed"),
+            (MANIFEST_FILE_NAME, "removed"),
+            (PREVIOUS_EXE_NAME, "removed"),
+            (INSTALLED_EXE_NAME, "removed"),
+            ("tools", "skipped (contains other files)"),
+        ]));
+        assert!(!own.exists());
+        assert_eq!(fs::read_dir(&shared).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn state_config_and_log_files() {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        let config = root.path().join("config");
+        fs::create_dir_all(data.join("logs")).unwrap();
+        fs::create_dir(&config).unwrap();
+        fs::write(data.join("decisions.jsonl"), "").unwrap();
+        fs::write(data.join("logs").join("wpc.log"), "").unwrap();
+        fs::write(config.join("config.toml"), "threshold = 30").unwrap();
+
+        let mut summary = UninstallSummary::default();
+        remove_log_files(Some(data.join("logs").join("wpc.log")), Some(data.join("logs").join("wpc.log.old")), &mut summary);
+        remove_state_files(&data.join("decisions.jsonl"), &mut summary);
+        remove_config_file(&config.join("config.toml"), &mut summary);
+        remove_config_file(&config.join("config.toml"), &mut summary);
+        assert_eq!(items(&summary), pairs(&[
+            ("wpc.log", "removed"),
+            ("logs", "removed"),
+            ("decisions.jsonl", "removed"),
+            ("data", "removed"),
+            ("config.toml", "removed"),
+            ("config", "removed"),
+            ("config.toml", "not found"),
+        ]));
+        assert!(fs::read_dir(root.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn saved_answers_files_are_removed_with_their_list() {
+        let root = tempfile::tempdir().unwrap();
+        let list = root.path().join("state").join("saved-answers.txt");
+        let answers = root.path().join("answers.toml");
+        let moved = root.path().join("moved.toml");
+        fs::write(&answers, "startup = \"task\"").unwrap();
+        Answers::record_saved(&list, &answers).unwrap();
+        Answers::record_saved(&list, &moved).unwrap();
+        Answers::record_saved(&list, &answers).unwrap();
+        assert_eq!(Answers::recorded_saved(&list), [answers.clone(), moved]);
+
+        let mut summary = UninstallSummary::default();
+        remove_saved_answers(&list, &mut summary);
+        assert_eq!(items(&summary), pairs(&[
+            ("answers.toml", "removed"),
+            ("moved.toml", "not found"),
+            ("saved-answers.txt", "removed"),
+        ]));
+        assert!(!answers.exists() && !list.exists());
+    }
+
+    #[test]
+    fn the_delete_script_takes_paths_only_from_the_environment() {
+        assert!(!DELETE_AFTER_EXIT_SCRIPT.contains(INSTALLED_EXE_NAME));
+        for var in ["%WPC_UNINSTALL_EXE%", "%WPC_UNINSTALL_DIR%"] {
+            assert!(DELETE_AFTER_EXIT_SCRIPT.contains(&format!("\"{}\"", var)), "{} is not quoted", var);
+        }
+    }
+}
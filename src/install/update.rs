@@ -0,0 +1,461 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use url::Url;
+use windows_service::service::{ServiceAccess, ServiceState};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+use crate::cli::Cli;
+use crate::error::UpdateError;
+use crate::install::manifest::{installed_version, manifest_path, InstallManifest, PreviousInstall, PREVIOUS_EXE_NAME};
+use crate::install::{
+    default_install_dir, service_exe_path, start_service, task_exe_path, wait_for_service_stop, INSTALLED_EXE_NAME,
+    SERVICE_NAME, TASK_NAME,
+};
+use crate::version::Version;
+
+// Releases are described by a small JSON manifest published next to the binary. The manifest carries the
+// blake3 hash of the binary and an ed25519 signature over version and hash, so a tampered manifest or
+// binary is rejected before anything on disk is touched.
+
+/// Where `--update`/`--check-update` look when no `--update-url` is given
+pub const DEFAULT_UPDATE_URL: &str =
+    "https://github.com/dnetguru/wallpaper-engine-controller/releases/latest/download/release-manifest.json";
+// Hex-encoded public half of the release signing key, baked in by CI builds (empty or missing otherwise)
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("WPC_RELEASE_PUBLIC_KEY");
+const MAX_MANIFEST_BYTES: u64 = 64 * 1024;
+const MAX_BINARY_BYTES: u64 = 128 * 1024 * 1024;
+const DOWNLOAD_FILE_NAME: &str = "wallpaper-controller.exe.download";
+const SERVICE_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A published release, as served at the update URL
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReleaseManifest {
+    pub version: String,
+    /// Location of the executable, absolute or relative to the manifest
+    pub url: String,
+    /// Hex-encoded blake3 hash of the executable
+    pub blake3: String,
+    /// Hex-encoded ed25519 signature over `signed_message()`
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl ReleaseManifest {
+    /// The bytes the release signature covers: `wallpaper-controller:<version>:<blake3>`
+    pub fn signed_message(&self) -> String {
+        format!("wallpaper-controller:{}:{}", self.version.trim(), self.blake3.trim().to_ascii_lowercase())
+    }
+
+    fn parsed_version(&self) -> Result<Version, UpdateError> {
+        Version::parse(&self.version)
+            .ok_or_else(|| UpdateError::InvalidManifest(format!("'{}' is not a valid version", self.version)))
+    }
+}
+
+/// The copy that `--update` replaces
+struct Installation {
+    exe: PathBuf,
+    version: Option<Version>,
+    hash: Option<blake3::Hash>,
+    service: bool,
+    task: bool,
+}
+
+/// Reports whether the release at the update URL is newer than the installed (or running) copy
+pub fn handle_check_update(args: &Cli) -> Result<(), UpdateError> {
+    let public_key = public_key(args)?;
+    let manifest_url = update_url(args)?;
+    let release = fetch_release(&manifest_url, public_key)?;
+    let latest = release.parsed_version()?;
+
+    let (label, current) = match find_installation(args) {
+        Some(installation) => (format!("Installed at {}", installation.exe.display()), installation.version),
+        None => (String::from("Not installed; this copy is"), Some(Version::current())),
+    };
+    println!();
+    println!("  {}: {}", label, current.as_ref().map_or(String::from("unknown version"), |v| v.to_string()));
+    println!("  Latest release: {}", latest);
+    match current {
+        Some(current) if current >= latest => println!("\n  Up to date.\n"),
+        _ => {
+            println!("\n  An update is available. Run with --update to install it.");
+            if let Some(notes) = &release.notes {
+                println!("\n  {}", notes.trim());
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
+
+/// Downloads and verifies the latest release, swaps it in for the installed executable and restarts the
+/// startup service/task that runs it
+pub fn handle_update(args: &Cli) -> Result<(), UpdateError> {
+    let public_key = public_key(args)?;
+    let installation = find_installation(args).ok_or(UpdateError::NotInstalled)?;
+    let manifest_url = update_url(args)?;
+    let release = fetch_release(&manifest_url, public_key)?;
+    let latest = release.parsed_version()?;
+
+    if let Some(current) = &installation.version {
+        if *current >= latest {
+            info!("{} is up to date ({}).", installation.exe.display(), current);
+            return Ok(());
+        }
+        info!("Updating {} → {}", current, latest);
+    } else {
+        info!("Updating an executable of unknown version to {}", latest);
+    }
+
+    let binary_url = manifest_url
+        .join(&release.url)
+        .map_err(|e| UpdateError::InvalidManifest(format!("bad binary url '{}': {}", release.url, e)))?;
+    let binary = fetch(&binary_url, MAX_BINARY_BYTES)?;
+    verify_binary(&release, &latest, &binary)?;
+
+    let dir = installation.exe.parent().unwrap_or(Path::new("."));
+    let download = dir.join(DOWNLOAD_FILE_NAME);
+    fs::write(&download, &binary).map_err(|e| UpdateError::Swap {
+        path: download.clone(),
+        source: anyhow::Error::new(e).context("Failed to write the downloaded executable"),
+    })?;
+
+    stop_startup(&installation);
+    let swapped = swap_executable(&installation.exe, &download);
+    let _ = fs::remove_file(&download);
+    let previous = swapped.map_err(|source| UpdateError::Swap { path: installation.exe.clone(), source })?;
+    info!("Installed {} to {}", latest, installation.exe.display());
+
+    if let Err(e) = write_manifest(&installation, &release, previous) {
+        warn!("Could not update the install manifest: {:#}", e);
+    }
+
+    restart_startup(&installation).map_err(|source| UpdateError::Restart { version: latest.to_string(), source })?;
+    info!("Update completed successfully.");
+    Ok(())
+}
+
+// Checked before anything is fetched, so a build without a key fails right away rather than after a download
+fn public_key(args: &Cli) -> Result<&str, UpdateError> {
+    args.update_public_key
+        .as_deref()
+        .or(RELEASE_PUBLIC_KEY)
+        .filter(|key| !key.trim().is_empty())
+        .ok_or(UpdateError::NoPublicKey)
+}
+
+fn update_url(args: &Cli) -> Result<Url, UpdateError> {
+    let raw = args.update_url.as_deref().unwrap_or(DEFAULT_UPDATE_URL);
+    parse_location(raw).ok_or_else(|| UpdateError::Fetch {
+        url: raw.to_string(),
+        source: anyhow!("not a URL or an existing path"),
+    })
+}
+
+// Accepts http(s)/file URLs and plain local paths (a drive letter would otherwise parse as a URL scheme)
+fn parse_location(raw: &str) -> Option<Url> {
+    match Url::parse(raw) {
+        Ok(url) if url.scheme().len() > 1 => Some(url),
+        _ => Url::from_file_path(std::path::absolute(raw).ok()?).ok(),
+    }
+}
+
+fn fetch(url: &Url, limit: u64) -> Result<Vec<u8>, UpdateError> {
+    debug!("Fetching {}", url);
+    let fetched = match url.scheme() {
+        "file" => url
+            .to_file_path()
+            .map_err(|_| anyhow!("not a local file"))
+            .and_then(|path| Ok(fs::read(path)?)),
+        "http" | "https" => ureq::get(url.as_str())
+            .call()
+            .and_then(|mut response| response.body_mut().with_config().limit(limit).read_to_vec())
+            .map_err(anyhow::Error::from),
+        other => Err(anyhow!("unsupported scheme '{}'", other)),
+    };
+    fetched.map_err(|source| UpdateError::Fetch { url: url.to_string(), source })
+}
+
+fn fetch_release(url: &Url, public_key: &str) -> Result<ReleaseManifest, UpdateError> {
+    let body = fetch(url, MAX_MANIFEST_BYTES)?;
+    let release: ReleaseManifest =
+        serde_json::from_slice(&body).map_err(|e| UpdateError::InvalidManifest(e.to_string()))?;
+    verify_signature(&release, public_key)?;
+    Ok(release)
+}
+
+/// Checks the manifest's signature against a hex-encoded ed25519 public key
+pub fn verify_signature(release: &ReleaseManifest, public_key: &str) -> Result<(), UpdateError> {
+    let key: [u8; 32] = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| UpdateError::InvalidPublicKey(String::from("expected 64 hex characters")))?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|e| UpdateError::InvalidPublicKey(e.to_string()))?;
+    let signature: [u8; 64] = hex::decode(release.signature.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| UpdateError::InvalidManifest(String::from("signature must be 128 hex characters")))?;
+    key.verify_strict(release.signed_message().as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| UpdateError::BadSignature)
+}
+
+fn verify_binary(release: &ReleaseManifest, version: &Version, binary: &[u8]) -> Result<(), UpdateError> {
+    let expected = blake3::Hash::from_hex(release.blake3.trim())
+        .map_err(|e| UpdateError::InvalidManifest(format!("bad blake3 hash: {}", e)))?;
+    let actual = blake3::hash(binary);
+    // blake3::Hash compares in constant time
+    if actual != expected {
+        return Err(UpdateError::HashMismatch { expected: expected.to_hex().to_string(), actual: actual.to_hex().to_string() });
+    }
+    match Version::embedded_in(binary) {
+        Some(embedded) if embedded != *version => Err(UpdateError::VersionMismatch {
+            expected: version.to_string(),
+            found: embedded.to_string(),
+        }),
+        Some(_) => Ok(()),
+        None => {
+            warn!("The downloaded executable carries no version marker; trusting the signed manifest");
+            Ok(())
+        }
+    }
+}
+
+/// The installed copy the service/task runs, else the one in `--install-dir` or the default location
+fn find_installation(args: &Cli) -> Option<Installation> {
+    let service_exe = service_exe_path();
+    let task_exe = task_exe_path();
+    let candidates = service_exe
+        .iter()
+        .chain(task_exe.iter())
+        .cloned()
+        .chain(args.install_dir.as_ref().map(|dir| Path::new(dir).join(INSTALLED_EXE_NAME)))
+        .chain(std::iter::once(default_install_dir().join(INSTALLED_EXE_NAME)));
+
+    for exe in candidates {
+        // A service/task pointing at a downloaded executable isn't ours to replace
+        let is_installed_copy = exe.file_name().is_some_and(|n| n.eq_ignore_ascii_case(INSTALLED_EXE_NAME));
+        if !is_installed_copy || !exe.is_file() {
+            continue;
+        }
+        let Ok(contents) = fs::read(&exe) else { continue };
+        let hash = blake3::hash(&contents);
+        let manifest = exe.parent().and_then(|dir| fs::read_to_string(manifest_path(dir)).ok());
+        let version = installed_version(manifest.as_deref(), Some(hash.to_hex().as_str()), &contents);
+        return Some(Installation {
+            service: service_exe.as_ref() == Some(&exe),
+            task: task_exe.as_ref() == Some(&exe),
+            exe,
+            version,
+            hash: Some(hash),
+        });
+    }
+    None
+}
+
+fn stop_startup(installation: &Installation) {
+    if installation.service {
+        info!("Stopping service '{}'...", SERVICE_NAME);
+        let service = ServiceManager::local_computer(None::<&OsStr>, ServiceManagerAccess::CONNECT)
+            .and_then(|manager| manager.open_service(SERVICE_NAME, ServiceAccess::STOP | ServiceAccess::QUERY_STATUS));
+        match service {
+            Ok(service) => {
+                if service.query_status().is_ok_and(|s| s.current_state != ServiceState::Stopped) {
+                    if let Err(e) = service.stop() {
+                        warn!("Failed to stop service '{}': {}", SERVICE_NAME, e);
+                    }
+                    wait_for_service_stop(&service, SERVICE_STOP_TIMEOUT);
+                }
+            }
+            Err(e) => warn!("Could not open service '{}': {}", SERVICE_NAME, e),
+        }
+    }
+    if installation.task {
+        info!("Ending scheduled task '{}'...", TASK_NAME);
+        let _ = Command::new("schtasks").args(["/End", "/TN", TASK_NAME]).output();
+    }
+}
+
+// Windows allows renaming an executable that is still running, so the old copy is moved aside rather than
+// deleted; if moving the new one in fails the old one is put back. Returns where the old copy went.
+fn swap_executable(target: &Path, download: &Path) -> Result<PathBuf> {
+    let previous = target.with_file_name(PREVIOUS_EXE_NAME);
+    if previous.exists() {
+        fs::remove_file(&previous).with_context(|| format!("Failed to remove {}", previous.display()))?;
+    }
+    fs::rename(target, &previous).with_context(|| format!("Failed to move {} aside", target.display()))?;
+    if let Err(e) = fs::rename(download, target) {
+        if let Err(restore) = fs::rename(&previous, target) {
+            bail!("{} (and restoring the previous executable failed: {})", e, restore);
+        }
+        return Err(anyhow::Error::new(e).context("Failed to move the new executable into place"));
+    }
+    Ok(previous)
+}
+
+fn write_manifest(installation: &Installation, release: &ReleaseManifest, previous: PathBuf) -> Result<()> {
+    let dir = installation.exe.parent().unwrap_or(Path::new("."));
+    let path = manifest_path(dir);
+    let old = fs::read_to_string(&path).ok().and_then(|m| InstallManifest::parse(&m).ok());
+    let manifest = InstallManifest {
+        version: release.version.trim().to_string(),
+        hash: release.blake3.trim().to_ascii_lowercase(),
+        installed_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        // The service/task command line is unchanged, so its arguments carry over
+        args: old.map(|m| m.args).unwrap_or_default(),
+        previous: installation.hash.map(|hash| PreviousInstall {
+            version: installation.version.as_ref().map(|v| v.to_string()),
+            hash: hash.to_hex().to_string(),
+            file: previous,
+        }),
+    };
+    fs::write(&path, manifest.to_json()).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn restart_startup(installation: &Installation) -> Result<()> {
+    if installation.service {
+        info!("Starting service '{}'...", SERVICE_NAME);
+        start_service(SERVICE_NAME)?;
+    }
+    if installation.task {
+        info!("Starting scheduled task '{}'...", TASK_NAME);
+        let output = Command::new("schtasks").args(["/Run", "/TN", TASK_NAME]).output()?;
+        if !output.status.success() {
+            bail!("schtasks /Run failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+    if !installation.service && !installation.task {
+        info!("No startup service or task runs this copy; start it yourself to use the new version.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const BINARY: &[u8] = b"MZ...wallpaper-controller-version=9.1.0\0...";
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn public_key_hex(key: &SigningKey) -> String {
+        hex::encode(key.verifying_key().as_bytes())
+    }
+
+    fn signed_release(key: &SigningKey, version: &str, binary: &[u8]) -> ReleaseManifest {
+        let mut release = ReleaseManifest {
+            version: version.to_string(),
+            url: String::from("wallpaper-controller.exe"),
+            blake3: blake3::hash(binary).to_hex().to_string(),
+            signature: String::new(),
+            notes: None,
+        };
+        release.signature = hex::encode(key.sign(release.signed_message().as_bytes()).to_bytes());
+        release
+    }
+
+    #[test]
+    fn a_signed_release_is_accepted() {
+        let key = signing_key(1);
+        let release = signed_release(&key, "9.1.0", BINARY);
+        verify_signature(&release, &public_key_hex(&key)).unwrap();
+        verify_binary(&release, &release.parsed_version().unwrap(), BINARY).unwrap();
+    }
+
+    #[test]
+    fn a_tampered_manifest_is_rejected() {
+        let key = signing_key(1);
+        let public_key = public_key_hex(&key);
+        let release = signed_release(&key, "9.1.0", BINARY);
+
+        let mut newer = release.clone();
+        newer.version = String::from("9.2.0");
+        assert!(matches!(verify_signature(&newer, &public_key), Err(UpdateError::BadSignature)));
+
+        let mut other_binary = release.clone();
+        other_binary.blake3 = blake3::hash(b"something else").to_hex().to_string();
+        assert!(matches!(verify_signature(&other_binary, &public_key), Err(UpdateError::BadSignature)));
+
+        // The URL and notes aren't signed; the hash pins the binary wherever it comes from
+        let mut moved = release.clone();
+        moved.url = String::from("https://mirror.example/wpc.exe");
+        verify_signature(&moved, &public_key).unwrap();
+    }
+
+    #[test]
+    fn a_bad_signature_is_rejected() {
+        let key = signing_key(1);
+        let release = signed_release(&key, "9.1.0", BINARY);
+
+        // Signed by someone else
+        let forged = signed_release(&signing_key(2), "9.1.0", BINARY);
+        assert!(matches!(verify_signature(&forged, &public_key_hex(&key)), Err(UpdateError::BadSignature)));
+
+        let mut flipped = release.clone();
+        flipped.signature.replace_range(0..2, if flipped.signature.starts_with("00") { "01" } else { "00" });
+        assert!(matches!(verify_signature(&flipped, &public_key_hex(&key)), Err(UpdateError::BadSignature)));
+
+        let mut truncated = release.clone();
+        truncated.signature.truncate(64);
+        assert!(matches!(verify_signature(&truncated, &public_key_hex(&key)), Err(UpdateError::InvalidManifest(_))));
+
+        let mut not_hex = release.clone();
+        not_hex.signature = "zz".repeat(64);
+        assert!(matches!(verify_signature(&not_hex, &public_key_hex(&key)), Err(UpdateError::InvalidManifest(_))));
+
+        assert!(matches!(verify_signature(&release, "abcd"), Err(UpdateError::InvalidPublicKey(_))));
+    }
+
+    #[test]
+    fn a_hash_mismatch_is_rejected() {
+        let key = signing_key(1);
+        let release = signed_release(&key, "9.1.0", BINARY);
+        let version = release.parsed_version().unwrap();
+
+        let mut tampered = BINARY.to_vec();
+        tampered.push(0);
+        let error = verify_binary(&release, &version, &tampered).unwrap_err();
+        assert!(matches!(error, UpdateError::HashMismatch { ref actual, .. } if *actual == blake3::hash(&tampered).to_hex().to_string()));
+
+        let mut bad_hash = release.clone();
+        bad_hash.blake3 = String::from("not a hash");
+        assert!(matches!(verify_binary(&bad_hash, &version, BINARY), Err(UpdateError::InvalidManifest(_))));
+    }
+
+    #[test]
+    fn a_binary_of_another_version_is_rejected() {
+        let key = signing_key(1);
+        let release = signed_release(&key, "9.2.0", BINARY);
+        let error = verify_binary(&release, &release.parsed_version().unwrap(), BINARY).unwrap_err();
+        assert!(matches!(error, UpdateError::VersionMismatch { ref found, .. } if found == "9.1.0"));
+    }
+
+    #[test]
+    fn fetched_manifests_are_verified() {
+        let key = signing_key(1);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("release-manifest.json");
+        let url = Url::from_file_path(&path).unwrap();
+
+        let release = signed_release(&key, "9.1.0", BINARY);
+        fs::write(&path, serde_json::to_string(&release).unwrap()).unwrap();
+        assert_eq!(fetch_release(&url, &public_key_hex(&key)).unwrap(), release);
+
+        let tampered = serde_json::to_string(&release).unwrap().replace("9.1.0", "9.9.9");
+        fs::write(&path, tampered).unwrap();
+        assert!(matches!(fetch_release(&url, &public_key_hex(&key)), Err(UpdateError::BadSignature)));
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(fetch_release(&url, &public_key_hex(&key)), Err(UpdateError::InvalidManifest(_))));
+    }
+}
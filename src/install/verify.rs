@@ -0,0 +1,597 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+use crate::cli::Cli;
+use crate::error::InstallError;
+use crate::install::answers::StartupMode;
+use crate::install::manifest::{manifest_path, InstallManifest};
+use crate::install::plan::compute_file_hash;
+use crate::install::reconfigure::{
+    apply_in_place, current_settings, decode_installed_cli, read_installed_config, InstalledConfig,
+};
+use crate::install::{
+    copy_executable, remove_existing_task_if_any, task_exe_path, INSTALLED_EXE_NAME, TASK_NAME,
+    WALLPAPER_SERVICE_32_PATH,
+};
+use crate::steam::{resolve_wallpaper_engine, validate_wallpaper_engine_dir};
+use crate::version::{Version, CURRENT_VERSION};
+
+#[derive(Debug)]
+enum Status {
+    Pass,
+    Fail(String),
+    /// Not applicable or not checkable for this installation
+    Skip(String),
+    /// Failed, and `--repair` fixed it
+    Fixed(String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "[PASS]"),
+            Status::Fail(_) => write!(f, "[FAIL]"),
+            Status::Skip(_) => write!(f, "[SKIP]"),
+            Status::Fixed(_) => write!(f, "[FIXED]"),
+        }
+    }
+}
+
+/// Checklist in the order the checks ran
+#[derive(Debug, Default)]
+struct Report {
+    checks: Vec<(String, Status)>,
+}
+
+impl Report {
+    fn add(&mut self, check: impl Into<String>, status: Status) {
+        let check = check.into();
+        match &status {
+            Status::Fail(why) => warn!("{}: {}", check, why),
+            other => info!("{}: {}", check, other),
+        }
+        self.checks.push((check, status));
+    }
+
+    fn failures(&self) -> usize {
+        self.checks.iter().filter(|(_, s)| matches!(s, Status::Fail(_))).count()
+    }
+
+    // Plain text without colors so it can be pasted into a bug report as-is
+    fn print(&self) {
+        println!("\nWallpaper Controller {} installation check:", CURRENT_VERSION);
+        for (check, status) in &self.checks {
+            match status {
+                Status::Pass => println!("  {} {}", status, check),
+                Status::Fail(detail) | Status::Skip(detail) | Status::Fixed(detail) => {
+                    println!("  {} {}: {}", status, check, detail)
+                }
+            }
+        }
+        match self.failures() {
+            0 => println!("\nAll checks passed.\n"),
+            n => println!("\n{} check(s) failed.\n", n),
+        }
+    }
+}
+
+/// What the checks read from and change on the system, so the checklist can be tested against a fake one
+pub trait VerifyEnv {
+    fn installed_config(&self) -> Option<InstalledConfig>;
+    /// Whether the scheduled task is registered (next to a service install)
+    fn task_registered(&self) -> bool;
+    fn remove_task(&self) -> Result<()>;
+    fn is_file(&self, path: &Path) -> bool;
+    /// blake3 hash, hex encoded
+    fn file_hash(&self, path: &Path) -> Result<String>;
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    /// Copies the running executable over `target`, only if it hashes to `expected_hash` when one is given
+    fn restore_from_current_exe(&self, target: &Path, expected_hash: Option<&str>) -> Result<()>;
+    fn write_manifest(&self, installed: &InstalledConfig, hash: &str) -> Result<()>;
+    /// The Wallpaper Engine folder the arguments resolve to, and why it can't be used
+    fn wallpaper_engine(&self, explicit_dir: Option<&str>, prefer_64bit: bool) -> (String, Result<(), String>);
+    /// Registers the service/task again with the runtime arguments of `cli`
+    fn reregister(&self, installed: &InstalledConfig, cli: &Cli) -> Result<(), InstallError>;
+}
+
+/// Checks the installed service/task, its executable and command line, the install manifest and the
+/// Wallpaper Engine files it depends on; with `repair`, fixes what can be fixed without asking
+pub fn handle_verify_install(repair: bool) -> Result<(), InstallError> {
+    let report = verify(&SystemVerifyEnv, repair);
+    report.print();
+    match report.failures() {
+        0 => Ok(()),
+        failed => Err(InstallError::VerifyFailed(failed)),
+    }
+}
+
+fn verify(env: &dyn VerifyEnv, repair: bool) -> Report {
+    let mut report = Report::default();
+    if let Some(installed) = check_startup_entry(env, &mut report, repair) {
+        let cli = check_arguments(&installed, &mut report);
+        check_executable(env, &installed, &mut report, repair);
+        check_manifest(env, &installed, &mut report, repair);
+        check_service_host(env, &installed, &mut report);
+        // A repair may have turned the service into a task
+        let installed = env.installed_config().unwrap_or(installed);
+        check_wallpaper_engine(env, &installed, cli.as_ref(), &mut report, repair);
+    }
+    report
+}
+
+fn check_startup_entry(env: &dyn VerifyEnv, report: &mut Report, repair: bool) -> Option<InstalledConfig> {
+    let check = "Startup service/task is registered";
+    let Some(installed) = env.installed_config() else {
+        report.add(check, Status::Fail(String::from(
+            "neither the service nor the scheduled task exists; run the installer again"
+        )));
+        return None;
+    };
+    report.add(check, Status::Pass);
+
+    // Both at once start the controller twice
+    if installed.startup == StartupMode::Service {
+        let check = "Only one of service and task is registered";
+        if !env.task_registered() {
+            report.add(check, Status::Pass);
+        } else if repair {
+            match env.remove_task() {
+                Ok(()) => report.add(check, Status::Fixed(format!("removed the scheduled task '{}'", TASK_NAME))),
+                Err(e) => report.add(check, Status::Fail(format!("could not remove the scheduled task: {:#}", e))),
+            }
+        } else {
+            report.add(check, Status::Fail(format!(
+                "both the service and the scheduled task '{}' are registered", TASK_NAME
+            )));
+        }
+    }
+    Some(installed)
+}
+
+fn check_arguments(installed: &InstalledConfig, report: &mut Report) -> Option<Cli> {
+    let check = "Registered arguments are valid";
+    match decode_installed_cli(&installed.args) {
+        Ok(cli) => {
+            report.add(check, Status::Pass);
+            Some(cli)
+        }
+        Err(e) => {
+            // clap's message spans several lines; the first one names the problem
+            let message = e.to_string();
+            let first_line = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+            report.add(check, Status::Fail(format!("{} (change them with --reconfigure or reinstall)", first_line)));
+            None
+        }
+    }
+}
+
+fn check_executable(env: &dyn VerifyEnv, installed: &InstalledConfig, report: &mut Report, repair: bool) {
+    let check = format!("Executable exists ({})", installed.exe.display());
+    if installed.exe.file_name().is_none_or(|n| !n.eq_ignore_ascii_case(INSTALLED_EXE_NAME)) {
+        // Registered straight from a download; works, but updates and uninstall won't manage it
+        report.add(check, Status::Skip(format!("not an installed copy (expected {})", INSTALLED_EXE_NAME)));
+        return;
+    }
+    if env.is_file(&installed.exe) {
+        return report.add(check, Status::Pass);
+    }
+    if !repair {
+        return report.add(check, Status::Fail(String::from("missing")));
+    }
+    // With a manifest, only the version it describes may take the missing executable's place
+    let expected = installed.exe.parent()
+        .and_then(|dir| env.read_to_string(&manifest_path(dir)).ok())
+        .and_then(|content| InstallManifest::parse(&content).ok())
+        .map(|manifest| manifest.hash);
+    match env.restore_from_current_exe(&installed.exe, expected.as_deref()) {
+        Ok(()) => report.add(check, Status::Fixed(String::from("restored from this executable"))),
+        Err(e) => report.add(check, Status::Fail(format!("missing, and could not be restored: {:#}", e))),
+    }
+}
+
+fn check_manifest(env: &dyn VerifyEnv, installed: &InstalledConfig, report: &mut Report, repair: bool) {
+    let check = "Executable matches the install manifest";
+    let Some(dir) = installed.exe.parent().filter(|_| env.is_file(&installed.exe)) else {
+        return report.add(check, Status::Skip(String::from("no executable to check")));
+    };
+    let path = manifest_path(dir);
+    let actual = match env.file_hash(&installed.exe) {
+        Ok(hash) => hash,
+        Err(e) => return report.add(check, Status::Fail(format!("could not read the executable: {:#}", e))),
+    };
+
+    let manifest = match env.read_to_string(&path) {
+        Ok(content) => match InstallManifest::parse(&content) {
+            Ok(manifest) => manifest,
+            // Overwriting it would hide whatever damaged it, so only a missing manifest is written
+            Err(e) => return report.add(check, Status::Fail(format!(
+                "unreadable manifest {}: {:#} (reinstall or use --update)", path.display(), e
+            ))),
+        },
+        // Installs made before manifests existed are not broken, just unverifiable
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let why = "no install manifest";
+            if !repair {
+                return report.add(check, Status::Skip(String::from(why)));
+            }
+            return match env.write_manifest(installed, &actual) {
+                Ok(()) => report.add(check, Status::Fixed(format!("{}; wrote a new one", why))),
+                Err(e) => report.add(check, Status::Fail(format!("{}; could not write one: {:#}", why, e))),
+            };
+        }
+        Err(e) => return report.add(check, Status::Fail(format!("could not read {}: {}", path.display(), e))),
+    };
+    if manifest.hash == actual {
+        return report.add(check, Status::Pass);
+    }
+    if !repair {
+        return report.add(check, Status::Fail(format!(
+            "blake3 {} does not match the manifest's {} (modified or corrupted)", actual, manifest.hash
+        )));
+    }
+    match env.restore_from_current_exe(&installed.exe, Some(&manifest.hash)) {
+        Ok(()) => report.add(check, Status::Fixed(format!("restored version {} from this executable", manifest.version))),
+        Err(e) => report.add(check, Status::Fail(format!(
+            "blake3 {} does not match the manifest's {} and could not be restored: {:#}", actual, manifest.hash, e
+        ))),
+    }
+}
+
+// Not repaired: running as a scheduled task instead changes how the controller starts (and what it can see),
+// so that is left to the user
+fn check_service_host(env: &dyn VerifyEnv, installed: &InstalledConfig, report: &mut Report) {
+    let check = "wallpaperservice32.exe is present";
+    if installed.startup != StartupMode::Service {
+        return report.add(check, Status::Skip(String::from("installed as a scheduled task")));
+    }
+    if env.is_file(Path::new(WALLPAPER_SERVICE_32_PATH)) {
+        return report.add(check, Status::Pass);
+    }
+    report.add(check, Status::Fail(format!(
+        "{} is missing; enable Wallpaper Engine's High Priority mode, or switch to a scheduled task with --reconfigure",
+        WALLPAPER_SERVICE_32_PATH
+    )));
+}
+
+fn check_wallpaper_engine(env: &dyn VerifyEnv, installed: &InstalledConfig, cli: Option<&Cli>, report: &mut Report, repair: bool) {
+    let check = "Wallpaper Engine executables exist";
+    let Some(cli) = cli else {
+        return report.add(check, Status::Skip(String::from("registered arguments are invalid")));
+    };
+    let (_, result) = env.wallpaper_engine(cli.wallpaper_engine_path.as_deref(), cli.bit64);
+    let Err(e) = result else {
+        return report.add(check, Status::Pass);
+    };
+
+    // A configured folder that went away (e.g. Steam library moved) can fall back to detection
+    let (detected, detection) = env.wallpaper_engine(None, cli.bit64);
+    if repair && cli.wallpaper_engine_path.is_some() && detection.is_ok() {
+        let mut fixed = cli.clone();
+        fixed.wallpaper_engine_path = None;
+        return match env.reregister(installed, &fixed) {
+            Ok(()) => report.add(check, Status::Fixed(format!("{}; now detected at {}", e, detected))),
+            Err(err) => report.add(check, Status::Fail(format!("{}; re-registering failed: {}", e, err))),
+        };
+    }
+    report.add(check, Status::Fail(e));
+}
+
+/// Reads and repairs the real installation
+pub struct SystemVerifyEnv;
+
+impl VerifyEnv for SystemVerifyEnv {
+    fn installed_config(&self) -> Option<InstalledConfig> {
+        read_installed_config()
+    }
+
+    fn task_registered(&self) -> bool {
+        task_exe_path().is_some()
+    }
+
+    fn remove_task(&self) -> Result<()> {
+        remove_existing_task_if_any()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn file_hash(&self, path: &Path) -> Result<String> {
+        Ok(compute_file_hash(path)?.to_hex().to_string())
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    // Only a byte-identical copy may replace a corrupted install, so the manifest stays truthful
+    fn restore_from_current_exe(&self, target: &Path, expected_hash: Option<&str>) -> Result<()> {
+        let current = std::env::current_exe()?;
+        if current == target {
+            return Err(anyhow!("this is the installed executable"));
+        }
+        if let Some(expected) = expected_hash {
+            if self.file_hash(&current)? != expected {
+                return Err(anyhow!("this executable is not the installed version; reinstall or use --update"));
+            }
+        }
+        copy_executable(&current, target)
+    }
+
+    fn write_manifest(&self, installed: &InstalledConfig, hash: &str) -> Result<()> {
+        let dir = installed.exe.parent().ok_or_else(|| anyhow!("executable has no parent folder"))?;
+        let contents = fs::read(&installed.exe)?;
+        let version = Version::embedded_in(&contents)
+            .ok_or_else(|| anyhow!("the executable predates version metadata"))?;
+        let manifest = InstallManifest {
+            version: version.to_string(),
+            hash: hash.to_string(),
+            installed_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            args: installed.args.iter().map(|a| a.to_string_lossy().to_string()).collect(),
+            previous: None,
+        };
+        fs::write(manifest_path(dir), manifest.to_json())?;
+        Ok(())
+    }
+
+    fn wallpaper_engine(&self, explicit_dir: Option<&str>, prefer_64bit: bool) -> (String, Result<(), String>) {
+        let resolved = resolve_wallpaper_engine(explicit_dir, prefer_64bit);
+        let result = validate_wallpaper_engine_dir(Path::new(&resolved.dir), resolved.use_64bit).map_err(|e| e.to_string());
+        (resolved.dir, result)
+    }
+
+    // Keeps the task settings that are registered now; only the runtime arguments change
+    fn reregister(&self, installed: &InstalledConfig, cli: &Cli) -> Result<(), InstallError> {
+        let mut cli = cli.clone();
+        if let Ok(current) = current_settings(installed) {
+            cli.task_triggers = current.task_triggers;
+            cli.task_delay = current.task_delay;
+            cli.task_restart_count = current.task_restart_count;
+            cli.task_restart_interval = current.task_restart_interval;
+        }
+        apply_in_place(installed, &cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    use crate::install::manifest::MANIFEST_FILE_NAME;
+
+    const CURRENT_HASH: &str = "current build";
+
+    /// Files hash to their contents; the running executable hashes to `CURRENT_HASH`
+    struct FakeEnv {
+        installed: Option<InstalledConfig>,
+        task_registered: bool,
+        files: RefCell<HashMap<PathBuf, String>>,
+        configured_we_exists: bool,
+        detected_we_exists: bool,
+        /// What was changed, in order
+        repairs: RefCell<Vec<String>>,
+    }
+
+    impl FakeEnv {
+        fn new(startup: StartupMode) -> Self {
+            let installed = InstalledConfig {
+                startup,
+                exe: exe(),
+                args: vec![OsString::from("-w"), OsString::from(r"D:\we")],
+                task_xml: None,
+            };
+            let mut files = HashMap::new();
+            files.insert(exe(), String::from(CURRENT_HASH));
+            files.insert(manifest(), manifest_json(CURRENT_HASH));
+            files.insert(PathBuf::from(WALLPAPER_SERVICE_32_PATH), String::new());
+            Self {
+                installed: Some(installed),
+                task_registered: false,
+                files: RefCell::new(files),
+                configured_we_exists: true,
+                detected_we_exists: true,
+                repairs: RefCell::default(),
+            }
+        }
+
+        fn with_file(self, path: PathBuf, contents: Option<&str>) -> Self {
+            match contents {
+                Some(contents) => self.files.borrow_mut().insert(path, contents.to_string()),
+                None => self.files.borrow_mut().remove(&path),
+            };
+            self
+        }
+
+        fn repaired(&self, what: String) {
+            self.repairs.borrow_mut().push(what);
+        }
+    }
+
+    impl VerifyEnv for FakeEnv {
+        fn installed_config(&self) -> Option<InstalledConfig> { self.installed.clone() }
+        fn task_registered(&self) -> bool { self.task_registered }
+        fn remove_task(&self) -> Result<()> {
+            self.repaired(String::from("removed the task"));
+            Ok(())
+        }
+        fn is_file(&self, path: &Path) -> bool { self.files.borrow().contains_key(path) }
+        fn file_hash(&self, path: &Path) -> Result<String> {
+            self.files.borrow().get(path).cloned().ok_or_else(|| anyhow!("{} not found", path.display()))
+        }
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            self.files.borrow().get(path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+        fn restore_from_current_exe(&self, target: &Path, expected_hash: Option<&str>) -> Result<()> {
+            if expected_hash.is_some_and(|hash| hash != CURRENT_HASH) {
+                return Err(anyhow!("not the installed version"));
+            }
+            self.files.borrow_mut().insert(target.to_path_buf(), String::from(CURRENT_HASH));
+            self.repaired(format!("restored {}", target.display()));
+            Ok(())
+        }
+        fn write_manifest(&self, _installed: &InstalledConfig, hash: &str) -> Result<()> {
+            self.files.borrow_mut().insert(manifest(), manifest_json(hash));
+            self.repaired(String::from("wrote the manifest"));
+            Ok(())
+        }
+        fn wallpaper_engine(&self, explicit_dir: Option<&str>, _prefer_64bit: bool) -> (String, Result<(), String>) {
+            match explicit_dir {
+                Some(dir) => (dir.to_string(), self.configured_we_exists.then_some(()).ok_or(format!("{} is missing", dir))),
+                None => (String::from(r"C:\Steam\we"), self.detected_we_exists.then_some(()).ok_or(String::from("not found"))),
+            }
+        }
+        fn reregister(&self, _installed: &InstalledConfig, cli: &Cli) -> Result<(), InstallError> {
+            self.repaired(format!("re-registered with -w {:?}", cli.wallpaper_engine_path));
+            Ok(())
+        }
+    }
+
+    fn exe() -> PathBuf {
+        PathBuf::from("wpc").join(INSTALLED_EXE_NAME)
+    }
+
+    fn manifest() -> PathBuf {
+        PathBuf::from("wpc").join(MANIFEST_FILE_NAME)
+    }
+
+    fn manifest_json(hash: &str) -> String {
+        InstallManifest {
+            version: String::from("0.2.1"),
+            hash: hash.to_string(),
+            installed_at: 0,
+            args: Vec::new(),
+            previous: None,
+        }.to_json()
+    }
+
+    /// The checklist as printed, without details
+    fn checklist(env: &FakeEnv, repair: bool) -> Vec<String> {
+        verify(env, repair).checks.iter().map(|(check, status)| format!("{} {}", status, check)).collect()
+    }
+
+    fn status_of(env: &FakeEnv, repair: bool, check: &str) -> String {
+        verify(env, repair).checks.into_iter()
+            .find(|(name, _)| name.starts_with(check))
+            .map(|(_, status)| format!("{:?}", status))
+            .unwrap_or_else(|| panic!("no '{}' check", check))
+    }
+
+    #[test]
+    fn a_healthy_service_install_passes_every_check() {
+        let env = FakeEnv::new(StartupMode::Service);
+        assert_eq!(checklist(&env, false), [
+            String::from("[PASS] Startup service/task is registered"),
+            String::from("[PASS] Only one of service and task is registered"),
+            String::from("[PASS] Registered arguments are valid"),
+            format!("[PASS] Executable exists ({})", exe().display()),
+            String::from("[PASS] Executable matches the install manifest"),
+            String::from("[PASS] wallpaperservice32.exe is present"),
+            String::from("[PASS] Wallpaper Engine executables exist"),
+        ]);
+        assert_eq!(verify(&env, true).failures(), 0);
+        assert!(env.repairs.borrow().is_empty());
+    }
+
+    #[test]
+    fn nothing_installed_is_a_single_failure() {
+        let env = FakeEnv { installed: None, ..FakeEnv::new(StartupMode::Service) };
+        assert_eq!(checklist(&env, true), ["[FAIL] Startup service/task is registered"]);
+    }
+
+    #[test]
+    fn task_installs_skip_the_service_checks() {
+        let env = FakeEnv { task_registered: true, ..FakeEnv::new(StartupMode::Task) };
+        let checks = checklist(&env, false);
+        assert!(!checks.iter().any(|c| c.contains("Only one of")), "{:?}", checks);
+        assert!(checks.contains(&String::from("[SKIP] wallpaperservice32.exe is present")));
+
+        let env = FakeEnv::new(StartupMode::Service).with_file(PathBuf::from(WALLPAPER_SERVICE_32_PATH), None);
+        assert!(status_of(&env, true, "wallpaperservice32.exe").starts_with("Fail"));
+    }
+
+    #[test]
+    fn a_task_next_to_the_service_is_removed_on_repair() {
+        let env = FakeEnv { task_registered: true, ..FakeEnv::new(StartupMode::Service) };
+        assert!(status_of(&env, false, "Only one of").starts_with("Fail"));
+        assert!(env.repairs.borrow().is_empty());
+        assert!(status_of(&env, true, "Only one of").starts_with("Fixed"));
+        assert_eq!(*env.repairs.borrow(), ["removed the task"]);
+    }
+
+    #[test]
+    fn unreadable_manifests_are_reported_and_left_alone() {
+        let env = FakeEnv::new(StartupMode::Service).with_file(manifest(), Some("{ truncated"));
+        let status = status_of(&env, true, "Executable matches");
+        assert!(status.starts_with("Fail(\"unreadable manifest"), "{}", status);
+        assert!(env.repairs.borrow().is_empty());
+        assert_eq!(env.files.borrow()[&manifest()], "{ truncated");
+    }
+
+    #[test]
+    fn missing_manifests_are_only_written_on_repair() {
+        let env = FakeEnv::new(StartupMode::Service).with_file(manifest(), None);
+        assert!(status_of(&env, false, "Executable matches").starts_with("Skip"));
+        assert!(env.repairs.borrow().is_empty());
+        assert!(status_of(&env, true, "Executable matches").starts_with("Fixed"));
+        assert_eq!(*env.repairs.borrow(), ["wrote the manifest"]);
+        assert!(status_of(&env, false, "Executable matches").starts_with("Pass"));
+    }
+
+    #[test]
+    fn modified_executables_are_restored_only_from_the_same_build() {
+        let env = FakeEnv::new(StartupMode::Service).with_file(exe(), Some("patched"));
+        assert!(status_of(&env, false, "Executable matches").starts_with("Fail"));
+        assert!(status_of(&env, true, "Executable matches").starts_with("Fixed"));
+
+        let env = FakeEnv::new(StartupMode::Service)
+            .with_file(exe(), Some("patched"))
+            .with_file(manifest(), Some(&manifest_json("another build")));
+        assert!(status_of(&env, true, "Executable matches").starts_with("Fail"));
+        assert!(env.repairs.borrow().is_empty());
+    }
+
+    #[test]
+    fn missing_executables_are_restored_on_repair() {
+        let env = FakeEnv::new(StartupMode::Service).with_file(exe(), None);
+        assert_eq!(checklist(&env, false)[3..5], [
+            format!("[FAIL] Executable exists ({})", exe().display()),
+            String::from("[SKIP] Executable matches the install manifest"),
+        ]);
+        assert_eq!(checklist(&env, true)[3..5], [
+            format!("[FIXED] Executable exists ({})", exe().display()),
+            String::from("[PASS] Executable matches the install manifest"),
+        ]);
+    }
+
+    #[test]
+    fn a_vanished_wallpaper_engine_folder_falls_back_to_detection() {
+        let env = FakeEnv { configured_we_exists: false, ..FakeEnv::new(StartupMode::Service) };
+        assert!(status_of(&env, false, "Wallpaper Engine").starts_with("Fail"));
+        let status = status_of(&env, true, "Wallpaper Engine");
+        assert!(status.contains(r"now detected at C:\\Steam\\we"), "{}", status);
+        assert_eq!(*env.repairs.borrow(), ["re-registered with -w None"]);
+
+        let env = FakeEnv { configured_we_exists: false, detected_we_exists: false, ..FakeEnv::new(StartupMode::Service) };
+        assert!(status_of(&env, true, "Wallpaper Engine").starts_with("Fail"));
+        assert!(env.repairs.borrow().is_empty());
+    }
+
+    #[test]
+    fn invalid_arguments_skip_the_wallpaper_engine_check() {
+        let mut env = FakeEnv::new(StartupMode::Service);
+        if let Some(installed) = &mut env.installed {
+            installed.args = vec![OsString::from("--threshold"), OsString::from("lots")];
+        }
+        let checks = checklist(&env, false);
+        assert!(checks.contains(&String::from("[FAIL] Registered arguments are valid")));
+        assert!(checks.contains(&String::from("[SKIP] Wallpaper Engine executables exist")));
+    }
+}
@@ -1,18 +1,33 @@
 #![windows_subsystem = "windows"]
 
+mod arbiter;
 mod cli;
+mod config;
+mod doctor;
+mod error;
+mod explain;
 mod monitor;
+mod paths;
+mod profiles;
+mod reload;
+mod selector;
+mod steam;
+mod version;
 mod wallpaper;
 mod install;
 
-use std::{env, thread};
+use std::{env, fs, thread};
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::process::Command;
 use std::time::Duration;
-use clap::Parser;
 use tokio::signal;
 use tracing::{info, error, warn};
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{EnvFilter, Registry};
+use tracing_subscriber::fmt::format::{DefaultFields, Format};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use sentry::integrations::tracing::EventFilter;
@@ -23,19 +38,42 @@ use single_instance::SingleInstance;
 use windows_elevate::{check_elevated, elevate};
 use anyhow::{Result, anyhow};
 
-use cli::{Cli, parse_monitor_indices};
-use install::handle_installation;
+use cli::{Cli, Invocation, Mode};
+use config::{ArgsOrigin, Settings};
+use error::{AppError, ExitCode};
+use explain::{DecisionHistory, DecisionLog, DECISION_LOG_CAPACITY};
+use install::{filtered_passthrough_args, handle_installation};
 use monitor::VisibilityMonitor;
+use profiles::ActiveProfile;
+use steam::{resolve_wallpaper_engine, validate_wallpaper_engine_dir, PathSource};
 use wallpaper::WallpaperController;
-use crate::install::exit_blocking;
-use crate::install::tui::run_install_tui_and_relaunch;
+use crate::install::{exit_blocking, INSTALLED_EXE_NAME};
+use crate::install::forward::decode_forwarded_args;
+use crate::install::answers::Answers;
+use crate::install::reconfigure::{handle_reconfigure, read_installed_config};
+use crate::install::verify::handle_verify_install;
+use crate::install::tui::{relaunch_args, run_install_tui_and_relaunch};
+use crate::install::uninstall::handle_uninstall;
+use crate::install::update::{handle_check_update, handle_update};
+
+// The log file is moved aside once it grows past this, so at most about twice this is kept
+const LOG_FILE_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
+// How often the profile rules are checked against the clock and the running processes
+const RULE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+type LogFileLayer = tracing_subscriber::fmt::Layer<Registry, DefaultFields, Format, Mutex<File>>;
 
 #[tokio::main(worker_threads = 2)]
 async fn main() {
-    let raw_args: Vec<String> = std::env::args().collect();
+    let args: Vec<String> = std::env::args().collect();
+    // Flags the service/task was registered with rank below the config file (see config)
+    let args_origin = ArgsOrigin::of(&args);
+    // Service installs receive some arguments encoded (see install::forward)
+    let raw_args: Vec<String> = decode_forwarded_args(args);
     let in_silent_mode = raw_args.iter().any(|a| a == "-silent");
 
-    let mut filtered_args: Vec<String> = raw_args
+    let filtered_args: Vec<String> = raw_args
         .clone()
         .into_iter()
         .filter(|a| !["-safe", "-silent", "-service"].contains(&a.as_str()))
@@ -52,23 +90,43 @@ async fn main() {
             true
         };
 
-    let mut cli = Cli::parse_from(&filtered_args);
+    let parsed = cli::parse(&filtered_args);
+    let Invocation { mode, mut cli, matches, mut flat_args } = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) if !e.use_stderr() => e.exit(), // --help and --version
+        Err(e) => {
+            e.print().ok();
+            exit_blocking(ExitCode::InvalidArguments.code());
+        }
+    };
+
+    let paths = paths::init(cli.portable);
+    // Started without arguments: the interactive installer, or just a run for a portable copy
+    let mode = mode.unwrap_or(if paths.is_portable() { Mode::Run } else { Mode::Install });
+    let install_tui = mode == Mode::Install && (cli.install_tui || !cli.has_install_target());
 
-    // Sort the filtered args for unique key
-    filtered_args.sort();
+    let settings = match config::load(&cli, &matches, args_origin, paths) {
+        Ok(settings) => settings,
+        Err(e) => fail_before_logging(e.into(), in_silent_mode),
+    };
+    // The config file is layered over the command line as typed again when it changes
+    let launch_cli = cli.clone();
+    settings.apply(&mut cli);
+
+    // The service/task gets the flat form; keep it before sorting
+    let passthrough_args: Vec<OsString> = flat_args[1..].iter().map(OsString::from).collect();
+
+    // Sort the flat args for unique key, so both forms of the same command line share one instance
+    flat_args.sort();
 
     // Create a unique mutex name based on sorted args
     let mut hasher = DefaultHasher::new();
-    filtered_args[1..].join("|").hash(&mut hasher);
+    flat_args[1..].join("|").hash(&mut hasher);
     let instance_mutex = SingleInstance::new(&format!("Global\\WallpaperController_{}", hasher.finish())).unwrap();
 
     if !instance_mutex.is_single() {
-        if !in_silent_mode {
-            eprintln!("Another instance with the same arguments is already running.");
-            drop(instance_mutex);
-            exit_blocking(5);
-        }
-        return;
+        drop(instance_mutex);
+        fail_before_logging(AppError::AlreadyRunning, in_silent_mode);
     }
 
     let _guard: ClientInitGuard;
@@ -91,7 +149,11 @@ async fn main() {
         .add_directive("sentry_core=warn".parse().unwrap())
         .add_directive("sentry_tracing=warn".parse().unwrap());
 
+    // Only the monitoring run writes a log file (see start_log_file); one-shot commands print to the console
+    let (log_file_layer, log_file_handle) = tracing_subscriber::reload::Layer::new(None::<LogFileLayer>);
+
     tracing_subscriber::registry()
+        .with(log_file_layer)
         .with(filter, )
         .with(tracing_subscriber::fmt::layer().with_ansi(ansi_colors).without_time())
         .with(
@@ -104,135 +166,439 @@ async fn main() {
         )
         .init();
 
+    if cli.print_config {
+        settings.print();
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if mode == Mode::Status {
+        let result = match paths.status_file() {
+            Some(path) => profiles::print_status(&path),
+            None => Err(anyhow!("Could not determine the status file location")),
+        };
+        if let Err(e) = result {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if let Some(name) = &cli.switch_profile {
+        let result = match (paths.profile_request_file(), paths.status_file()) {
+            (Some(request_file), Some(status_file)) => profiles::request_switch(&request_file, &status_file, &settings, name),
+            _ => Err(anyhow!("Could not determine the status file location")),
+        };
+        if let Err(e) = result {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
     // Check if the user asked to list monitors
-    if cli.list_monitors {
+    if mode == Mode::Monitors {
         print_monitor_list();
-        exit_blocking(0);
+        exit_blocking(ExitCode::Success.code());
     }
 
-    if (raw_args.len() <= 1) || cli.install_tui {
-        elevate_and_kill_others(instance_mutex);
+    if cli.doctor {
+        match doctor::run_doctor(&doctor::SystemEnv, &cli) {
+            0 => exit_blocking(ExitCode::Success.code()),
+            failed => fail(AppError::DoctorFailed(failed)),
+        }
+    }
+
+    let trace_file = cli.trace_file.as_ref().map(PathBuf::from).or_else(|| paths.trace_file());
+
+    if let Some(n) = cli.explain_last {
+        let result = match &trace_file {
+            Some(path) => explain::print_explanations(path, n),
+            None => Err(anyhow!("Could not determine the decision trace location; pass --trace-file")),
+        };
+        if let Err(e) = result {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if cli.save_answers.is_some() && !install_tui {
+        fail(AppError::InvalidArguments("--save-answers can only be used with the interactive installer".into()));
+    }
+
+    // A portable copy runs from wherever it is and leaves nothing registered behind
+    if paths.is_portable() && (mode == Mode::Install || mode == Mode::Uninstall
+        || cli.reconfigure || cli.repair || cli.update)
+    {
+        fail(AppError::InvalidArguments(format!(
+            "portable mode doesn't install, change or remove a service or task; delete the '{}' file next to the executable (and drop --portable) first",
+            paths::PORTABLE_MARKER_FILE
+        )));
+    }
+
+    // The wizard itself needs no administrator rights; the install it relaunches asks for them if its choices do
+    if install_tui {
         if let Err(e) = run_install_tui_and_relaunch(cli) {
             error!("Installation aborted: {}", e);
         }
 
-        std::process::exit(0);
+        std::process::exit(ExitCode::Success.code());
     }
 
-    if cli.install_dir.is_some() || cli.add_startup_service || cli.add_startup_task {
-        if cli.add_startup_service && cli.add_startup_task {
-            error!("Cannot use both --add-startup-service and --add-startup-task");
-            exit_blocking(8);
+    if cli.verify_install || cli.repair {
+        // Checking only reads; repairs change the service/task and installed files
+        if cli.repair {
+            ensure_elevated(instance_mutex);
+        }
+        if let Err(e) = handle_verify_install(cli.repair) {
+            fail(e.into());
         }
+        exit_blocking(ExitCode::Success.code());
+    }
 
+    if cli.reconfigure {
+        // The running controller is restarted only once the changes are applied. The service and an elevated
+        // task need administrator rights to change, a per-user task doesn't; when nothing can be read
+        // unelevated, the elevated process looks again
+        if read_installed_config().is_none_or(|installed| installed.needs_elevation()) {
+            ensure_elevated(instance_mutex);
+        }
+        if let Err(e) = handle_reconfigure(cli) {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if mode == Mode::Uninstall {
+        if cli.add_startup_service || cli.add_startup_task {
+            fail(AppError::InvalidArguments("--uninstall cannot be combined with --add-startup-service or --add-startup-task".into()));
+        }
         elevate_and_kill_others(instance_mutex);
-        handle_installation(&cli);
-        return;
+        if let Err(e) = handle_uninstall(&cli).await {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
     }
 
-    // Parse monitor IDs
-    let monitor_indices = parse_monitor_indices(&cli.monitors);
+    if cli.update || cli.check_update {
+        if cli.add_startup_service || cli.add_startup_task {
+            fail(AppError::InvalidArguments("--update and --check-update cannot be combined with --add-startup-service or --add-startup-task".into()));
+        }
+        // Checking only reads; replacing the installed copy and restarting the service/task needs elevation
+        let result = if cli.update {
+            elevate_and_kill_others(instance_mutex);
+            handle_update(&cli)
+        } else {
+            handle_check_update(&cli)
+        };
+        if let Err(e) = result {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if let Some(path) = cli.install_from.clone() {
+        if cli.install_dir.is_some() || cli.add_startup_service || cli.add_startup_task {
+            fail(AppError::InvalidArguments("--install-from cannot be combined with --install-dir, --add-startup-service or --add-startup-task".into()));
+        }
+        // Validate before elevating so mistakes are reported in this console
+        match Answers::load(Path::new(&path)) {
+            Ok(answers) => answers.apply(&mut cli),
+            Err(e) => fail(e.into()),
+        }
+        prepare_install(&cli, instance_mutex);
+        // The service/task gets the same command line the interactive installer would have given it
+        if let Err(e) = handle_installation(&cli, &filtered_passthrough_args(relaunch_args(&cli))) {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if cli.install_dir.is_some() || cli.add_startup_service || cli.add_startup_task || cli.per_user || cli.install_dry_run.is_some() {
+        if cli.add_startup_service && cli.add_startup_task {
+            fail(AppError::InvalidArguments("cannot use both --add-startup-service and --add-startup-task".into()));
+        }
+        if cli.per_user {
+            if cli.add_startup_service {
+                fail(AppError::InvalidArguments("--per-user installs a scheduled task and cannot be combined with --add-startup-service".into()));
+            }
+            cli.add_startup_task = true;
+        }
+
+        // Catch a wrong Wallpaper Engine path now rather than on the first threshold crossing after startup
+        if let Some(dir) = &cli.wallpaper_engine_path {
+            let resolved = resolve_wallpaper_engine(Some(dir), cli.bit64);
+            if let Err(e) = validate_wallpaper_engine_dir(Path::new(&resolved.dir), resolved.use_64bit) {
+                fail(e.into());
+            }
+        }
+
+        prepare_install(&cli, instance_mutex);
+        if let Err(e) = handle_installation(&cli, &filtered_passthrough_args(passthrough_args)) {
+            fail(e.into());
+        }
+        exit_blocking(ExitCode::Success.code());
+    }
+
+    if let Some(path) = paths.log_file() {
+        match open_log_file(&path, paths.rotated_log_file().as_deref()) {
+            Ok(file) => {
+                let layer = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(Mutex::new(file));
+                if let Err(e) = log_file_handle.reload(Some(layer)) {
+                    warn!("Could not start writing {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => warn!("Could not open log file {}: {:#}", path.display(), e),
+        }
+    }
+    if paths.is_portable() {
+        info!("Portable mode: keeping logs and state in {}", paths.data_dir().map(|d| d.display().to_string()).unwrap_or_default());
+    }
+
+    // Locate Wallpaper Engine and pick the 32/64-bit executable
+    let wallpaper_engine = resolve_wallpaper_engine(cli.wallpaper_engine_path.as_deref(), cli.bit64);
+    info!("Using Wallpaper Engine at {} ({}, {}-bit)",
+          wallpaper_engine.dir,
+          match wallpaper_engine.source {
+              PathSource::CommandLine => "from command line",
+              PathSource::Detected => "detected",
+              PathSource::Default => "default location",
+          },
+          if wallpaper_engine.use_64bit { 64 } else { 32 });
 
     // Create the wallpaper controller with the 64-bit flag
-    let controller = WallpaperController::new(cli.wallpaper_engine_path, cli.bit64);
+    let mut controller = match WallpaperController::new(wallpaper_engine.dir, wallpaper_engine.use_64bit, cli.dry_run) {
+        Ok(controller) => controller,
+        Err(e) => {
+            let err = AppError::from(e);
+            error!("{}", err);
+            std::process::exit(err.exit_code().code());
+        }
+    };
+    if cli.dry_run {
+        info!("Dry run: Wallpaper Engine will not be touched");
+    }
+
+    // A request left for a controller that has since stopped is stale
+    let profile_request_file = paths.profile_request_file();
+    if let Some(path) = &profile_request_file {
+        let _ = fs::remove_file(path);
+    }
+    let mut manual_profile: Option<String> = None;
+    let mut active_profile = profiles::select_system_profile(&settings, None).await;
+    info!("Active profile: {}", profiles::describe(active_profile.as_ref()));
+    let active_name = active_profile.as_ref().map(|a| a.name.as_str());
+    controller.set_pause_action(settings.pause_action(active_name));
 
     // Create and start visibility monitoring
-    let mut monitor = VisibilityMonitor::new(
-        controller,
-        cli.per_monitor,
-        cli.threshold.unwrap_or(20),
-        monitor_indices,
-    );
-
-    if monitor.start_monitoring(cli.update_rate).await {
-        info!("Started monitoring desktop visibility");
-
-        if let Err(err) = signal::ctrl_c().await {
-            error!("Unable to listen for shutdown signal: {}", err);
-        } else {
-            info!("Ctrl+C received");
+    let decisions = DecisionLog::new(DECISION_LOG_CAPACITY, trace_file);
+    let history = decisions.history();
+    let mut monitor = VisibilityMonitor::new(controller, settings.monitor_settings(active_name), decisions);
+
+    if let Err(e) = monitor.start_monitoring().await {
+        error!("Failed to start monitoring task: {}", e);
+        std::process::exit(AppError::from(e).exit_code().code());
+    }
+    info!("Started monitoring desktop visibility");
+
+    let status_file = paths.status_file();
+    write_status(status_file.as_deref(), &settings, active_profile.as_ref(), &history);
+    let mut status_newest_decision = newest_decision(&history);
+
+    let mut reloads = reload::spawn_watcher(launch_cli, matches, args_origin, paths, &settings);
+    let mut settings = settings;
+    let mut rule_tick = tokio::time::interval(RULE_CHECK_INTERVAL);
+    rule_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            result = signal::ctrl_c() => {
+                match result {
+                    Ok(()) => info!("Ctrl+C received"),
+                    Err(err) => error!("Unable to listen for shutdown signal: {}", err),
+                }
+                break;
+            }
+            Some(new) = async {
+                match &mut reloads {
+                    Some(rx) => rx.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let changes = reload::changes(&settings, &new);
+                if changes.is_empty() {
+                    continue;
+                }
+                if manual_profile.as_ref().is_some_and(|name| !new.profiles.contains_key(name)) {
+                    warn!("Profile '{}' was removed from the config file, letting the rules pick the profile again",
+                          manual_profile.take().unwrap_or_default());
+                }
+                let selected = profiles::select_system_profile(&new, manual_profile.as_deref()).await;
+                match reload::apply(&mut monitor, &settings, &new, selected.as_ref()).await {
+                    Ok(effective) => {
+                        info!("Reloaded the config file: {}", changes.join(", "));
+                        if selected != active_profile {
+                            info!("Switched to profile {}", profiles::describe(selected.as_ref()));
+                        }
+                        settings = effective;
+                        active_profile = selected;
+                        write_status(status_file.as_deref(), &settings, active_profile.as_ref(), &history);
+                    }
+                    Err(e) => warn!("Rejected the changed config file, keeping the current settings: {}", e),
+                }
+            }
+            _ = rule_tick.tick() => {
+                match profile_request_file.as_deref().and_then(profiles::take_switch_request) {
+                    Some(name) if name == profiles::AUTOMATIC => {
+                        info!("Letting the rules pick the profile again");
+                        manual_profile = None;
+                    }
+                    Some(name) if settings.profiles.contains_key(&name) => manual_profile = Some(name),
+                    Some(name) => warn!("Ignoring the request to switch to profile '{}', which is not in the config file", name),
+                    None => {}
+                }
+                let selected = profiles::select_system_profile(&settings, manual_profile.as_deref()).await;
+                let mut switched = false;
+                if selected != active_profile {
+                    match profiles::activate(&mut monitor, &settings, selected.as_ref()).await {
+                        Ok(()) => {
+                            info!("Switched to profile {}", profiles::describe(selected.as_ref()));
+                            active_profile = selected;
+                            switched = true;
+                        }
+                        Err(e) => warn!("Could not switch to profile {}: {}", profiles::describe(selected.as_ref()), e),
+                    }
+                }
+                // The status file also carries the decision history, so it is refreshed after new decisions
+                let newest = newest_decision(&history);
+                if switched || newest != status_newest_decision {
+                    write_status(status_file.as_deref(), &settings, active_profile.as_ref(), &history);
+                    status_newest_decision = newest;
+                }
+            }
         }
+    }
 
-        info!("Stopping monitoring task...");
-        if monitor.stop_monitoring().await {
-            info!("Stopped monitoring task");
-        } else {
-            error!("Failed to stop monitoring task");
+    info!("Stopping monitoring task...");
+    if let Err(e) = monitor.stop_monitoring().await {
+        error!("Failed to stop monitoring task cleanly: {}", e);
+        std::process::exit(AppError::from(e).exit_code().code());
+    }
+    info!("Stopped monitoring task");
+    if let Some(path) = &status_file {
+        let _ = fs::remove_file(path);
+    }
+
+    if cli.dry_run {
+        let controller = monitor.get_controller().await;
+        let planned: Vec<String> = controller.planned_commands().map(|c| c.to_string()).collect();
+        info!("Dry run planned {} command(s):", planned.len());
+        for command in planned {
+            info!("  {}", command);
+        }
+    }
+}
+
+/// Publishes the active profile and settings for --status; a failure only costs --status its answer
+fn write_status(path: Option<&Path>, settings: &Settings, active: Option<&ActiveProfile>, history: &DecisionHistory) {
+    if let Some(path) = path {
+        if let Err(e) = profiles::Status::new(settings, active, history.recent(DECISION_LOG_CAPACITY)).write(path) {
+            warn!("Could not write the status file: {:#}", e);
+        }
+    }
+}
+
+fn newest_decision(history: &DecisionHistory) -> Option<u64> {
+    history.recent(1).first().map(|decision| decision.timestamp_ms)
+}
+
+/// Opens the log file for appending, first moving it to `rotated` if it has grown too large
+fn open_log_file(path: &Path, rotated: Option<&Path>) -> Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if let (Some(rotated), Ok(metadata)) = (rotated, fs::metadata(path)) {
+        if metadata.len() > LOG_FILE_MAX_BYTES {
+            fs::rename(path, rotated)?;
         }
+    }
+    Ok(File::options().create(true).append(true).open(path)?)
+}
+
+/// Like `fail`, for errors that happen before logging is initialized
+fn fail_before_logging(err: AppError, in_silent_mode: bool) -> ! {
+    if !in_silent_mode {
+        eprintln!("{}", err);
+        exit_blocking(err.exit_code().code());
+    }
+    std::process::exit(err.exit_code().code());
+}
+
+/// Logs the error and exits with its documented exit code
+fn fail(err: AppError) -> ! {
+    error!("{}", err);
+    exit_blocking(err.exit_code().code());
+}
+
+/// Gets ready to run an install: elevates unless it is a dry run or only touches the current user's profile
+/// and tasks, and stops running instances
+fn prepare_install(cli: &Cli, instance_mutex: SingleInstance) {
+    // Previewing the plan only reads system state, so it needs neither elevation nor a clean slate
+    if cli.install_dry_run.is_some() {
+        return;
+    }
+    if cli.per_user {
+        kill_other_instances().ok();
     } else {
-        error!("Failed to start monitoring task");
+        elevate_and_kill_others(instance_mutex);
     }
 }
 
 fn elevate_and_kill_others(instance_mutex: SingleInstance) {
+    ensure_elevated(instance_mutex);
+    kill_other_instances().ok();
+}
+
+/// Restarts the process elevated (and exits this one) unless it already is
+fn ensure_elevated(instance_mutex: SingleInstance) {
     if !check_elevated().unwrap_or(false) {
         info!("Requesting administrator privileges...");
         info!("Process will continue in a new window");
         drop(instance_mutex);
 
         if let Err(e) = elevate() {
-            error!("Failed to elevate process: {:?}", e);
+            fail(AppError::Elevation(format!("{:?}", e)));
         }
 
-        std::process::exit(0); // Exit the non-elevated process
-    } else {
-        kill_other_instances().ok();
+        std::process::exit(ExitCode::Success.code()); // Exit the non-elevated process
     }
 }
 
 fn kill_other_instances() -> Result<()> {
-    // Determine the image name of the current executable
+    // The installed copy runs under its own name, so look for that as well as the current image name
+    // (like doctor's duplicate instance check)
     let this_exe = env::current_exe()?;
     let image_name = this_exe.file_name()
         .and_then(|s| s.to_str())
         .ok_or_else(|| anyhow!("Failed to determine current executable name"))?
         .to_string();
-
-    let this_pid = std::process::id();
-    info!("Attempting to terminate other running instances of {}...", image_name);
-
-    // Query tasklist for processes with the same image name in CSV for easier parsing -- somewhat hacky but works
-    let output = Command::new("tasklist")
-        .args(["/FI", &format!("IMAGENAME eq {}", image_name), "/FO", "CSV"])
-        .output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        warn!("tasklist failed while searching for other instances: {}", stderr);
-        return Ok(());
+    let mut image_names = vec![INSTALLED_EXE_NAME.to_string()];
+    if !image_name.eq_ignore_ascii_case(INSTALLED_EXE_NAME) {
+        image_names.push(image_name);
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    // Collect target PIDs (excluding self)
-    let mut target_pids: Vec<u32> = Vec::new();
-    for (i, line) in stdout.lines().enumerate() {
-        if i == 0 { continue; } // skip header
-        let trimmed = line.trim();
-        if trimmed.is_empty() { continue; }
-        // CSV fields quoted, expect: "Image Name","PID","Session Name","Session#","Mem Usage"
-        let parts: Vec<String> = trimmed.split(',')
-            .map(|s| s.trim().trim_matches('"').to_string())
-            .collect();
-        if parts.len() < 2 { continue; }
-        if let Ok(pid) = parts[1].parse::<u32>() {
-            if pid != this_pid { target_pids.push(pid); }
-        }
-    }
+    info!("Attempting to terminate other running instances of {}...", image_names.join(" and "));
 
+    let target_pids = list_other_instances(&image_names, "searching for other instances");
     if target_pids.is_empty() {
         return Ok(());
     }
 
     // First, try a graceful termination using taskkill without /F (no console tricks)
-    for pid in &target_pids {
+    for (pid, name) in &target_pids {
         let res = Command::new("taskkill")
             .args(["/PID", &pid.to_string()])
             .output();
         match res {
             Ok(out) => {
                 if out.status.success() {
-                    info!("Requested graceful termination for PID {} ({})", pid, image_name);
+                    info!("Requested graceful termination for PID {} ({})", pid, name);
                 } else {
                     let stderr = String::from_utf8_lossy(&out.stderr);
                     warn!("Graceful taskkill failed for PID {}: {}", pid, stderr);
@@ -246,45 +612,22 @@ fn kill_other_instances() -> Result<()> {
     thread::sleep(Duration::from_millis(2500));
 
     // Force-kill any remaining instances
-    let output2 = Command::new("tasklist")
-        .args(["/FI", &format!("IMAGENAME eq {}", image_name), "/FO", "CSV"])
-        .output()?;
-
-    if !output2.status.success() {
-        let stderr = String::from_utf8_lossy(&output2.stderr);
-        warn!("tasklist failed while verifying remaining instances: {}", stderr);
-        return Ok(());
-    }
-
-    let stdout2 = String::from_utf8_lossy(&output2.stdout);
     let mut killed_any = false;
-
-    for (i, line) in stdout2.lines().enumerate() {
-        if i == 0 { continue; }
-        let trimmed = line.trim();
-        if trimmed.is_empty() { continue; }
-        let parts: Vec<String> = trimmed
-            .split(',')
-            .map(|s| s.trim().trim_matches('"').to_string())
-            .collect();
-        if parts.len() < 2 { continue; }
-        if let Ok(pid) = parts[1].parse::<u32>() {
-            if pid == this_pid { continue; }
-            if !target_pids.contains(&pid) { continue; } // only those we targeted
-
-            let kill = Command::new("taskkill").args(["/PID", &pid.to_string(), "/F"]).output();
-            match kill {
-                Ok(res) => {
-                    if res.status.success() {
-                        info!("Force terminated process PID {} ({})", pid, image_name);
-                        killed_any = true;
-                    } else {
-                        let stderr = String::from_utf8_lossy(&res.stderr);
-                        warn!("Failed to force terminate PID {}: {}", pid, stderr);
-                    }
+    for (pid, name) in list_other_instances(&image_names, "verifying remaining instances") {
+        if !target_pids.contains(&(pid, name.clone())) { continue; } // only those we targeted
+
+        let kill = Command::new("taskkill").args(["/PID", &pid.to_string(), "/F"]).output();
+        match kill {
+            Ok(res) => {
+                if res.status.success() {
+                    info!("Force terminated process PID {} ({})", pid, name);
+                    killed_any = true;
+                } else {
+                    let stderr = String::from_utf8_lossy(&res.stderr);
+                    warn!("Failed to force terminate PID {}: {}", pid, stderr);
                 }
-                Err(e) => warn!("taskkill failed for PID {}: {}", pid, e),
             }
+            Err(e) => warn!("taskkill failed for PID {}: {}", pid, e),
         }
     }
 
@@ -296,6 +639,46 @@ fn kill_other_instances() -> Result<()> {
     Ok(())
 }
 
+/// PIDs (other than this process) running one of `image_names`, with the image name each runs as
+fn list_other_instances(image_names: &[String], purpose: &str) -> Vec<(u32, String)> {
+    let this_pid = std::process::id();
+    let mut instances = Vec::new();
+    for image_name in image_names {
+        // Query tasklist for processes with the image name in CSV for easier parsing -- somewhat hacky but works
+        let output = match Command::new("tasklist")
+            .args(["/FI", &format!("IMAGENAME eq {}", image_name), "/FO", "CSV"])
+            .output()
+        {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!("tasklist failed while {}: {}", purpose, stderr);
+                continue;
+            }
+            Err(e) => {
+                warn!("tasklist failed while {}: {}", purpose, e);
+                continue;
+            }
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for (i, line) in stdout.lines().enumerate() {
+            if i == 0 { continue; } // skip header
+            let trimmed = line.trim();
+            if trimmed.is_empty() { continue; }
+            // CSV fields quoted, expect: "Image Name","PID","Session Name","Session#","Mem Usage"
+            let parts: Vec<String> = trimmed.split(',')
+                .map(|s| s.trim().trim_matches('"').to_string())
+                .collect();
+            if parts.len() < 2 { continue; }
+            if let Ok(pid) = parts[1].parse::<u32>() {
+                if pid != this_pid { instances.push((pid, image_name.clone())); }
+            }
+        }
+    }
+    instances
+}
+
 fn print_monitor_list() {
     info!("Listing available monitors...");
 
@@ -1,42 +1,65 @@
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 use tracing::{info, debug, error, warn};
 use tokio::sync::{mpsc, Mutex};
 use libvisdesk::{LibVisInstance, MonitorVisibleInfo};
+use windows::core::PCWSTR;
+use windows::Win32::Graphics::Gdi::{EnumDisplayDevicesW, DISPLAY_DEVICEW, DISPLAY_DEVICE_PRIMARY_DEVICE};
 
+use crate::arbiter::{PauseRequest, Target, PRIORITY_VISIBILITY, SOURCE_VISIBILITY};
+use crate::error::MonitorError;
+use crate::explain::{
+    now_ms, Aggregation, Crossing, Decision, DecisionLog, MonitorSample, Outcome, RateLimit, DECISION_LOG_CAPACITY,
+};
+use crate::selector::{MonitorFacts, MonitorSelector};
 use crate::wallpaper::WallpaperController;
 
+// How often pending requests are re-evaluated when no visibility update arrives (lets requests expire)
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(1);
+
 // Define our own message type for the monitor channel
 enum MonitorMessage {
     VisibilityUpdate(Vec<MonitorVisibleInfo>),
+    Settings(MonitorSettings),
     Shutdown,
 }
 
+/// How visibility is compared against the threshold
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorSettings {
+    pub per_monitor: bool,
+    pub threshold: u8,
+    /// Resolved against the connected monitors whenever they change
+    pub monitors: MonitorSelector,
+    /// Weight of each monitor's area in global mode; monitors not listed weigh 1
+    pub weights: BTreeMap<i64, f64>,
+    /// Maximum update frequency of the visibility watcher
+    pub update_rate_ms: u64,
+}
+
 pub struct VisibilityMonitor {
     instance: LibVisInstance,
     controller: Arc<Mutex<WallpaperController>>,
-    per_monitor: bool,
-    threshold: u8,
-    monitor_indices: Option<Vec<i64>>,
+    settings: MonitorSettings,
     tx: Option<mpsc::Sender<MonitorMessage>>,
     running: bool,
+    decisions: Option<DecisionLog>,
 }
 
 impl VisibilityMonitor {
     pub fn new(
         controller: WallpaperController,
-        per_monitor: bool,
-        threshold: u8,
-        monitor_indices: Option<Vec<i64>>,
+        settings: MonitorSettings,
+        decisions: DecisionLog,
     ) -> Self {
         Self {
             instance: LibVisInstance::new(),
             controller: Arc::new(Mutex::new(controller)),
-            per_monitor,
-            threshold,
-            monitor_indices,
+            settings,
             tx: None,
             running: false,
+            decisions: Some(decisions),
         }
     }
     
@@ -45,10 +68,9 @@ impl VisibilityMonitor {
         self.controller.lock().await
     }
 
-    pub async fn start_monitoring(&mut self, throttle_ms: u64) -> bool {
+    pub async fn start_monitoring(&mut self) -> Result<(), MonitorError> {
         if self.running {
-            warn!("Already monitoring");
-            return false;
+            return Err(MonitorError::AlreadyRunning);
         }
 
         // Create a channel for communication
@@ -57,39 +79,53 @@ impl VisibilityMonitor {
 
         // Start the processor task
         let controller = Arc::clone(&self.controller);
-        let per_monitor = self.per_monitor;
-        let threshold = self.threshold;
-        let monitor_indices = self.monitor_indices.clone();
+        let settings = self.settings.clone();
+        let decisions = self.decisions.take()
+            .unwrap_or_else(|| DecisionLog::new(DECISION_LOG_CAPACITY, None));
 
         tokio::spawn(async move {
             Self::process_visibility_updates(
                 rx, 
                 controller, 
-                per_monitor, 
-                threshold
+                settings,
+                decisions,
             ).await;
         });
 
+        self.watch(self.settings.update_rate_ms)
+    }
+
+    /// Applies new settings to the running monitor without dropping active pause requests; the latest visibility
+    /// is compared against them right away
+    pub async fn update_settings(&mut self, settings: MonitorSettings) -> Result<(), MonitorError> {
+        if self.running && settings.update_rate_ms != self.settings.update_rate_ms {
+            // libvisdesk only takes the update rate when the watcher starts
+            if !self.instance.stop_watch_visible_area() {
+                return Err(MonitorError::WatcherStop);
+            }
+            self.running = false;
+            if let Err(e) = self.watch(settings.update_rate_ms) {
+                // Keep watching at the old rate
+                self.watch(self.settings.update_rate_ms)?;
+                return Err(e);
+            }
+        }
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(MonitorMessage::Settings(settings.clone())).await;
+        }
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Starts the libvisdesk watcher, forwarding its updates to the processor
+    fn watch(&mut self, throttle_ms: u64) -> Result<(), MonitorError> {
         // Set up the callback to forward messages to our channel
-        let tx_clone = self.tx.clone().unwrap();
+        let Some(tx_clone) = self.tx.clone() else { return Err(MonitorError::NotRunning) };
         let callback = move |monitors: &[MonitorVisibleInfo], _total_visible: i64, _total_area: i64, _: *mut std::ffi::c_void| {
-            // Filter monitors if specific indices were provided
-            let filtered_monitors = if let Some(indices) = &monitor_indices {
-                monitors.iter()
-                    .filter(|m| indices.contains(&m.monitor_index))
-                    .cloned()
-                    .collect::<Vec<_>>()
-            } else {
-                monitors.to_vec()
-            };
-            
-            if filtered_monitors.is_empty() {
-                return;
-            }
-            
-            // Clone the data and send it through the channel
+            // Clone the data and send it through the channel; monitor selection happens in the processor so
+            // decisions can record the full input snapshot
             let message = MonitorMessage::VisibilityUpdate(
-                filtered_monitors,
+                monitors.to_vec(),
             );
             
             // Use try_send to avoid blocking in the callback
@@ -102,113 +138,269 @@ impl VisibilityMonitor {
         if self.instance.watch_visible_area(callback, throttle_ms, std::ptr::null_mut()) {
             self.running = true;
             info!("Started monitoring desktop visibility");
-            true
+            Ok(())
         } else {
-            error!("Failed to start monitoring");
-            false
+            Err(MonitorError::WatcherInit { update_rate_ms: throttle_ms })
         }
     }
 
     async fn process_visibility_updates(
         mut rx: mpsc::Receiver<MonitorMessage>,
         controller: Arc<Mutex<WallpaperController>>,
-        per_monitor: bool,
-        threshold: u8,
+        mut settings: MonitorSettings,
+        mut decisions: DecisionLog,
     ) {
         // Create local tracking variables for this function instance
+        let mut last_snapshot: Option<Vec<MonitorVisibleInfo>> = None;
+        // Number, display and size of each connected monitor, to notice monitors being plugged in or out
+        let mut last_layout: Vec<(i64, i64, i64)> = Vec::new();
+        let mut selected_monitors: Vec<i64> = Vec::new();
         let mut previous_global_visibility: Option<u8> = None;
         let mut previous_monitor_visibilities: HashMap<i64, u8> = HashMap::new();
+        let mut previous_update: Option<Instant> = None;
         
-        while let Some(message) = rx.recv().await {
-            match message {
-                MonitorMessage::VisibilityUpdate(monitors) => {
-                    if !per_monitor {
-                        // Global mode - Calculate total visibility percentage across all monitored displays
-                        let mut monitored_visible = 0;
-                        let mut monitored_total = 0;
-                        
-                        for monitor in &monitors {
-                            monitored_visible += monitor.current_visible;
-                            monitored_total += monitor.max_visible;
-                        }
-                        
-                        let visibility_percent = if monitored_total > 0 {
-                            (monitored_visible as f64 / monitored_total as f64 * 100.0) as u8
-                        } else {
-                            0
-                        };
-                        
-                        debug!("Global visibility: {}%", visibility_percent);
-                        
-                        let mut controller_lock = controller.lock().await;
-                        
-                        // Check if we crossed the threshold in either direction
-                        let crossed_threshold_down = visibility_percent < threshold && 
-                            (previous_global_visibility.is_none() || previous_global_visibility.unwrap() >= threshold);
-                        let crossed_threshold_up = visibility_percent >= threshold && 
-                            (previous_global_visibility.is_none() || previous_global_visibility.unwrap() < threshold);
-                        
-                        // Update previous visibility
-                        previous_global_visibility = Some(visibility_percent);
-                        
-                        if crossed_threshold_down && controller_lock.is_playing(None) {
-                            info!("Global visibility {visibility_percent} is below threshold ({threshold}%), pausing Wallpaper Engine");
-                            controller_lock.pause(None).await;
-                        } else if crossed_threshold_up && !controller_lock.is_playing(None) {
-                            info!("Global visibility {visibility_percent} is above threshold ({threshold}%), resuming Wallpaper Engine");
-                            controller_lock.play(None).await;
-                        }
-                    } else {
-                        // Per-monitor mode - Apply the same threshold to each monitor
-                        let mut controller_lock = controller.lock().await;
-                        
-                        for monitor in &monitors {
-                            let visibility_percent = if monitor.max_visible > 0 {
-                                (monitor.current_visible as f64 / monitor.max_visible as f64 * 100.0) as u8
-                            } else {
-                                0
-                            };
-                            
-                            debug!("Monitor number {} visibility: {}%", monitor.monitor_index, visibility_percent);
-                            
-                            // Get previous visibility for this monitor
-                            let previous_visibility = previous_monitor_visibilities.get(&monitor.monitor_index).cloned();
-                            
-                            // Check if we crossed the threshold in either direction
-                            let crossed_threshold_down = visibility_percent < threshold && 
-                                (previous_visibility.is_none() || previous_visibility.unwrap() >= threshold);
-                            let crossed_threshold_up = visibility_percent >= threshold && 
-                                (previous_visibility.is_none() || previous_visibility.unwrap() < threshold);
-                            
-                            // Update previous visibility for this monitor
-                            previous_monitor_visibilities.insert(monitor.monitor_index, visibility_percent);
-                            
-                            if crossed_threshold_down && controller_lock.is_playing(Some(monitor.monitor_index)) {
-                                info!("Monitor number {} visibility below threshold ({}%), pausing",
-                                      monitor.monitor_index, threshold);
-                                controller_lock.pause(Some(monitor.monitor_index)).await;
-                            } else if crossed_threshold_up && !controller_lock.is_playing(Some(monitor.monitor_index)) {
-                                info!("Monitor number {} visibility above threshold ({}%), resuming",
-                                      monitor.monitor_index, threshold);
-                                controller_lock.play(Some(monitor.monitor_index)).await;
-                            }
-                        }
+        let mut reconcile_tick = tokio::time::interval(RECONCILE_INTERVAL);
+        reconcile_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let message = tokio::select! {
+                message = rx.recv() => message,
+                _ = reconcile_tick.tick() => {
+                    controller.lock().await.reconcile().await.ok();
+                    continue;
+                }
+            };
+            let Some(message) = message else { break };
+
+            let all_monitors = match message {
+                MonitorMessage::VisibilityUpdate(all_monitors) => all_monitors,
+                MonitorMessage::Settings(new_settings) => {
+                    let new_selection = last_snapshot.as_deref()
+                        .map(|snapshot| new_settings.monitors.resolve(&monitor_facts(snapshot)))
+                        .unwrap_or_default();
+                    Self::release_dropped_targets(&controller, &settings, &new_settings, &new_selection, &previous_monitor_visibilities).await;
+                    info!("Applied new monitor settings");
+                    settings = new_settings;
+                    // Resolve the new selector below
+                    last_layout.clear();
+                    // Re-evaluate the latest snapshot against the new settings instead of waiting for visibility to
+                    // change. Pause requests are kept, so targets that stay below the threshold aren't resumed.
+                    previous_global_visibility = None;
+                    previous_monitor_visibilities.clear();
+                    match &last_snapshot {
+                        Some(snapshot) => snapshot.clone(),
+                        None => continue,
                     }
-                },
+                }
                 MonitorMessage::Shutdown => {
                     info!("Received shutdown message");
                     break;
                 }
+            };
+            last_snapshot = Some(all_monitors.clone());
+
+            let layout: Vec<(i64, i64, i64)> = all_monitors.iter()
+                .map(|m| (m.monitor_index, m.monitor_id, m.total_area))
+                .collect();
+            if layout != last_layout {
+                if !last_layout.is_empty() {
+                    info!("Monitors changed: {} connected", all_monitors.len());
+                }
+                let selection = settings.monitors.resolve(&monitor_facts(&all_monitors));
+                if selection.is_empty() {
+                    let connected: Vec<String> = all_monitors.iter().map(|m| m.monitor_index.to_string()).collect();
+                    warn!("Monitors \"{}\" match none of the connected monitors ({})", settings.monitors, connected.join(", "));
+                    // Nothing is evaluated until a selected monitor returns, so don't leave the wallpaper paused
+                    if !settings.per_monitor {
+                        controller.lock().await.withdraw(SOURCE_VISIBILITY, None);
+                        previous_global_visibility = None;
+                    }
+                } else if selection != selected_monitors || last_layout.is_empty() {
+                    let numbers: Vec<String> = selection.iter().map(|i| i.to_string()).collect();
+                    info!("Watching monitor(s) {} (selected by \"{}\")", numbers.join(", "), settings.monitors);
+                }
+                // Monitors that are unplugged or no longer selected must not stay paused
+                Self::release_dropped_targets(&controller, &settings, &settings, &selection, &previous_monitor_visibilities).await;
+                previous_monitor_visibilities.retain(|index, _| selection.contains(index));
+                selected_monitors = selection;
+                last_layout = layout;
+            }
+
+            let MonitorSettings { per_monitor, threshold, ref weights, update_rate_ms, .. } = settings;
+            let monitors: Vec<MonitorVisibleInfo> = all_monitors.iter()
+                .filter(|m| selected_monitors.contains(&m.monitor_index))
+                .cloned()
+                .collect();
+
+            if monitors.is_empty() {
+                continue;
+            }
+
+            let now = Instant::now();
+            let rate_limit = RateLimit {
+                update_rate_ms,
+                since_previous_update_ms: previous_update.map(|t| now.duration_since(t).as_millis() as u64),
+            };
+            previous_update = Some(now);
+
+            let inputs: Vec<MonitorSample> = all_monitors.iter()
+                .map(|m| MonitorSample {
+                    monitor_index: m.monitor_index,
+                    current_visible: m.current_visible,
+                    max_visible: m.max_visible,
+                    visibility_percent: visibility_percent(m.current_visible, m.max_visible),
+                })
+                .collect();
+
+            if !per_monitor {
+                // Global mode - Calculate total visibility percentage across all monitored displays,
+                // scaling each monitor's area by its weight (1 unless configured)
+                let mut monitored_visible = 0.0;
+                let mut monitored_total = 0.0;
+                
+                for monitor in &monitors {
+                    let weight = weights.get(&monitor.monitor_index).copied().unwrap_or(1.0);
+                    monitored_visible += monitor.current_visible as f64 * weight;
+                    monitored_total += monitor.max_visible as f64 * weight;
+                }
+                
+                let visibility_percent = weighted_visibility_percent(monitored_visible, monitored_total);
+                
+                debug!("Global visibility: {}%", visibility_percent);
+                
+                let mut controller_lock = controller.lock().await;
+                
+                // Check if we crossed the threshold in either direction
+                let crossed_threshold_down = visibility_percent < threshold && 
+                    (previous_global_visibility.is_none() || previous_global_visibility.unwrap() >= threshold);
+                let crossed_threshold_up = visibility_percent >= threshold && 
+                    (previous_global_visibility.is_none() || previous_global_visibility.unwrap() < threshold);
+                
+                // Update previous visibility
+                let previous_visibility = previous_global_visibility.replace(visibility_percent);
+                
+                let crossing = if crossed_threshold_down {
+                    info!("Global visibility {visibility_percent} is below threshold ({threshold}%), requesting pause");
+                    controller_lock.submit(PauseRequest::pause(SOURCE_VISIBILITY, None, PRIORITY_VISIBILITY));
+                    Some(Crossing::Below)
+                } else if crossed_threshold_up {
+                    info!("Global visibility {visibility_percent} is above threshold ({threshold}%), withdrawing pause request");
+                    controller_lock.withdraw(SOURCE_VISIBILITY, None);
+                    Some(Crossing::Above)
+                } else {
+                    None
+                };
+
+                let was_playing = controller_lock.is_playing(None);
+                let success = controller_lock.reconcile().await.is_ok();
+
+                if let Some(crossing) = crossing {
+                    decisions.record(Decision {
+                        timestamp_ms: now_ms(),
+                        target: None,
+                        inputs,
+                        selected_monitors: selected_monitors.clone(),
+                        aggregation: if weights.is_empty() { Aggregation::GlobalAreaSum } else { Aggregation::WeightedAreaSum },
+                        weights: weights.clone(),
+                        visibility_percent,
+                        previous_visibility_percent: previous_visibility,
+                        threshold,
+                        crossing,
+                        rate_limit,
+                        active_reasons: reason_sources(&controller_lock, None),
+                        outcome: outcome(was_playing, controller_lock.is_playing(None), success),
+                    });
+                }
+            } else {
+                // Per-monitor mode - Apply the same threshold to each monitor
+                let mut controller_lock = controller.lock().await;
+                let mut crossings = Vec::new();
+                
+                for monitor in &monitors {
+                    let visibility_percent = visibility_percent(monitor.current_visible, monitor.max_visible);
+                    
+                    debug!("Monitor number {} visibility: {}%", monitor.monitor_index, visibility_percent);
+                    
+                    // Get previous visibility for this monitor
+                    let previous_visibility = previous_monitor_visibilities.get(&monitor.monitor_index).cloned();
+                    
+                    // Check if we crossed the threshold in either direction
+                    let crossed_threshold_down = visibility_percent < threshold && 
+                        (previous_visibility.is_none() || previous_visibility.unwrap() >= threshold);
+                    let crossed_threshold_up = visibility_percent >= threshold && 
+                        (previous_visibility.is_none() || previous_visibility.unwrap() < threshold);
+                    
+                    // Update previous visibility for this monitor
+                    previous_monitor_visibilities.insert(monitor.monitor_index, visibility_percent);
+                    
+                    let target = Some(monitor.monitor_index);
+                    if crossed_threshold_down {
+                        info!("Monitor number {} visibility below threshold ({}%), requesting pause",
+                              monitor.monitor_index, threshold);
+                        controller_lock.submit(PauseRequest::pause(SOURCE_VISIBILITY, target, PRIORITY_VISIBILITY));
+                        crossings.push((target, Crossing::Below, visibility_percent, previous_visibility, controller_lock.is_playing(target)));
+                    } else if crossed_threshold_up {
+                        info!("Monitor number {} visibility above threshold ({}%), withdrawing pause request",
+                              monitor.monitor_index, threshold);
+                        controller_lock.withdraw(SOURCE_VISIBILITY, target);
+                        crossings.push((target, Crossing::Above, visibility_percent, previous_visibility, controller_lock.is_playing(target)));
+                    }
+                }
+
+                let success = controller_lock.reconcile().await.is_ok();
+
+                for (target, crossing, visibility_percent, previous_visibility, was_playing) in crossings {
+                    decisions.record(Decision {
+                        timestamp_ms: now_ms(),
+                        target,
+                        inputs: inputs.clone(),
+                        selected_monitors: selected_monitors.clone(),
+                        aggregation: Aggregation::PerMonitor,
+                        weights: BTreeMap::new(),
+                        visibility_percent,
+                        previous_visibility_percent: previous_visibility,
+                        threshold,
+                        crossing,
+                        rate_limit: rate_limit.clone(),
+                        active_reasons: reason_sources(&controller_lock, target),
+                        outcome: outcome(was_playing, controller_lock.is_playing(target), success),
+                    });
+                }
             }
         }
         
         info!("Visibility update processor stopped");
     }
 
-    pub async fn stop_monitoring(&mut self) -> bool {
+    /// Withdraws the visibility pause requests of targets `new` no longer evaluates, which would otherwise stay
+    /// paused. `new_selection` are the monitors `new` selects; `tracked` are the monitors evaluated so far in
+    /// per-monitor mode.
+    async fn release_dropped_targets(
+        controller: &Mutex<WallpaperController>,
+        old: &MonitorSettings,
+        new: &MonitorSettings,
+        new_selection: &[i64],
+        tracked: &HashMap<i64, u8>,
+    ) {
+        let mut controller = controller.lock().await;
+        match (old.per_monitor, new.per_monitor) {
+            (false, true) => {
+                controller.withdraw(SOURCE_VISIBILITY, None);
+            }
+            (true, per_monitor) => {
+                let selected = |index: i64| new_selection.contains(&index);
+                for &index in tracked.keys().filter(|&&index| !per_monitor || !selected(index)) {
+                    controller.withdraw(SOURCE_VISIBILITY, Some(index));
+                }
+            }
+            (false, false) => {}
+        }
+    }
+
+    /// Stops the watcher and resumes all wallpapers. The watcher is stopped even if resuming fails.
+    pub async fn stop_monitoring(&mut self) -> Result<(), MonitorError> {
         if !self.running {
-            warn!("Not monitoring");
-            return false;
+            return Err(MonitorError::NotRunning);
         }
 
         // Send a shutdown message
@@ -217,25 +409,84 @@ impl VisibilityMonitor {
         }
 
         // Resume wallpapers before stopping the watcher
+        let mut resume_result = Ok(());
         {
             let mut controller = self.get_controller().await;
-            if let Some(ref indices) = self.monitor_indices {
-                for &i in indices.iter() { controller.play(Some(i)).await; }
-            } else {
-                controller.play(None).await;
+            let reasons = controller.active_reasons(None);
+            if !reasons.is_empty() {
+                let sources = reasons.iter().map(|r| r.source.as_str()).collect::<Vec<_>>().join(", ");
+                info!("Overriding active pause reasons on shutdown: {}", sources);
+            }
+            if !self.settings.monitors.is_all() {
+                let (monitors, _, _) = self.instance.get_visible_area();
+                for i in self.settings.monitors.resolve(&monitor_facts(&monitors)) {
+                    if let Err(e) = controller.play(Some(i)).await {
+                        resume_result = Err(MonitorError::Resume(e));
+                    }
+                }
+            } else if let Err(e) = controller.play(None).await {
+                resume_result = Err(MonitorError::Resume(e));
             }
         } // Release the lock on the controller here
 
-        info!("Resumed all wallpapers...");
+        if resume_result.is_ok() {
+            info!("Resumed all wallpapers...");
+        }
 
         // Stop the libvisdesk watcher
-        if self.instance.stop_watch_visible_area() {
-            self.running = false;
-            info!("Stopped monitoring desktop visibility");
-            true
-        } else {
-            error!("Failed to stop monitoring");
-            false
+        if !self.instance.stop_watch_visible_area() {
+            return Err(MonitorError::WatcherStop);
+        }
+        self.running = false;
+        info!("Stopped monitoring desktop visibility");
+        resume_result
+    }
+}
+
+/// The facts of the monitors libvisdesk reports, marking the primary one
+pub fn monitor_facts(monitors: &[MonitorVisibleInfo]) -> Vec<MonitorFacts> {
+    let primary = primary_display_number();
+    monitors.iter()
+        .map(|m| MonitorFacts { index: m.monitor_index, area: m.total_area, primary: Some(m.monitor_id) == primary })
+        .collect()
+}
+
+/// The number in the primary display's device name (`\\.\DISPLAY<n>`), which libvisdesk reports as `monitor_id`
+fn primary_display_number() -> Option<i64> {
+    let mut device = DISPLAY_DEVICEW { cb: std::mem::size_of::<DISPLAY_DEVICEW>() as u32, ..Default::default() };
+    let mut number = 0;
+    while unsafe { EnumDisplayDevicesW(PCWSTR::null(), number, &mut device, 0) }.as_bool() {
+        if device.StateFlags.contains(DISPLAY_DEVICE_PRIMARY_DEVICE) {
+            let len = device.DeviceName.iter().position(|&c| c == 0).unwrap_or(device.DeviceName.len());
+            let name = String::from_utf16_lossy(&device.DeviceName[..len]);
+            return name.trim_start_matches(r"\\.\DISPLAY").parse().ok();
         }
+        number += 1;
     }
-}
\ No newline at end of file
+    None
+}
+
+fn visibility_percent(visible: i64, max_visible: i64) -> u8 {
+    weighted_visibility_percent(visible as f64, max_visible as f64)
+}
+
+fn weighted_visibility_percent(visible: f64, max_visible: f64) -> u8 {
+    if max_visible > 0.0 {
+        (visible / max_visible * 100.0) as u8
+    } else {
+        0
+    }
+}
+
+fn reason_sources(controller: &WallpaperController, target: Target) -> Vec<String> {
+    controller.active_reasons(target).into_iter().map(|r| r.source).collect()
+}
+
+fn outcome(was_playing: bool, is_playing: bool, success: bool) -> Outcome {
+    match (was_playing, is_playing) {
+        _ if !success => Outcome::CommandFailed,
+        (true, false) => Outcome::Paused,
+        (false, true) => Outcome::Resumed,
+        _ => Outcome::Unchanged,
+    }
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Every file the controller reads or writes for itself (config, logs, decision traces, state) is resolved
+// here, so the installed and portable layouts can't drift apart. Installed copies keep them in the user
+// profile; portable copies keep them next to the executable and leave the user profile alone.
+
+/// Next to the executable, this file switches the controller to portable mode
+pub const PORTABLE_MARKER_FILE: &str = "portable";
+const APP_DIR_NAME: &str = "wallpaper-controller";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const TRACE_FILE_NAME: &str = "decisions.jsonl";
+const STATUS_FILE_NAME: &str = "status.json";
+const PROFILE_REQUEST_FILE_NAME: &str = "profile-request.txt";
+const SAVED_ANSWERS_LIST_FILE_NAME: &str = "saved-answers.txt";
+const LOG_DIR_NAME: &str = "logs";
+const LOG_FILE_NAME: &str = "wallpaper-controller.log";
+const ROTATED_LOG_FILE_NAME: &str = "wallpaper-controller.log.old";
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Installed,
+    Portable,
+}
+
+pub struct Paths {
+    layout: Layout,
+    config_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+}
+
+/// Decides the layout once at startup: portable with `--portable` or when the marker file sits next to the
+/// executable
+pub fn init(portable: bool) -> &'static Paths {
+    PATHS.get_or_init(|| Paths::detect(portable))
+}
+
+/// The layout decided by `init`, or the one the marker file implies if it hasn't been called
+pub fn current() -> &'static Paths {
+    PATHS.get_or_init(|| Paths::detect(false))
+}
+
+impl Paths {
+    fn detect(portable: bool) -> Self {
+        let exe_dir = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+        let marker = exe_dir.as_ref().is_some_and(|dir| dir.join(PORTABLE_MARKER_FILE).is_file());
+        if portable || marker {
+            Self { layout: Layout::Portable, config_dir: exe_dir.clone(), data_dir: exe_dir }
+        } else {
+            Self {
+                layout: Layout::Installed,
+                config_dir: dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME)),
+                data_dir: dirs::data_local_dir().map(|dir| dir.join(APP_DIR_NAME)),
+            }
+        }
+    }
+
+    pub fn is_portable(&self) -> bool {
+        self.layout == Layout::Portable
+    }
+
+    /// Folder for state files: the executable's folder when portable, otherwise `wallpaper-controller` in the
+    /// local app data folder
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
+    /// The config file read unless --config says otherwise: next to the executable when portable, otherwise
+    /// `wallpaper-controller\config.toml` in the roaming app data folder
+    pub fn config_file(&self) -> Option<PathBuf> {
+        self.config_dir.as_deref().map(|dir| dir.join(CONFIG_FILE_NAME))
+    }
+
+    /// Where decisions are recorded for --explain-last unless --trace-file says otherwise
+    pub fn trace_file(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(TRACE_FILE_NAME))
+    }
+
+    /// Where the running controller publishes its active profile and settings for --status
+    pub fn status_file(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(STATUS_FILE_NAME))
+    }
+
+    /// Where `wallpaper-controller profile NAME` leaves the profile for the running controller to switch to
+    pub fn profile_request_file(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(PROFILE_REQUEST_FILE_NAME))
+    }
+
+    /// Where the answers files saved with --save-answers are listed, so --uninstall can remove them
+    pub fn saved_answers_list(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(SAVED_ANSWERS_LIST_FILE_NAME))
+    }
+
+    pub fn log_file(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(LOG_DIR_NAME).join(LOG_FILE_NAME))
+    }
+
+    /// The previous log, kept when the current one grows too large
+    pub fn rotated_log_file(&self) -> Option<PathBuf> {
+        self.data_dir().map(|dir| dir.join(LOG_DIR_NAME).join(ROTATED_LOG_FILE_NAME))
+    }
+}
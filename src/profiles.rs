@@ -0,0 +1,550 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use chrono::Timelike;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+
+use crate::config::{Settings, Source};
+use crate::error::MonitorError;
+use crate::explain::{format_age, now_ms, Decision};
+use crate::monitor::VisibilityMonitor;
+use crate::wallpaper::PauseAction;
+
+// A profile overrides some of the settings (e.g. a high threshold while gaming, a low one while working).
+// The first rule whose condition holds picks the active profile; without a matching rule the profile selected
+// with --profile (or `profile` in the config file) is used, and without one of those the plain settings. A
+// profile picked with `wallpaper-controller profile NAME` wins over all of them until "auto" hands the choice back.
+
+/// `--switch-profile` value that hands the choice back to the rules
+pub const AUTOMATIC: &str = "auto";
+
+/// How visibility is aggregated over the selected monitors
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AggregationMode {
+    /// One visibility for all selected monitors together
+    Global,
+    /// Each selected monitor on its own
+    PerMonitor,
+}
+
+/// Settings a profile overrides; anything left out keeps the configured value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitors: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregation: Option<AggregationMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<PauseAction>,
+}
+
+/// Activates `profile` while all of its conditions hold
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub profile: String,
+    /// Image name of a process that must be running, e.g. "eldenring.exe"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// Local time of day, e.g. "09:00-17:30"; may wrap past midnight ("22:00-06:00")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub between: Option<TimeRange>,
+}
+
+/// A daily time window in minutes since midnight; the end is exclusive
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    start: u16,
+    end: u16,
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (start, end) = value.split_once('-').ok_or_else(|| format!("'{}' is not HH:MM-HH:MM", value))?;
+        Ok(Self { start: parse_time_of_day(start)?, end: parse_time_of_day(end)? })
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        format!("{:02}:{:02}-{:02}:{:02}", range.start / 60, range.start % 60, range.end / 60, range.end % 60)
+    }
+}
+
+fn parse_time_of_day(value: &str) -> Result<u16, String> {
+    let value = value.trim();
+    let (hours, minutes) = value.split_once(':').ok_or_else(|| format!("'{}' is not HH:MM", value))?;
+    match (hours.parse::<u16>(), minutes.parse::<u16>()) {
+        (Ok(hours), Ok(minutes)) if hours < 24 && minutes < 60 => Ok(hours * 60 + minutes),
+        _ => Err(format!("'{}' is not a time between 00:00 and 23:59", value)),
+    }
+}
+
+impl TimeRange {
+    fn contains(&self, minute_of_day: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, env: &dyn RuleEnv, processes: &mut Option<Vec<String>>) -> bool {
+        if let Some(range) = &self.between {
+            if !range.contains(env.minute_of_day()) {
+                return false;
+            }
+        }
+        if let Some(process) = &self.process {
+            // Listing processes is the expensive part, so it happens at most once per evaluation
+            let running = processes.get_or_insert_with(|| env.running_processes());
+            let wanted = process.to_lowercase();
+            let wanted = if wanted.ends_with(".exe") { wanted } else { format!("{}.exe", wanted) };
+            if !running.contains(&wanted) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(process) = &self.process {
+            conditions.push(format!("{} is running", process));
+        }
+        if let Some(range) = self.between {
+            conditions.push(format!("time is {}", String::from(range)));
+        }
+        if conditions.is_empty() {
+            write!(f, "rule without conditions")
+        } else {
+            write!(f, "rule: {}", conditions.join(" and "))
+        }
+    }
+}
+
+/// What the rules look at
+pub trait RuleEnv {
+    /// Local time in minutes since midnight
+    fn minute_of_day(&self) -> u16;
+    /// Image names of the running processes, lowercase
+    fn running_processes(&self) -> Vec<String>;
+}
+
+/// Reads the real clock and process list
+pub struct SystemRuleEnv;
+
+impl RuleEnv for SystemRuleEnv {
+    fn minute_of_day(&self) -> u16 {
+        let now = chrono::Local::now();
+        (now.hour() * 60 + now.minute()) as u16
+    }
+
+    // Read in-process from a snapshot; starting tasklist every few seconds would flash a console window
+    fn running_processes(&self) -> Vec<String> {
+        let Ok(snapshot) = (unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }) else {
+            return Vec::new();
+        };
+        let mut entry = PROCESSENTRY32W { dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32, ..Default::default() };
+        let mut names = Vec::new();
+        let mut next = unsafe { Process32FirstW(snapshot, &mut entry) };
+        while next.is_ok() {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+            names.push(String::from_utf16_lossy(&entry.szExeFile[..len]).to_lowercase());
+            next = unsafe { Process32NextW(snapshot, &mut entry) };
+        }
+        unsafe { CloseHandle(snapshot) }.ok();
+        names
+    }
+}
+
+/// The active profile and why it is active
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveProfile {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Picks the profile switched to by hand (`manual`), or else the one the rules (or, without a matching rule, the
+/// `profile` setting) call for
+pub fn select_profile(settings: &Settings, manual: Option<&str>, env: &dyn RuleEnv) -> Option<ActiveProfile> {
+    if let Some(name) = manual.filter(|name| settings.profiles.contains_key(*name)) {
+        return Some(ActiveProfile { name: name.to_string(), reason: String::from("switched with the profile command") });
+    }
+    let mut processes = None;
+    if let Some(rule) = settings.rules.iter().find(|rule| rule.matches(env, &mut processes)) {
+        return Some(ActiveProfile { name: rule.profile.clone(), reason: rule.to_string() });
+    }
+    settings.profile.as_ref().map(|name| ActiveProfile {
+        name: name.clone(),
+        reason: match settings.source("profile") {
+            Source::CommandLine => String::from("selected with --profile"),
+            source => format!("selected in {}", source),
+        },
+    })
+}
+
+/// `select_profile` with the real clock and process list. Listing processes blocks for a while, so with a
+/// `process` rule it runs on the blocking pool rather than the async runtime.
+pub async fn select_system_profile(settings: &Settings, manual: Option<&str>) -> Option<ActiveProfile> {
+    if !settings.rules.iter().any(|rule| rule.process.is_some()) {
+        return select_profile(settings, manual, &SystemRuleEnv);
+    }
+    let settings = settings.clone();
+    let manual = manual.map(String::from);
+    tokio::task::spawn_blocking(move || select_profile(&settings, manual.as_deref(), &SystemRuleEnv))
+        .await
+        .expect("selecting a profile panicked")
+}
+
+/// Leaves `name` in `request_file` for the running controller, which picks it up with its next rule check.
+/// Fails if no controller is running or the config file has no such profile.
+pub fn request_switch(request_file: &Path, status_file: &Path, settings: &Settings, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name != AUTOMATIC && !settings.profiles.contains_key(name) {
+        let known: Vec<&str> = settings.profiles.keys().map(String::as_str).collect();
+        bail!("There is no profile '{}' in the config file (profiles: {}; or \"{}\" to let the rules decide)",
+              name, if known.is_empty() { String::from("none") } else { known.join(", ") }, AUTOMATIC);
+    }
+    if !status_file.exists() {
+        bail!("No controller is running (no status in {})", status_file.display());
+    }
+    fs::write(request_file, name).with_context(|| format!("Failed to write {}", request_file.display()))?;
+    if name == AUTOMATIC {
+        println!("Asked the running controller to let the rules pick the profile again");
+    } else {
+        println!("Asked the running controller to switch to profile '{}'", name);
+    }
+    Ok(())
+}
+
+/// Takes the profile name a `profile` command left for the running controller, if there is one
+pub fn take_switch_request(request_file: &Path) -> Option<String> {
+    let name = fs::read_to_string(request_file).ok()?;
+    // Removed first, so a request that can't be applied isn't retried on every check
+    if let Err(e) = fs::remove_file(request_file) {
+        warn!("Could not remove {}: {}", request_file.display(), e);
+    }
+    Some(name.trim().to_string())
+}
+
+/// Applies the settings of `active` (or the plain settings) to the running monitor and controller
+pub async fn activate(
+    monitor: &mut VisibilityMonitor,
+    settings: &Settings,
+    active: Option<&ActiveProfile>,
+) -> Result<(), MonitorError> {
+    let name = active.map(|a| a.name.as_str());
+    monitor.update_settings(settings.monitor_settings(name)).await?;
+    monitor.get_controller().await.set_pause_action(settings.pause_action(name));
+    Ok(())
+}
+
+pub fn describe(active: Option<&ActiveProfile>) -> String {
+    match active {
+        Some(active) => format!("'{}' ({})", active.name, active.reason),
+        None => String::from("none (plain settings)"),
+    }
+}
+
+/// What the running controller is doing, written for `--status`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Status {
+    pub pid: u32,
+    pub updated_ms: u64,
+    pub profile: Option<String>,
+    pub profile_reason: Option<String>,
+    pub threshold: u8,
+    pub monitors: String,
+    pub aggregation: AggregationMode,
+    pub action: PauseAction,
+    /// The running controller's in-memory decision history, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_decisions: Vec<Decision>,
+}
+
+// How many of the recent decisions --status explains
+const STATUS_DECISIONS_SHOWN: usize = 3;
+
+impl Status {
+    pub fn new(settings: &Settings, active: Option<&ActiveProfile>, recent_decisions: Vec<Decision>) -> Self {
+        let name = active.map(|a| a.name.as_str());
+        let monitor_settings = settings.monitor_settings(name);
+        Self {
+            pid: std::process::id(),
+            updated_ms: now_ms(),
+            profile: active.map(|a| a.name.clone()),
+            profile_reason: active.map(|a| a.reason.clone()),
+            threshold: monitor_settings.threshold,
+            monitors: name
+                .and_then(|name| settings.profiles.get(name))
+                .and_then(|profile| profile.monitors.clone())
+                .unwrap_or_else(|| settings.monitors.clone()),
+            aggregation: if monitor_settings.per_monitor { AggregationMode::PerMonitor } else { AggregationMode::Global },
+            action: settings.pause_action(name),
+            recent_decisions,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Prints the status the running controller wrote to `path`
+pub fn print_status(path: &Path) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("No controller is running (no status in {})", path.display());
+            return Ok(());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let status: Status = serde_json::from_str(&content).with_context(|| format!("Invalid status file {}", path.display()))?;
+    println!("Controller running (PID {}, updated {})", status.pid, format_age(status.updated_ms));
+    match (&status.profile, &status.profile_reason) {
+        (Some(profile), Some(reason)) => println!("  Profile:     {} ({})", profile, reason),
+        (Some(profile), None) => println!("  Profile:     {}", profile),
+        _ => println!("  Profile:     none"),
+    }
+    println!("  Threshold:   {}%", status.threshold);
+    println!("  Monitors:    {}", status.monitors);
+    println!("  Aggregation: {}", match status.aggregation {
+        AggregationMode::Global => "global",
+        AggregationMode::PerMonitor => "per monitor",
+    });
+    println!("  Action:      {}", status.action.name());
+    let shown = status.recent_decisions.len().min(STATUS_DECISIONS_SHOWN);
+    if shown > 0 {
+        println!("\nLast {} of {} decision(s) kept in memory:\n", shown, status.recent_decisions.len());
+        for decision in &status.recent_decisions[status.recent_decisions.len() - shown..] {
+            println!("{}", decision.explain());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use clap::{CommandFactory, FromArgMatches};
+    use crate::cli::Cli;
+    use crate::paths;
+
+    struct FakeRuleEnv {
+        minute_of_day: u16,
+        processes: Vec<&'static str>,
+        listed: Cell<usize>,
+    }
+
+    impl FakeRuleEnv {
+        fn at(time: &str) -> Self {
+            Self { minute_of_day: parse_time_of_day(time).unwrap(), processes: Vec::new(), listed: Cell::new(0) }
+        }
+
+        fn running(mut self, processes: &[&'static str]) -> Self {
+            self.processes = processes.to_vec();
+            self
+        }
+    }
+
+    impl RuleEnv for FakeRuleEnv {
+        fn minute_of_day(&self) -> u16 {
+            self.minute_of_day
+        }
+
+        fn running_processes(&self) -> Vec<String> {
+            self.listed.set(self.listed.get() + 1);
+            self.processes.iter().map(|name| name.to_string()).collect()
+        }
+    }
+
+    fn range(value: &str) -> TimeRange {
+        TimeRange::try_from(value.to_string()).unwrap()
+    }
+
+    fn rule(process: Option<&str>, between: Option<&str>) -> Rule {
+        Rule { profile: String::from("gaming"), process: process.map(String::from), between: between.map(range) }
+    }
+
+    fn matches(rule: &Rule, env: &FakeRuleEnv) -> bool {
+        rule.matches(env, &mut None)
+    }
+
+    fn settings(config: &str) -> Settings {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, config).unwrap();
+        let matches = Cli::command().get_matches_from(["wallpaper-controller", "--config", path.to_str().unwrap()]);
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        crate::config::load(&cli, &matches, crate::config::ArgsOrigin::User, paths::current()).unwrap()
+    }
+
+    const CONFIG: &str = r#"
+profile = "work"
+
+[profiles.work]
+threshold = 10
+
+[profiles.gaming]
+threshold = 90
+
+[profiles.night]
+action = "mute"
+
+[[rules]]
+profile = "gaming"
+process = "eldenring.exe"
+
+[[rules]]
+profile = "night"
+between = "22:00-06:00"
+"#;
+
+    #[test]
+    fn time_ranges_parse() {
+        assert_eq!(range("09:00-17:30"), TimeRange { start: 9 * 60, end: 17 * 60 + 30 });
+        assert_eq!(range(" 9:05 - 17:30 "), TimeRange { start: 9 * 60 + 5, end: 17 * 60 + 30 });
+        assert_eq!(range("22:00-06:00"), TimeRange { start: 22 * 60, end: 6 * 60 });
+        assert_eq!(String::from(range("9:05-0:00")), "09:05-00:00");
+    }
+
+    #[test]
+    fn malformed_time_ranges_are_rejected() {
+        for (value, error) in [
+            ("09:00", "'09:00' is not HH:MM-HH:MM"),
+            ("0900-1700", "'0900' is not HH:MM"),
+            ("24:00-06:00", "'24:00' is not a time between 00:00 and 23:59"),
+            ("09:60-10:00", "'09:60' is not a time between 00:00 and 23:59"),
+            ("nine:00-10:00", "'nine:00' is not a time between 00:00 and 23:59"),
+            ("09:00-", "'' is not HH:MM"),
+        ] {
+            assert_eq!(TimeRange::try_from(value.to_string()).unwrap_err(), error, "{}", value);
+        }
+        let error = toml::from_str::<Rule>("profile = \"work\"\nbetween = \"9-17\"").unwrap_err();
+        assert!(error.message().contains("'9' is not HH:MM"), "{}", error);
+    }
+
+    #[test]
+    fn time_ranges_include_the_start_but_not_the_end() {
+        let day = range("09:00-17:30");
+        assert!(!day.contains(parse_time_of_day("08:59").unwrap()));
+        assert!(day.contains(parse_time_of_day("09:00").unwrap()));
+        assert!(day.contains(parse_time_of_day("17:29").unwrap()));
+        assert!(!day.contains(parse_time_of_day("17:30").unwrap()));
+    }
+
+    #[test]
+    fn time_ranges_wrap_past_midnight() {
+        let night = range("22:00-06:00");
+        for (time, inside) in [("21:59", false), ("22:00", true), ("23:59", true), ("00:00", true), ("05:59", true), ("06:00", false), ("12:00", false)] {
+            assert_eq!(night.contains(parse_time_of_day(time).unwrap()), inside, "{}", time);
+        }
+        // An empty range never matches
+        assert!(!range("10:00-10:00").contains(parse_time_of_day("10:00").unwrap()));
+    }
+
+    #[test]
+    fn rules_match_running_processes() {
+        let env = FakeRuleEnv::at("12:00").running(&["explorer.exe", "eldenring.exe"]);
+        assert!(matches(&rule(Some("eldenring.exe"), None), &env));
+        assert!(matches(&rule(Some("EldenRing.EXE"), None), &env));
+        assert!(matches(&rule(Some("eldenring"), None), &env));
+        assert!(!matches(&rule(Some("witcher3.exe"), None), &env));
+        assert!(!matches(&rule(Some("eldenring.exe.bak"), None), &env));
+    }
+
+    #[test]
+    fn rules_need_all_conditions() {
+        let both = rule(Some("eldenring.exe"), Some("22:00-06:00"));
+        assert!(matches(&both, &FakeRuleEnv::at("23:00").running(&["eldenring.exe"])));
+        assert!(matches(&both, &FakeRuleEnv::at("01:30").running(&["eldenring.exe"])));
+        assert!(!matches(&both, &FakeRuleEnv::at("12:00").running(&["eldenring.exe"])));
+        assert!(!matches(&both, &FakeRuleEnv::at("23:00")));
+        assert!(matches(&rule(None, None), &FakeRuleEnv::at("12:00")));
+    }
+
+    #[test]
+    fn processes_are_listed_at_most_once_and_only_when_needed() {
+        let env = FakeRuleEnv::at("12:00").running(&["eldenring.exe"]);
+        let mut processes = None;
+        assert!(!rule(Some("eldenring.exe"), Some("22:00-06:00")).matches(&env, &mut processes));
+        assert_eq!(env.listed.get(), 0, "processes were listed although the time didn't match");
+        assert!(!rule(Some("witcher3.exe"), None).matches(&env, &mut processes));
+        assert!(rule(Some("eldenring.exe"), None).matches(&env, &mut processes));
+        assert_eq!(env.listed.get(), 1);
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins_over_the_profile_setting() {
+        let settings = settings(CONFIG);
+        let name = |env: &FakeRuleEnv| select_profile(&settings, None, env).map(|active| active.name);
+        assert_eq!(name(&FakeRuleEnv::at("23:00").running(&["eldenring.exe"])).as_deref(), Some("gaming"));
+        assert_eq!(name(&FakeRuleEnv::at("23:00")).as_deref(), Some("night"));
+        assert_eq!(name(&FakeRuleEnv::at("12:00")).as_deref(), Some("work"));
+
+        let active = select_profile(&settings, None, &FakeRuleEnv::at("12:00")).unwrap();
+        assert!(active.reason.starts_with("selected in"), "{}", active.reason);
+        assert_eq!(select_profile(&settings, None, &FakeRuleEnv::at("23:00")).unwrap().reason, "rule: time is 22:00-06:00");
+        let plain = self::settings("[profiles.work]\nthreshold = 10\n");
+        assert_eq!(select_profile(&plain, None, &FakeRuleEnv::at("12:00")), None);
+    }
+
+    #[test]
+    fn a_manual_switch_wins_over_the_rules() {
+        let settings = settings(CONFIG);
+        let env = FakeRuleEnv::at("23:00").running(&["eldenring.exe"]);
+        let active = select_profile(&settings, Some("work"), &env).unwrap();
+        assert_eq!(active.name, "work");
+        assert_eq!(active.reason, "switched with the profile command");
+        assert_eq!(env.listed.get(), 0);
+        // A profile that is gone from the config file is ignored
+        assert_eq!(select_profile(&settings, Some("movie"), &env).unwrap().name, "gaming");
+    }
+
+    #[test]
+    fn switch_requests_reach_the_running_controller_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let request_file = dir.path().join("profile-request.txt");
+        let status_file = dir.path().join("status.json");
+        let settings = settings(CONFIG);
+
+        let error = request_switch(&request_file, &status_file, &settings, "gaming").unwrap_err();
+        assert!(error.to_string().starts_with("No controller is running"), "{}", error);
+
+        fs::write(&status_file, "{}").unwrap();
+        let error = request_switch(&request_file, &status_file, &settings, "movie").unwrap_err();
+        assert!(error.to_string().contains("profiles: gaming, night, work"), "{}", error);
+        assert!(!request_file.exists());
+
+        request_switch(&request_file, &status_file, &settings, " gaming ").unwrap();
+        assert_eq!(take_switch_request(&request_file).as_deref(), Some("gaming"));
+        assert_eq!(take_switch_request(&request_file), None);
+
+        request_switch(&request_file, &status_file, &settings, AUTOMATIC).unwrap();
+        assert_eq!(take_switch_request(&request_file).as_deref(), Some(AUTOMATIC));
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use clap::ArgMatches;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::cli::Cli;
+use crate::config::{self, ArgsOrigin, Settings};
+use crate::error::AppError;
+use crate::monitor::VisibilityMonitor;
+use crate::paths::Paths;
+use crate::profiles::{self, ActiveProfile};
+use crate::steam::{resolve_wallpaper_engine, validate_wallpaper_engine_dir};
+
+// The config file is polled rather than watched with change notifications: editors save in different ways
+// (writing in place, or replacing the file), and a changed modification time or size catches all of them.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls the config file and sends the re-layered settings each time it changes into a valid configuration.
+/// `launch_cli` is the command line as parsed, before the config was applied to it. Returns `None` if there
+/// is no config file location to watch.
+pub fn spawn_watcher(
+    launch_cli: Cli,
+    matches: ArgMatches,
+    origin: ArgsOrigin,
+    paths: &'static Paths,
+    settings: &Settings,
+) -> Option<mpsc::Receiver<Settings>> {
+    let path = settings.config_file.clone()?;
+    Some(watch(path, POLL_INTERVAL, move || config::load(&launch_cli, &matches, origin, paths)))
+}
+
+/// Polls `path` every `interval` and sends what `load` returns each time the file changes, keeping quiet
+/// (apart from a warning) when it fails
+fn watch<E, F>(path: PathBuf, interval: Duration, mut load: F) -> mpsc::Receiver<Settings>
+where
+    E: fmt::Display + Send,
+    F: FnMut() -> Result<Settings, E> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        let mut stamp = file_stamp(&path);
+        let mut poll = tokio::time::interval(interval);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            poll.tick().await;
+            let current = file_stamp(&path);
+            if current == stamp {
+                continue;
+            }
+            stamp = current;
+            debug!("{} changed, reloading", path.display());
+            match load() {
+                Ok(settings) => {
+                    if tx.send(settings).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Ignoring the changed config file, keeping the current settings: {}", e),
+            }
+        }
+    });
+    rx
+}
+
+type FileStamp = Option<(Option<SystemTime>, u64)>;
+
+fn file_stamp(path: &Path) -> FileStamp {
+    fs::metadata(path).ok().map(|metadata| (metadata.modified().ok(), metadata.len()))
+}
+
+/// Each setting that differs, as "key: old → new"
+pub fn changes(old: &Settings, new: &Settings) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut compare = |key: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("{}: {} → {}", key, old, new));
+        }
+    };
+    let path = |path: &Option<String>| path.clone().unwrap_or_else(|| String::from("(not set)"));
+    compare("monitors", old.monitors.clone(), new.monitors.clone());
+    compare("threshold", old.threshold.to_string(), new.threshold.to_string());
+    compare("per_monitor", old.per_monitor.to_string(), new.per_monitor.to_string());
+    compare("update_rate", old.update_rate.to_string(), new.update_rate.to_string());
+    compare("weights", format!("{:?}", old.weights.0), format!("{:?}", new.weights.0));
+    compare("wallpaper_engine_path", path(&old.wallpaper_engine_path), path(&new.wallpaper_engine_path));
+    compare("bit64", old.bit64.to_string(), new.bit64.to_string());
+    compare("dry_run", old.dry_run.to_string(), new.dry_run.to_string());
+    compare("trace_file", path(&old.trace_file), path(&new.trace_file));
+    compare("disable_sentry", old.disable_sentry.to_string(), new.disable_sentry.to_string());
+    compare("sentry_dsn", path(&old.sentry_dsn), path(&new.sentry_dsn));
+    compare("profile", path(&old.profile), path(&new.profile));
+    for name in old.profiles.keys().chain(new.profiles.keys().filter(|name| !old.profiles.contains_key(*name))) {
+        let profile = |settings: &Settings| settings.profiles.get(name)
+            .map(|p| format!("{:?}", p))
+            .unwrap_or_else(|| String::from("(not set)"));
+        compare(&format!("profiles.{}", name), profile(old), profile(new));
+    }
+    let rules = |settings: &Settings| settings.rules.iter().map(|rule| format!("{} → {}", rule, rule.profile)).collect::<Vec<_>>();
+    compare("rules", format!("{:?}", rules(old)), format!("{:?}", rules(new)));
+    changes
+}
+
+/// Applies reloaded settings to the running monitor and controller, keeping the current pause state, and
+/// returns the settings now in effect (settings only read at startup keep their old values). `active` is the
+/// profile `new` selects. Nothing is changed if the new Wallpaper Engine location is unusable.
+pub async fn apply(
+    monitor: &mut VisibilityMonitor,
+    old: &Settings,
+    new: &Settings,
+    active: Option<&ActiveProfile>,
+) -> Result<Settings, AppError> {
+    let we_changed = old.wallpaper_engine_path != new.wallpaper_engine_path || old.bit64 != new.bit64;
+    let wallpaper_engine = resolve_wallpaper_engine(new.wallpaper_engine_path.as_deref(), new.bit64);
+    // Dry runs never touch Wallpaper Engine, so they tolerate a missing one (as at startup)
+    if we_changed && !old.dry_run {
+        validate_wallpaper_engine_dir(Path::new(&wallpaper_engine.dir), wallpaper_engine.use_64bit)?;
+    }
+
+    profiles::activate(monitor, new, active).await?;
+    if we_changed {
+        monitor.get_controller().await.set_wallpaper_engine(wallpaper_engine.dir, wallpaper_engine.use_64bit)?;
+    }
+
+    let mut effective = new.clone();
+    effective.dry_run = old.dry_run;
+    effective.trace_file = old.trace_file.clone();
+    effective.disable_sentry = old.disable_sentry;
+    effective.sentry_dsn = old.sentry_dsn.clone();
+    let needs_restart = changes(&effective, new);
+    if !needs_restart.is_empty() {
+        warn!("Restart the controller to apply: {}", needs_restart.join(", "));
+    }
+    Ok(effective)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    use tokio::time::timeout;
+    use crate::paths;
+
+    const TEST_POLL_INTERVAL: Duration = Duration::from_millis(10);
+    // Long enough for several polls, short enough to keep a missed reload from hanging the suite
+    const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn load(path: &Path) -> Result<Settings, crate::error::ConfigError> {
+        let matches = Cli::command().get_matches_from(["wallpaper-controller", "--config", path.to_str().unwrap()]);
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        config::load(&cli, &matches, ArgsOrigin::User, paths::current())
+    }
+
+    fn watch_file(path: &Path) -> mpsc::Receiver<Settings> {
+        let watched = path.to_path_buf();
+        watch(path.to_path_buf(), TEST_POLL_INTERVAL, move || load(&watched))
+    }
+
+    async fn next(rx: &mut mpsc::Receiver<Settings>) -> Settings {
+        timeout(RECEIVE_TIMEOUT, rx.recv()).await.expect("no reload was sent").expect("the watcher stopped")
+    }
+
+    #[tokio::test]
+    async fn a_valid_rewrite_sends_the_new_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "threshold = 30\n").unwrap();
+        let mut rx = watch_file(&path);
+
+        tokio::time::sleep(TEST_POLL_INTERVAL * 3).await;
+        fs::write(&path, "threshold = 45\nmonitors = \"1\"\n").unwrap();
+        let settings = next(&mut rx).await;
+        assert_eq!(settings.threshold, 45);
+        assert_eq!(settings.monitors, "1");
+    }
+
+    #[tokio::test]
+    async fn an_invalid_rewrite_keeps_the_old_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "threshold = 30\n").unwrap();
+        let mut rx = watch_file(&path);
+
+        tokio::time::sleep(TEST_POLL_INTERVAL * 3).await;
+        fs::write(&path, "threshold = 300\n").unwrap();
+        tokio::time::sleep(TEST_POLL_INTERVAL * 10).await;
+        assert!(rx.try_recv().is_err(), "an out of range threshold was sent");
+
+        fs::write(&path, "threshold = [\n").unwrap();
+        tokio::time::sleep(TEST_POLL_INTERVAL * 10).await;
+        assert!(rx.try_recv().is_err(), "a config that doesn't parse was sent");
+
+        // The watcher is still running and picks up the next valid rewrite
+        fs::write(&path, "threshold = 35\n").unwrap();
+        assert_eq!(next(&mut rx).await.threshold, 35);
+    }
+
+    #[test]
+    fn changes_lists_exactly_the_keys_that_differ() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let base = "threshold = 30\nmonitors = \"1-2\"\n\n[profiles.work]\nthreshold = 50\n\n[profiles.gaming]\nthreshold = 90\n\n[[rules]]\nprofile = \"gaming\"\nprocess = \"eldenring.exe\"\n";
+        fs::write(&path, base).unwrap();
+        let old = load(&path).unwrap();
+        assert!(changes(&old, &old).is_empty());
+
+        let edited = base
+            .replace("threshold = 30", "threshold = 40")
+            .replace("[profiles.work]\nthreshold = 50", "[profiles.work]\nthreshold = 55")
+            .replace("eldenring.exe", "witcher3.exe")
+            + "\n[profiles.movie]\naction = \"mute\"\n";
+        fs::write(&path, edited).unwrap();
+        let new = load(&path).unwrap();
+
+        let keys: Vec<_> = changes(&old, &new).iter()
+            .map(|change| change.split(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(keys, ["threshold", "profiles.work", "profiles.movie", "rules"]);
+        assert_eq!(changes(&old, &new)[0], "threshold: 30 → 40");
+    }
+}
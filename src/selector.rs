@@ -0,0 +1,230 @@
+use std::fmt;
+use std::str::FromStr;
+
+// A monitor selector is a comma-separated list of terms: monitor numbers as shown in Display Settings, ranges
+// like `1-3`, `all`, `primary`, `largest` and `smallest`; terms prefixed with `!` are excluded (`all,!2`). It is
+// parsed once (the command line, the config file and the installer share this parser) and resolved against the
+// monitors connected at the time, so `primary` or `largest` follow the monitors as they are plugged in or out.
+
+const SYNTAX_HINT: &str = "use monitor numbers, ranges (1-3), all, primary, largest or smallest, and !TERM to exclude";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Term {
+    All,
+    Number(i64),
+    Range(i64, i64),
+    Primary,
+    Largest,
+    Smallest,
+}
+
+/// Which monitors to watch, as written by the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorSelector {
+    source: String,
+    include: Vec<Term>,
+    exclude: Vec<Term>,
+}
+
+/// What a selector needs to know about a connected monitor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorFacts {
+    /// Number shown in Display Settings
+    pub index: i64,
+    pub area: i64,
+    pub primary: bool,
+}
+
+impl MonitorSelector {
+    pub fn all() -> Self {
+        Self { source: String::from("all"), include: vec![Term::All], exclude: Vec::new() }
+    }
+
+    /// Whether every monitor is selected, whatever is connected
+    pub fn is_all(&self) -> bool {
+        self.exclude.is_empty() && self.include.contains(&Term::All)
+    }
+
+    /// The numbers of the selected monitors among `monitors`, in ascending order
+    pub fn resolve(&self, monitors: &[MonitorFacts]) -> Vec<i64> {
+        let matches = |term: &Term, monitor: &MonitorFacts| match *term {
+            Term::All => true,
+            Term::Number(n) => monitor.index == n,
+            Term::Range(start, end) => (start..=end).contains(&monitor.index),
+            Term::Primary => monitor.primary,
+            // Ties go to the lowest number, so the choice doesn't flip between equal monitors
+            Term::Largest => monitors.iter().max_by_key(|m| (m.area, -m.index)).map(|m| m.index) == Some(monitor.index),
+            Term::Smallest => monitors.iter().min_by_key(|m| (m.area, m.index)).map(|m| m.index) == Some(monitor.index),
+        };
+        // Only exclusions (`!2`) means everything else
+        let include_all = self.include.is_empty();
+        let mut selected: Vec<i64> = monitors.iter()
+            .filter(|m| include_all || self.include.iter().any(|term| matches(term, m)))
+            .filter(|m| !self.exclude.iter().any(|term| matches(term, m)))
+            .map(|m| m.index)
+            .collect();
+        selected.sort_unstable();
+        selected.dedup();
+        selected
+    }
+}
+
+impl FromStr for MonitorSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let source = s.trim();
+        if source.is_empty() {
+            return Err(format!("the monitor selector is empty; {}", SYNTAX_HINT));
+        }
+        let mut selector = Self { source: source.to_string(), include: Vec::new(), exclude: Vec::new() };
+        for entry in source.split(',').map(str::trim) {
+            if entry.is_empty() {
+                return Err(format!("'{}' contains an empty entry", source));
+            }
+            match entry.strip_prefix('!') {
+                Some(excluded) => match parse_term(excluded.trim())? {
+                    Term::All => return Err(format!("'{}' would exclude every monitor", entry)),
+                    term => selector.exclude.push(term),
+                },
+                None => selector.include.push(parse_term(entry)?),
+            }
+        }
+        Ok(selector)
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    match term.to_lowercase().as_str() {
+        "all" => return Ok(Term::All),
+        "primary" => return Ok(Term::Primary),
+        "largest" => return Ok(Term::Largest),
+        "smallest" => return Ok(Term::Smallest),
+        _ => {}
+    }
+    if let Some((start, end)) = term.split_once('-') {
+        let (start, end) = (parse_number(start.trim(), term)?, parse_number(end.trim(), term)?);
+        if start > end {
+            return Err(format!("'{}' is not a valid range: {} is greater than {}", term, start, end));
+        }
+        return Ok(Term::Range(start, end));
+    }
+    parse_number(term, term).map(Term::Number)
+}
+
+fn parse_number(number: &str, term: &str) -> Result<i64, String> {
+    match number.parse::<i64>() {
+        Ok(n) if n >= 1 => Ok(n),
+        Ok(_) => Err(format!("'{}' is not a valid monitor number (must be >= 1)", number)),
+        Err(_) => Err(format!("'{}' is not a valid monitor selector; {}", term, SYNTAX_HINT)),
+    }
+}
+
+impl fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(index: i64, area: i64, primary: bool) -> MonitorFacts {
+        MonitorFacts { index, area, primary }
+    }
+
+    /// Four monitors: 2 is primary, 1 and 3 tie for the largest, 2 and 4 tie for the smallest
+    fn monitors() -> Vec<MonitorFacts> {
+        vec![monitor(1, 8_294_400, false), monitor(2, 2_073_600, true), monitor(3, 8_294_400, false), monitor(4, 2_073_600, false)]
+    }
+
+    fn resolve(selector: &str) -> Vec<i64> {
+        selector.parse::<MonitorSelector>().unwrap().resolve(&monitors())
+    }
+
+    fn error(selector: &str) -> String {
+        selector.parse::<MonitorSelector>().unwrap_err()
+    }
+
+    #[test]
+    fn numbers_and_ranges() {
+        assert_eq!(resolve("2"), [2]);
+        assert_eq!(resolve("3,1"), [1, 3]);
+        assert_eq!(resolve("2-3"), [2, 3]);
+        assert_eq!(resolve(" 2 - 3 "), [2, 3]);
+        assert_eq!(resolve("1-2,2-3"), [1, 2, 3]);
+        assert_eq!(resolve("3-3"), [3]);
+        assert_eq!(resolve("3-9"), [3, 4]);
+        assert_eq!(resolve("7"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(resolve("all"), [1, 2, 3, 4]);
+        assert_eq!(resolve("ALL"), [1, 2, 3, 4]);
+        assert_eq!(resolve("primary"), [2]);
+        assert_eq!(resolve("primary,largest"), [1, 2]);
+        assert_eq!(MonitorSelector::all(), "all".parse().unwrap());
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_number() {
+        assert_eq!(resolve("largest"), [1]);
+        assert_eq!(resolve("smallest"), [2]);
+        // Without a tie the area decides, whatever the order
+        let monitors = [monitor(1, 100, false), monitor(2, 300, false), monitor(3, 200, false)];
+        let resolve = |selector: &str| selector.parse::<MonitorSelector>().unwrap().resolve(&monitors);
+        assert_eq!(resolve("largest"), [2]);
+        assert_eq!(resolve("smallest"), [1]);
+        assert_eq!(resolve("primary"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn exclusions() {
+        assert_eq!(resolve("all,!2"), [1, 3, 4]);
+        assert_eq!(resolve("1-4,!2-3"), [1, 4]);
+        assert_eq!(resolve("all,!primary"), [1, 3, 4]);
+        assert_eq!(resolve("largest,!largest"), Vec::<i64>::new());
+        // Exclusions win wherever they are written
+        assert_eq!(resolve("!1,1-2"), [2]);
+        assert_eq!(resolve("all, ! 3"), [1, 2, 4]);
+    }
+
+    #[test]
+    fn only_exclusions_select_everything_else() {
+        assert_eq!(resolve("!2"), [1, 3, 4]);
+        assert_eq!(resolve("!largest,!smallest"), [3, 4]);
+        assert_eq!(resolve("!1-4"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn is_all() {
+        assert!("all".parse::<MonitorSelector>().unwrap().is_all());
+        assert!("1,all".parse::<MonitorSelector>().unwrap().is_all());
+        assert!(!"all,!2".parse::<MonitorSelector>().unwrap().is_all());
+        assert!(!"1-4".parse::<MonitorSelector>().unwrap().is_all());
+    }
+
+    #[test]
+    fn the_source_is_kept_for_display() {
+        assert_eq!(" all, !2 ".parse::<MonitorSelector>().unwrap().to_string(), "all, !2");
+    }
+
+    #[test]
+    fn error_messages() {
+        let hint = "use monitor numbers, ranges (1-3), all, primary, largest or smallest, and !TERM to exclude";
+        assert_eq!(error(""), format!("the monitor selector is empty; {}", hint));
+        assert_eq!(error("  "), format!("the monitor selector is empty; {}", hint));
+        assert_eq!(error("1,,2"), "'1,,2' contains an empty entry");
+        assert_eq!(error("1,"), "'1,' contains an empty entry");
+        assert_eq!(error("!all"), "'!all' would exclude every monitor");
+        assert_eq!(error("0"), "'0' is not a valid monitor number (must be >= 1)");
+        assert_eq!(error("0-2"), "'0' is not a valid monitor number (must be >= 1)");
+        assert_eq!(error("3-1"), "'3-1' is not a valid range: 3 is greater than 1");
+        assert_eq!(error("1-"), format!("'1-' is not a valid monitor selector; {}", hint));
+        assert_eq!(error("-1"), format!("'-1' is not a valid monitor selector; {}", hint));
+        assert_eq!(error("biggest"), format!("'biggest' is not a valid monitor selector; {}", hint));
+        assert_eq!(error("!"), format!("'' is not a valid monitor selector; {}", hint));
+    }
+}
@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+
+use crate::error::WallpaperEngineError;
+
+pub mod vdf;
+
+/// Steam app id of Wallpaper Engine
+const WALLPAPER_ENGINE_APP_ID: u32 = 431960;
+const DEFAULT_STEAM_DIR: &str = "C:\\Program Files (x86)\\Steam";
+/// Where Wallpaper Engine lives in a default Steam installation; used when nothing else can be found
+pub const DEFAULT_WALLPAPER_ENGINE_DIR: &str = "C:\\Program Files (x86)\\Steam\\steamapps\\common\\wallpaper_engine";
+
+pub const WALLPAPER_32_EXE: &str = "wallpaper32.exe";
+pub const WALLPAPER_64_EXE: &str = "wallpaper64.exe";
+
+/// A Wallpaper Engine installation found in a Steam library
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WallpaperEngineInstall {
+    pub dir: PathBuf,
+    pub has_32bit: bool,
+    pub has_64bit: bool,
+}
+
+impl WallpaperEngineInstall {
+    pub fn probe(dir: PathBuf) -> Self {
+        Self {
+            has_32bit: dir.join(WALLPAPER_32_EXE).is_file(),
+            has_64bit: dir.join(WALLPAPER_64_EXE).is_file(),
+            dir,
+        }
+    }
+
+    /// Picks the executable flavor: 64-bit if requested or if it is the only one present, 32-bit otherwise
+    pub fn use_64bit(&self, prefer_64bit: bool) -> bool {
+        prefer_64bit || (self.has_64bit && !self.has_32bit)
+    }
+}
+
+/// Checks that `dir` is a Wallpaper Engine folder containing the executable that will be used
+pub fn validate_wallpaper_engine_dir(dir: &Path, use_64bit: bool) -> Result<(), WallpaperEngineError> {
+    if !dir.exists() {
+        return Err(WallpaperEngineError::MissingDir { dir: dir.to_path_buf() });
+    }
+    if !dir.is_dir() {
+        return Err(WallpaperEngineError::NotADirectory { dir: dir.to_path_buf() });
+    }
+    let exe = if use_64bit { WALLPAPER_64_EXE } else { WALLPAPER_32_EXE };
+    if !dir.join(exe).is_file() {
+        return Err(WallpaperEngineError::MissingExecutable { dir: dir.to_path_buf(), exe });
+    }
+    Ok(())
+}
+
+/// Where the Wallpaper Engine directory used at runtime came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathSource {
+    CommandLine,
+    Detected,
+    Default,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedWallpaperEngine {
+    pub dir: String,
+    pub use_64bit: bool,
+    pub source: PathSource,
+}
+
+/// Resolves the Wallpaper Engine directory and executable flavor from the command line, falling back to
+/// Steam library discovery and finally to the default Steam location
+pub fn resolve_wallpaper_engine(explicit_dir: Option<&str>, prefer_64bit: bool) -> ResolvedWallpaperEngine {
+    let (install, source) = match explicit_dir {
+        Some(dir) => (WallpaperEngineInstall::probe(PathBuf::from(dir)), PathSource::CommandLine),
+        None => match find_wallpaper_engine_installs().into_iter().next() {
+            Some(install) => {
+                info!("Found Wallpaper Engine at {}", install.dir.display());
+                (install, PathSource::Detected)
+            }
+            None => {
+                warn!("Could not find Wallpaper Engine in any Steam library; assuming {}", DEFAULT_WALLPAPER_ENGINE_DIR);
+                (WallpaperEngineInstall::probe(PathBuf::from(DEFAULT_WALLPAPER_ENGINE_DIR)), PathSource::Default)
+            }
+        },
+    };
+
+    let use_64bit = install.use_64bit(prefer_64bit);
+    if use_64bit && !prefer_64bit {
+        info!("Only {} is present; using the 64-bit executable", WALLPAPER_64_EXE);
+    }
+
+    ResolvedWallpaperEngine {
+        dir: install.dir.to_string_lossy().to_string(),
+        use_64bit,
+        source,
+    }
+}
+
+/// Finds every Wallpaper Engine installation across all Steam libraries
+pub fn find_wallpaper_engine_installs() -> Vec<WallpaperEngineInstall> {
+    let steam_dir = steam_install_dir();
+    debug!("Using Steam directory {}", steam_dir.display());
+
+    let mut libraries = vec![steam_dir.clone()];
+    match read_library_folders(&steam_dir) {
+        Ok(folders) => {
+            for folder in folders {
+                if !libraries.iter().any(|l| same_path(l, &folder)) {
+                    libraries.push(folder);
+                }
+            }
+        }
+        Err(e) => debug!("Could not read Steam library folders: {:#}", e),
+    }
+
+    let mut installs = Vec::new();
+    for library in libraries {
+        match find_app_in_library(&library, WALLPAPER_ENGINE_APP_ID) {
+            Ok(Some(dir)) => installs.push(WallpaperEngineInstall::probe(dir)),
+            Ok(None) => {}
+            Err(e) => debug!("Skipping Steam library {}: {:#}", library.display(), e),
+        }
+    }
+    installs
+}
+
+/// Library root folders listed in `steamapps/libraryfolders.vdf`
+pub fn read_library_folders(steam_dir: &Path) -> Result<Vec<PathBuf>> {
+    let file = steam_dir.join("steamapps").join("libraryfolders.vdf");
+    let content = fs::read_to_string(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let doc = vdf::parse(&content).with_context(|| format!("Failed to parse {}", file.display()))?;
+    Ok(library_folders_from_vdf(&doc))
+}
+
+pub fn library_folders_from_vdf(doc: &vdf::Value) -> Vec<PathBuf> {
+    let Some(root) = doc.get("libraryfolders").or_else(|| doc.get("LibraryFolders")) else {
+        return Vec::new();
+    };
+
+    root.entries()
+        .iter()
+        // Library entries are keyed by index; other keys (e.g. "contentstatsid") are metadata
+        .filter(|(key, _)| key.parse::<u32>().is_ok())
+        .filter_map(|(_, value)| match value {
+            // Current format: "0" { "path" "D:\\SteamLibrary" ... }
+            vdf::Value::Obj(_) => value.get("path").and_then(|p| p.as_str()),
+            // Legacy format: "1" "D:\\SteamLibrary"
+            vdf::Value::Str(path) => Some(path.as_str()),
+        })
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Install directory of `app_id` within a library, if its manifest exists there
+pub fn find_app_in_library(library: &Path, app_id: u32) -> Result<Option<PathBuf>> {
+    let steamapps = library.join("steamapps");
+    let manifest = steamapps.join(format!("appmanifest_{}.acf", app_id));
+    if !manifest.is_file() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&manifest).with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let doc = vdf::parse(&content).with_context(|| format!("Failed to parse {}", manifest.display()))?;
+    let Some(install_dir) = install_dir_from_manifest(&doc) else {
+        return Ok(None);
+    };
+
+    let dir = steamapps.join("common").join(install_dir);
+    Ok(dir.is_dir().then_some(dir))
+}
+
+pub fn install_dir_from_manifest(doc: &vdf::Value) -> Option<&str> {
+    doc.get("AppState")?.get("installdir")?.as_str()
+}
+
+fn steam_install_dir() -> PathBuf {
+    let candidates = [
+        ("HKCU\\Software\\Valve\\Steam", "SteamPath"),
+        ("HKLM\\SOFTWARE\\WOW6432Node\\Valve\\Steam", "InstallPath"),
+        ("HKLM\\SOFTWARE\\Valve\\Steam", "InstallPath"),
+    ];
+    candidates.iter()
+        .filter_map(|(key, value)| query_registry_string(key, value))
+        .map(PathBuf::from)
+        .find(|p| p.is_dir())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_STEAM_DIR))
+}
+
+// Uses `reg query` rather than the registry API, like the rest of the code shells out to tasklist/schtasks
+fn query_registry_string(key: &str, value: &str) -> Option<String> {
+    let output = Command::new("reg").args(["query", key, "/v", value]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Output looks like: "    SteamPath    REG_SZ    c:/program files (x86)/steam"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.split_once("REG_SZ").map(|(_, data)| data.trim().to_string()))
+        .filter(|data| !data.is_empty())
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    let normalize = |p: &Path| p.to_string_lossy().replace('/', "\\").trim_end_matches('\\').to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn folders(fixture_name: &str) -> Vec<PathBuf> {
+        let doc = vdf::parse(&fs::read_to_string(fixture(fixture_name)).unwrap()).unwrap();
+        library_folders_from_vdf(&doc)
+    }
+
+    #[test]
+    fn library_folders_in_the_current_format() {
+        assert_eq!(folders("libraryfolders_new.vdf"),
+            [PathBuf::from(r"C:\Program Files (x86)\Steam"), PathBuf::from(r"D:\SteamLibrary")]);
+    }
+
+    #[test]
+    fn library_folders_in_the_legacy_format() {
+        assert_eq!(folders("libraryfolders_old.vdf"),
+            [PathBuf::from(r"D:\SteamLibrary"), PathBuf::from(r"E:\Games\Steam")]);
+    }
+
+    #[test]
+    fn library_folders_without_a_libraryfolders_block() {
+        assert!(library_folders_from_vdf(&vdf::parse(r#""AppState" { "appid" "1" }"#).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn reading_library_folders_reports_bad_files() {
+        let steam = tempfile::tempdir().unwrap();
+        assert!(read_library_folders(steam.path()).is_err());
+
+        fs::create_dir(steam.path().join("steamapps")).unwrap();
+        let file = steam.path().join("steamapps").join("libraryfolders.vdf");
+        fs::copy(fixture("libraryfolders_bad.vdf"), &file).unwrap();
+        let error = format!("{:#}", read_library_folders(steam.path()).unwrap_err());
+        assert!(error.contains("Failed to parse") && error.contains("line 7"), "{}", error);
+
+        fs::copy(fixture("libraryfolders_old.vdf"), &file).unwrap();
+        assert_eq!(read_library_folders(steam.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn finds_the_app_through_its_manifest() {
+        let library = tempfile::tempdir().unwrap();
+        let steamapps = library.path().join("steamapps");
+        fs::create_dir(&steamapps).unwrap();
+        assert_eq!(find_app_in_library(library.path(), WALLPAPER_ENGINE_APP_ID).unwrap(), None);
+
+        fs::copy(fixture("appmanifest_431960.acf"), steamapps.join("appmanifest_431960.acf")).unwrap();
+        // The manifest alone isn't enough: the install folder must exist too
+        assert_eq!(find_app_in_library(library.path(), WALLPAPER_ENGINE_APP_ID).unwrap(), None);
+
+        let dir = steamapps.join("common").join("wallpaper_engine");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(find_app_in_library(library.path(), WALLPAPER_ENGINE_APP_ID).unwrap(), Some(dir.clone()));
+
+        fs::write(dir.join(WALLPAPER_64_EXE), "").unwrap();
+        let install = WallpaperEngineInstall::probe(dir);
+        assert!(install.has_64bit && !install.has_32bit);
+        assert!(install.use_64bit(false));
+    }
+
+    #[test]
+    fn paths_compare_like_windows_does() {
+        assert!(same_path(Path::new(r"C:\Program Files (x86)\Steam\"), Path::new("c:/program files (x86)/steam")));
+        assert!(!same_path(Path::new(r"D:\SteamLibrary"), Path::new(r"D:\SteamLibrary2")));
+    }
+}
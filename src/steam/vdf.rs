@@ -0,0 +1,218 @@
+// Minimal parser for Valve's KeyValues text format (`.vdf`/`.acf`), enough for Steam's
+// `libraryfolders.vdf` and `appmanifest_*.acf` files.
+
+use std::iter::Peekable;
+use std::str::Chars;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Obj(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up a key (case-insensitively, like Steam does) in an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries().iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            Value::Obj(_) => None,
+        }
+    }
+
+    /// Key/value pairs of an object, in file order; empty for strings
+    pub fn entries(&self) -> &[(String, Value)] {
+        match self {
+            Value::Obj(entries) => entries,
+            Value::Str(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("line {line}: {message}")]
+pub struct VdfError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { chars: input.chars().peekable(), line: 1 }
+    }
+
+    fn error(&self, message: impl Into<String>) -> VdfError {
+        VdfError { line: self.line, message: message.into() }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') { self.line += 1; }
+        c
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, VdfError> {
+        loop {
+            match self.chars.peek() {
+                None => return Ok(None),
+                Some(c) if c.is_whitespace() => { self.bump(); }
+                Some('/') => {
+                    self.bump();
+                    if self.chars.peek() != Some(&'/') {
+                        return Err(self.error("unexpected '/'"));
+                    }
+                    while let Some(c) = self.bump() {
+                        if c == '\n' { break; }
+                    }
+                }
+                // Platform conditionals such as [$WIN32] are ignored
+                Some('[') => {
+                    while let Some(c) = self.bump() {
+                        if c == ']' { break; }
+                    }
+                }
+                Some('{') => { self.bump(); return Ok(Some(Token::Open)); }
+                Some('}') => { self.bump(); return Ok(Some(Token::Close)); }
+                Some('"') => {
+                    self.bump();
+                    return self.quoted().map(|s| Some(Token::Str(s)));
+                }
+                Some(_) => return Ok(Some(Token::Str(self.unquoted()))),
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, VdfError> {
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(out),
+                Some('\\') => match self.bump() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('\\') => out.push('\\'),
+                    Some('"') => out.push('"'),
+                    Some(other) => { out.push('\\'); out.push(other); }
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> String {
+        let mut out = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || c == '{' || c == '}' || c == '"' { break; }
+            out.push(c);
+            self.bump();
+        }
+        out
+    }
+}
+
+/// Parses a document into an object holding its top-level key/value pairs
+pub fn parse(input: &str) -> Result<Value, VdfError> {
+    let mut lexer = Lexer::new(input.trim_start_matches('\u{feff}'));
+    let entries = parse_entries(&mut lexer, false)?;
+    Ok(Value::Obj(entries))
+}
+
+fn parse_entries(lexer: &mut Lexer, nested: bool) -> Result<Vec<(String, Value)>, VdfError> {
+    let mut entries = Vec::new();
+    loop {
+        let key = match lexer.next_token()? {
+            Some(Token::Str(key)) => key,
+            Some(Token::Close) if nested => return Ok(entries),
+            Some(Token::Close) => return Err(lexer.error("unexpected '}'")),
+            Some(Token::Open) => return Err(lexer.error("expected a key, found '{'")),
+            None if nested => return Err(lexer.error("unexpected end of file, missing '}'")),
+            None => return Ok(entries),
+        };
+        let value = match lexer.next_token()? {
+            Some(Token::Str(value)) => Value::Str(value),
+            Some(Token::Open) => Value::Obj(parse_entries(lexer, true)?),
+            Some(Token::Close) | None => return Err(lexer.error(format!("missing value for key '{}'", key))),
+        };
+        entries.push((key, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    fn str_at<'a>(doc: &'a Value, path: &[&str]) -> Option<&'a str> {
+        path.iter().try_fold(doc, |value, key| value.get(key))?.as_str()
+    }
+
+    #[test]
+    fn parses_nested_blocks_in_file_order() {
+        let doc = parse(&fixture("libraryfolders_new.vdf")).unwrap();
+        let keys: Vec<&str> = doc.get("libraryfolders").unwrap().entries().iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["contentstatsid", "0", "1"]);
+        assert_eq!(str_at(&doc, &["libraryfolders", "1", "path"]), Some(r"D:\SteamLibrary"));
+        assert_eq!(str_at(&doc, &["libraryfolders", "1", "apps", "431960"]), Some("1086543617"));
+        assert_eq!(str_at(&doc, &["libraryfolders", "0", "label"]), Some(""));
+    }
+
+    #[test]
+    fn keys_are_case_insensitive() {
+        let doc = parse(&fixture("libraryfolders_old.vdf")).unwrap();
+        assert_eq!(str_at(&doc, &["libraryfolders", "contentstatsid"]), Some("-4213765329541328562"));
+        assert_eq!(str_at(&doc, &["LIBRARYFOLDERS", "2"]), Some(r"E:\Games\Steam"));
+    }
+
+    #[test]
+    fn handles_escapes_comments_conditionals_and_unquoted_tokens() {
+        let doc = parse(&fixture("libraryfolders_escapes.vdf")).unwrap();
+        assert_eq!(str_at(&doc, &["libraryfolders", "0", "path"]), Some(r#"D:\Steam "Library""#));
+        assert_eq!(str_at(&doc, &["libraryfolders", "0", "label"]), Some("tab\there\nnewline"));
+        // Unknown escapes are kept as written
+        assert_eq!(str_at(&doc, &["libraryfolders", "0", "unknown"]), Some(r"\q"));
+        assert_eq!(str_at(&doc, &["libraryfolders", "1", "path"]), Some("E:/SteamLibrary"));
+    }
+
+    #[test]
+    fn strings_have_no_entries_and_objects_no_string() {
+        let doc = parse(r#""a" "b" "c" { }"#).unwrap();
+        assert!(doc.get("a").unwrap().entries().is_empty());
+        assert_eq!(doc.get("c").unwrap().as_str(), None);
+        assert_eq!(doc.get("missing"), None);
+    }
+
+    #[test]
+    fn reports_bad_input_with_its_line() {
+        let error = |input: &str| parse(input).unwrap_err().to_string();
+        assert_eq!(parse(&fixture("libraryfolders_bad.vdf")).unwrap_err(),
+            VdfError { line: 7, message: String::from("unexpected end of file, missing '}'") });
+        assert_eq!(error("\"a\"\n\"b"), "line 2: unterminated string");
+        assert_eq!(error("\"a\" \"b\"\n}"), "line 2: unexpected '}'");
+        assert_eq!(error("{"), "line 1: expected a key, found '{'");
+        assert_eq!(error("\"a\"\n\n"), "line 3: missing value for key 'a'");
+        assert_eq!(error("\"a\" { \"b\" }"), "line 1: missing value for key 'b'");
+        assert_eq!(error("/ comment"), "line 1: unexpected '/'");
+    }
+}
@@ -0,0 +1,166 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Version of this build
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const MARKER_PREFIX: &[u8] = b"wallpaper-controller-version=";
+
+// Kept in the binary so the installer can tell which version an installed executable is without running it
+#[used]
+static VERSION_MARKER: &str = concat!("wallpaper-controller-version=", env!("CARGO_PKG_VERSION"), "\0");
+
+/// A semantic version (`MAJOR.MINOR.PATCH[-PRE]`); build metadata is ignored
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Option<String>,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches('v');
+        let s = s.split_once('+').map_or(s, |(v, _build)| v);
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) if !pre.is_empty() => (core, Some(pre.to_string())),
+            Some(_) => return None,
+            None => (s, None),
+        };
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let (major, minor, patch) = (parts.next()??, parts.next()??, parts.next()??);
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { major, minor, patch, pre })
+    }
+
+    pub fn current() -> Self {
+        Self::parse(CURRENT_VERSION).expect("package version is valid semver")
+    }
+
+    /// Finds the version marker embedded in an executable's contents
+    pub fn embedded_in(binary: &[u8]) -> Option<Self> {
+        binary
+            .windows(MARKER_PREFIX.len())
+            .enumerate()
+            .filter(|(_, window)| *window == MARKER_PREFIX)
+            .find_map(|(i, _)| {
+                let rest = &binary[i + MARKER_PREFIX.len()..];
+                let end = rest.iter().take(64).position(|b| *b == 0)?;
+                Self::parse(std::str::from_utf8(&rest[..end]).ok()?)
+            })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                // A pre-release sorts before the release it leads up to
+                (None, None) => Ordering::Equal,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(a), Some(b)) => compare_pre_release(a, b),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Dot-separated identifiers; numeric ones compare numerically and sort before alphanumeric ones
+fn compare_pre_release(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        Version::parse(s).unwrap_or_else(|| panic!("{} should parse", s))
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(v("1.2.3"), Version { major: 1, minor: 2, patch: 3, pre: None });
+        assert_eq!(v(" v1.2.3-rc.1+build.5 "), Version { major: 1, minor: 2, patch: 3, pre: Some(String::from("rc.1")) });
+        assert_eq!(v("1.2.3-rc.1").to_string(), "1.2.3-rc.1");
+        assert_eq!(v("1.2.3+build").to_string(), "1.2.3");
+        assert_eq!(Version::current().to_string(), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn rejects_bad_strings() {
+        for bad in ["", "1", "1.2", "1.2.3.4", "1..3", "a.b.c", "1.2.x", "1.2.3-", "1.2.-3", "-1.2.3", "1.2.3 beta", "v"] {
+            assert_eq!(Version::parse(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn orders_by_precedence() {
+        let ordered = [
+            "0.9.9", "1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta", "1.0.0-beta.2",
+            "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0", "1.0.1", "1.2.0-rc.1", "1.2.0", "1.10.0", "2.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+            assert!(v(pair[1]) > v(pair[0]), "{} > {}", pair[1], pair[0]);
+        }
+        // Build metadata doesn't count
+        assert_eq!(v("1.2.0+a").cmp(&v("1.2.0+b")), Ordering::Equal);
+    }
+
+    #[test]
+    fn finds_the_marker_in_a_binary() {
+        let mut binary = b"MZ\x90\x00 some code wallpaper-controller-version= not this one".to_vec();
+        binary.extend_from_slice(b"\x00\x01wallpaper-controller-version=1.2.0-rc.1\x00trailing data");
+        assert_eq!(Version::embedded_in(&binary), Some(v("1.2.0-rc.1")));
+    }
+
+    #[test]
+    fn ignores_missing_or_broken_markers() {
+        assert_eq!(Version::embedded_in(b""), None);
+        assert_eq!(Version::embedded_in(b"no marker here"), None);
+        // Unterminated, not a version, or longer than a version could be
+        assert_eq!(Version::embedded_in(b"wallpaper-controller-version=1.2.3"), None);
+        assert_eq!(Version::embedded_in(b"wallpaper-controller-version=latest\0"), None);
+        let long = format!("wallpaper-controller-version=1.2.3-{}\0", "x".repeat(100));
+        assert_eq!(Version::embedded_in(long.as_bytes()), None);
+    }
+}
@@ -1,9 +1,65 @@
-use std::path::Path;
-use std::collections::HashMap;
-use std::time::Duration;
-use tracing::{info, error, debug};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::{info, error, debug, warn};
 use tokio::process::Command as TokioCommand;
 use tokio::time::timeout;
+use serde::{Deserialize, Serialize};
+
+use crate::arbiter::{DesiredState, PauseArbiter, PauseRequest, Target};
+use crate::error::{ControlError, WallpaperEngineError};
+use crate::steam::{validate_wallpaper_engine_dir, WALLPAPER_32_EXE, WALLPAPER_64_EXE};
+
+// Number of commands remembered in dry-run mode
+const PLANNED_COMMAND_HISTORY: usize = 1000;
+
+/// What "pausing" a target does in Wallpaper Engine
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PauseAction {
+    /// Freeze the wallpaper
+    #[default]
+    Pause,
+    /// Unload the wallpaper, which also frees its memory
+    Stop,
+    /// Keep the wallpaper running but silence it
+    Mute,
+}
+
+impl PauseAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            PauseAction::Pause => "pause",
+            PauseAction::Stop => "stop",
+            PauseAction::Mute => "mute",
+        }
+    }
+
+    fn resume_command(self) -> &'static str {
+        match self {
+            PauseAction::Pause | PauseAction::Stop => "play",
+            PauseAction::Mute => "unmute",
+        }
+    }
+}
+
+/// A Wallpaper Engine command line that was (or, in dry-run mode, would have been) spawned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+impl fmt::Display for PlannedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program.display())?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone)]
 pub struct WallpaperController {
@@ -11,27 +67,140 @@ pub struct WallpaperController {
     use_64bit: bool,
     global_state: bool, // true = playing, false = paused
     monitor_states: HashMap<i64, bool>,
+    arbiter: PauseArbiter,
+    pause_action: PauseAction,
+    // How each paused target was paused, so it is resumed the same way after the action changes
+    paused_with: HashMap<Target, PauseAction>,
+    dry_run: bool,
+    planned_commands: VecDeque<PlannedCommand>,
 }
 
 impl WallpaperController {
-    pub fn new(base_path: String, use_64bit: bool) -> Self {
-        Self {
+    /// Fails if `base_path` doesn't contain the selected Wallpaper Engine executable. In dry-run mode a
+    /// missing executable is only reported, and commands are only logged and recorded (see
+    /// `planned_commands`); the tracked state is updated as if they had succeeded.
+    pub fn new(base_path: String, use_64bit: bool, dry_run: bool) -> Result<Self, WallpaperEngineError> {
+        if let Err(e) = validate_wallpaper_engine_dir(Path::new(&base_path), use_64bit) {
+            if !dry_run {
+                return Err(e);
+            }
+            warn!("{} (ignored in dry-run mode)", e);
+        }
+
+        Ok(Self {
             executable_path: base_path,
             use_64bit,
             global_state: true, // Assume wallpaper is playing initially
             monitor_states: HashMap::new(),
+            arbiter: PauseArbiter::new(),
+            pause_action: PauseAction::default(),
+            paused_with: HashMap::new(),
+            dry_run,
+            planned_commands: VecDeque::new(),
+        })
+    }
+
+    /// Points the controller at another Wallpaper Engine folder, keeping the tracked pause state and requests.
+    /// Fails without changing anything if the folder doesn't contain the selected executable (except in
+    /// dry-run mode, as in `new`).
+    pub fn set_wallpaper_engine(&mut self, base_path: String, use_64bit: bool) -> Result<(), WallpaperEngineError> {
+        if let Err(e) = validate_wallpaper_engine_dir(Path::new(&base_path), use_64bit) {
+            if !self.dry_run {
+                return Err(e);
+            }
+            warn!("{} (ignored in dry-run mode)", e);
+        }
+        self.executable_path = base_path;
+        self.use_64bit = use_64bit;
+        Ok(())
+    }
+
+    /// Changes what pausing does from now on; targets paused already are resumed the way they were paused
+    pub fn set_pause_action(&mut self, action: PauseAction) {
+        self.pause_action = action;
+    }
+
+    /// Commands issued in dry-run mode, oldest first
+    pub fn planned_commands(&self) -> impl Iterator<Item = &PlannedCommand> {
+        self.planned_commands.iter()
+    }
+
+    /// Registers a pause/play request; call `reconcile` to act on it
+    pub fn submit(&mut self, request: PauseRequest) {
+        self.arbiter.submit(request);
+    }
+
+    /// Withdraws the request of `source` for `target`; call `reconcile` to act on it
+    pub fn withdraw(&mut self, source: &str, target: Target) -> bool {
+        self.arbiter.withdraw(source, target)
+    }
+
+    /// Requests currently in effect for `target`, strongest first
+    pub fn active_reasons(&self, target: Target) -> Vec<PauseRequest> {
+        self.arbiter.active_reasons(target, Instant::now()).into_iter().cloned().collect()
+    }
+
+    /// Brings every target in line with the arbiter's computed state, only issuing commands for targets
+    /// whose computed state differs from the current one. All targets are attempted; the first failure is returned.
+    pub async fn reconcile(&mut self) -> Result<(), ControlError> {
+        let now = Instant::now();
+        for expired in self.arbiter.prune_expired(now) {
+            debug!("Request from '{}' for {} expired", expired.source, describe_target(expired.target));
+        }
+
+        // Targets with requests, plus ones we paused earlier and may need to resume
+        let mut targets = self.arbiter.targets();
+        if !self.global_state { targets.insert(None); }
+        targets.extend(self.monitor_states.iter().filter(|(_, playing)| !**playing).map(|(i, _)| Some(*i)));
+
+        let mut targets: Vec<Target> = targets.into_iter().collect();
+        targets.sort();
+
+        let mut result = Ok(());
+        for target in targets {
+            let desired = self.arbiter.desired_state(target, now);
+            let playing = self.is_playing(target);
+            let reasons = self.arbiter.active_reasons(target, now)
+                .iter()
+                .map(|r| r.source.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let outcome = match desired {
+                DesiredState::Paused if playing => {
+                    info!("Pausing {} (reasons: {})", describe_target(target), reasons);
+                    self.pause(target).await
+                }
+                DesiredState::Playing if !playing => {
+                    info!("Resuming {} (reasons: {})", describe_target(target), if reasons.is_empty() { "none" } else { &reasons });
+                    self.play(target).await
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = outcome {
+                error!("{}", e);
+                if result.is_ok() { result = Err(e); }
+            }
         }
+        result
     }
 
-    pub async fn pause(&mut self, monitor_index: Option<i64>) -> bool {
-        self.execute_command("pause", monitor_index).await
+    pub async fn pause(&mut self, monitor_index: Option<i64>) -> Result<(), ControlError> {
+        let action = self.pause_action;
+        self.execute_command(action.name(), monitor_index, false).await?;
+        self.paused_with.insert(monitor_index, action);
+        Ok(())
     }
 
-    pub async fn play(&mut self, monitor_index: Option<i64>) -> bool {
-        self.execute_command("play", monitor_index).await
+    pub async fn play(&mut self, monitor_index: Option<i64>) -> Result<(), ControlError> {
+        let action = self.paused_with.get(&monitor_index).copied().unwrap_or(self.pause_action);
+        self.execute_command(action.resume_command(), monitor_index, true).await?;
+        self.paused_with.remove(&monitor_index);
+        Ok(())
     }
 
-    async fn execute_command(&mut self, command: &str, monitor_index: Option<i64>) -> bool {
+    /// Runs `-control <command>`; `playing` is the state the target is in afterwards
+    async fn execute_command(&mut self, command: &str, monitor_index: Option<i64>, playing: bool) -> Result<(), ControlError> {
         let mut args = vec![String::from("-control"), String::from(command)];
         
         // Add monitor index if specified
@@ -43,56 +212,65 @@ impl WallpaperController {
 
         // Determine which executable to use based on the 64-bit flag
         let executable_name = if self.use_64bit {
-            "wallpaper64.exe"
+            WALLPAPER_64_EXE
         } else {
-            "wallpaper32.exe"
+            WALLPAPER_32_EXE
         };
         
         let full_path = Path::new(&self.executable_path).join(executable_name);
+
+        if self.dry_run {
+            let planned = PlannedCommand { program: full_path, args };
+            info!("Dry run, would execute: {}", planned);
+            if self.planned_commands.len() == PLANNED_COMMAND_HISTORY {
+                self.planned_commands.pop_front();
+            }
+            self.planned_commands.push_back(planned);
+            self.record_state(playing, monitor_index);
+            return Ok(());
+        }
+
         let full_path_str = full_path.to_string_lossy().to_string();
         
         info!("Executing: {} {}", full_path_str, args.join(" "));
 
         // Use tokio::process for async execution with timeout
-        let mut child = match TokioCommand::new(&full_path)
+        let mut child = TokioCommand::new(&full_path)
             .args(&args)
-            .spawn() {
-            Ok(child) => child,
-            Err(e) => {
-                error!("Failed to spawn command: {}", e);
-                return false;
-            }
-        };
+            .spawn()
+            .map_err(|source| ControlError::Spawn { path: full_path.clone(), source })?;
 
         let wait_timeout = Duration::from_secs(5);
         let wait_result = timeout(wait_timeout, child.wait()).await;
 
-        let success = match wait_result {
-            Ok(Ok(status)) => status.success(),
-            Ok(Err(e)) => {
-                error!("Failed to wait for child process: {}", e);
-                false
-            }
+        let result = match wait_result {
+            Ok(Ok(status)) if status.success() => Ok(()),
+            Ok(Ok(status)) => Err(ControlError::NonZeroExit { path: full_path, status }),
+            Ok(Err(source)) => Err(ControlError::Wait { path: full_path, source }),
             Err(_) => {  // Timeout occurred
-                error!("Child process timed out after {:?}; attempting to kill", wait_timeout);
+                warn!("Child process timed out after {:?}; attempting to kill", wait_timeout);
                 if let Err(kill_err) = child.kill().await {
                     error!("Failed to kill timed-out child process: {}", kill_err);
                 }
-                false
+                Err(ControlError::Timeout { path: full_path, timeout: wait_timeout })
             }
         };
 
-        // Update state tracking
+        self.record_state(playing, monitor_index);
+
+        result
+    }
+
+    // Update state tracking
+    fn record_state(&mut self, playing: bool, monitor_index: Option<i64>) {
         match monitor_index {
             Some(index) => {
-                self.monitor_states.insert(index, command == "play");
+                self.monitor_states.insert(index, playing);
             },
             None => {
-                self.global_state = command == "play";
+                self.global_state = playing;
             }
         }
-
-        success
     }
 
     pub fn is_playing(&self, monitor_index: Option<i64>) -> bool {
@@ -101,4 +279,121 @@ impl WallpaperController {
             None => self.global_state,
         }
     }
-}
\ No newline at end of file
+}
+
+fn describe_target(target: Target) -> String {
+    match target {
+        Some(index) => format!("monitor number {}", index),
+        None => String::from("all wallpapers"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbiter::{PRIORITY_VISIBILITY, SOURCE_VISIBILITY};
+
+    const DIR: &str = "/opt/wallpaper_engine";
+
+    fn controller(use_64bit: bool) -> WallpaperController {
+        // The folder doesn't exist, which dry-run mode only reports
+        WallpaperController::new(String::from(DIR), use_64bit, true).unwrap()
+    }
+
+    fn planned(controller: &WallpaperController) -> Vec<(PathBuf, String)> {
+        controller.planned_commands().map(|c| (c.program.clone(), c.args.join(" "))).collect()
+    }
+
+    fn exe(name: &str) -> PathBuf {
+        Path::new(DIR).join(name)
+    }
+
+    #[test]
+    fn a_missing_folder_is_an_error_outside_dry_run() {
+        assert!(WallpaperController::new(String::from(DIR), false, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn commands_run_the_executable_in_the_wallpaper_engine_folder() {
+        let mut controller = controller(false);
+        controller.pause(None).await.unwrap();
+        controller.play(Some(2)).await.unwrap();
+        controller.set_wallpaper_engine(String::from(DIR), true).unwrap();
+        controller.play(None).await.unwrap();
+        assert_eq!(planned(&controller), [
+            (exe(WALLPAPER_32_EXE), String::from("-control pause")),
+            (exe(WALLPAPER_32_EXE), String::from("-control play -monitor 2")),
+            (exe(WALLPAPER_64_EXE), String::from("-control play")),
+        ]);
+        assert_eq!(controller.planned_commands().next().unwrap().to_string(),
+            format!("{} -control pause", exe(WALLPAPER_32_EXE).display()));
+    }
+
+    #[tokio::test]
+    async fn reconcile_only_issues_commands_on_changes() {
+        let mut controller = controller(true);
+        controller.submit(PauseRequest::pause(SOURCE_VISIBILITY, Some(1), PRIORITY_VISIBILITY));
+        controller.reconcile().await.unwrap();
+        controller.reconcile().await.unwrap();
+        assert!(!controller.is_playing(Some(1)));
+        assert!(controller.is_playing(None));
+
+        controller.withdraw(SOURCE_VISIBILITY, Some(1));
+        controller.reconcile().await.unwrap();
+        assert!(controller.is_playing(Some(1)));
+        let args: Vec<String> = planned(&controller).into_iter().map(|(_, args)| args).collect();
+        assert_eq!(args, ["-control pause -monitor 1", "-control play -monitor 1"]);
+    }
+
+    #[tokio::test]
+    async fn targets_resume_the_way_they_were_paused() {
+        let mut controller = controller(true);
+        controller.set_pause_action(PauseAction::Mute);
+        controller.pause(None).await.unwrap();
+        controller.set_pause_action(PauseAction::Stop);
+        controller.pause(Some(1)).await.unwrap();
+        controller.play(None).await.unwrap();
+        controller.play(Some(1)).await.unwrap();
+        let args: Vec<String> = planned(&controller).into_iter().map(|(_, args)| args).collect();
+        assert_eq!(args, [
+            "-control mute",
+            "-control stop -monitor 1",
+            "-control unmute",
+            "-control play -monitor 1",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn failed_commands_leave_the_pause_state_alone() {
+        let mut controller = controller(true);
+        controller.set_pause_action(PauseAction::Mute);
+        // Outside dry-run mode the missing executable fails to spawn
+        controller.dry_run = false;
+        assert!(matches!(controller.pause(Some(1)).await, Err(ControlError::Spawn { .. })));
+        assert!(controller.is_playing(Some(1)));
+        assert!(controller.paused_with.is_empty());
+
+        controller.dry_run = true;
+        controller.pause(Some(1)).await.unwrap();
+        controller.set_pause_action(PauseAction::Stop);
+        controller.dry_run = false;
+        assert!(controller.play(Some(1)).await.is_err());
+        assert_eq!(controller.paused_with.get(&Some(1)), Some(&PauseAction::Mute));
+
+        controller.dry_run = true;
+        controller.play(Some(1)).await.unwrap();
+        assert!(controller.paused_with.is_empty());
+        assert_eq!(controller.planned_commands().last().unwrap().args, ["-control", "unmute", "-monitor", "1"]);
+    }
+
+    #[tokio::test]
+    async fn the_planned_command_history_is_bounded() {
+        let mut controller = controller(true);
+        for _ in 0..PLANNED_COMMAND_HISTORY / 2 + 1 {
+            controller.pause(None).await.unwrap();
+            controller.play(None).await.unwrap();
+        }
+        assert_eq!(controller.planned_commands().count(), PLANNED_COMMAND_HISTORY);
+        assert_eq!(controller.planned_commands().next().unwrap().args, ["-control", "pause"]);
+    }
+}